@@ -0,0 +1,73 @@
+//! Counts the allocations of the per-token HTML callbacks.
+//!
+//! Once every capture name has been seen, opening a capture, writing a token and
+//! closing it must not allocate: classes are looked up, never formatted.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use highlighting::{Html, Renderer, NAMES};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// One line with a token for every capture name.
+fn line(html: &mut Html) {
+    for name in NAMES {
+        html.start_capture(name);
+        html.token(b"token", Some(name));
+        html.end_capture();
+    }
+}
+
+fn main() {
+    const LINES: usize = 10_000;
+
+    let mut html = Html::new();
+    html.start("rust");
+    line(&mut html);
+    html.newline();
+
+    let mut allocations = 0;
+    let start = Instant::now();
+    for _ in 0..LINES {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        line(&mut html);
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+        // Flushing the line grows the output, which may allocate.
+        html.newline();
+    }
+    let elapsed = start.elapsed();
+    black_box(html.finish());
+
+    let tokens = LINES * NAMES.len();
+    println!(
+        "{tokens} tokens: {:.1} ns/token, {allocations} allocations",
+        elapsed.as_nanos() as f64 / tokens as f64
+    );
+    assert_eq!(allocations, 0, "the per-token callbacks allocated");
+}
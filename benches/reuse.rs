@@ -0,0 +1,54 @@
+//! Compares highlighting with a new highlighter per call against a reused one, on a
+//! file of about 200 lines.
+//!
+//! `render` goes through the registry's pool of highlighters, so it should track the
+//! reused case.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use highlighting::{Languages, NAMES};
+use tree_sitter_highlight::{HighlightConfiguration, Highlighter};
+
+fn config() -> HighlightConfiguration {
+    let mut config = HighlightConfiguration::new(
+        tree_sitter_rust::language(),
+        include_str!("../queries/rust/highlights.scm"),
+        include_str!("../queries/rust/injections.scm"),
+        include_str!("../queries/rust/locals.scm"),
+    )
+    .unwrap();
+    config.configure(NAMES);
+    config
+}
+
+/// Drains the events of a highlight.
+fn highlight(highlighter: &mut Highlighter, config: &HighlightConfiguration, source: &[u8]) {
+    for event in highlighter
+        .highlight(config, source, None, |_| None)
+        .unwrap()
+    {
+        black_box(event.unwrap());
+    }
+}
+
+fn reuse(c: &mut Criterion) {
+    let source = include_str!("../fixtures/sample.rs").repeat(8);
+    let source = source.as_bytes();
+    let config = config();
+
+    c.bench_function("highlight/new", |b| {
+        b.iter(|| highlight(&mut Highlighter::new(), &config, source))
+    });
+    let mut highlighter = Highlighter::new();
+    c.bench_function("highlight/reused", |b| {
+        b.iter(|| highlight(&mut highlighter, &config, source))
+    });
+
+    let mut languages = Languages::new();
+    languages.insert("rust", self::config());
+    c.bench_function("render", |b| {
+        b.iter(|| black_box(languages.render("rust", source).unwrap()))
+    });
+}
+
+criterion_group!(benches, reuse);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+""
+use std::net::SocketAddr;
+use viz::{Request, Result, Router, Server, ServiceMaker};
+
+async fn index(_: Request) -> Result<&'static str> {
+    Ok("Hello Viz")
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
+    println!("listening on {}", addr);
+
+    let app = Router::new().get("/", index);
+
+    if let Err(err) = Server::bind(&addr)
+        .serve(ServiceMaker::from(app))
+        .await
+    {
+        println!("{}", err);
+    }
+
+    Ok(())
+}
+        ""
\ No newline at end of file
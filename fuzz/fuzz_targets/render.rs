@@ -0,0 +1,61 @@
+//! Renders arbitrary bytes with every renderer and options derived from the input.
+//!
+//! ```sh
+//! cargo +nightly fuzz run render
+//! ```
+
+#![no_main]
+
+use highlighting::{
+    ColorDepth, ColumnUnit, HighlightConfiguration, Languages, RenderOptions, SvgOptions, Theme,
+};
+use libfuzzer_sys::fuzz_target;
+
+thread_local! {
+    static LANGUAGES: Languages<'static> = {
+        let mut languages = Languages::new();
+        languages.insert(
+            "rust",
+            HighlightConfiguration::new(
+                tree_sitter_rust::language(),
+                include_str!("../../queries/rust/highlights.scm"),
+                include_str!("../../queries/rust/injections.scm"),
+                include_str!("../../queries/rust/locals.scm"),
+            )
+            .unwrap(),
+        );
+        languages
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    let [flags, wrap, rest @ ..] = data else {
+        return;
+    };
+    // An optional search term, up to the first NUL.
+    let (term, source) = match rest.iter().position(|b| *b == 0) {
+        Some(i) => (String::from_utf8_lossy(&rest[..i]), &rest[i + 1..]),
+        None => ("".into(), rest),
+    };
+
+    let mut options = RenderOptions::new()
+        .search_terms(&[&term])
+        .collapse(usize::from(*wrap % 8)..usize::from(*wrap))
+        .collapse_details(flags & 1 != 0)
+        .mark_conflicts(flags & 2 != 0);
+    if flags & 4 != 0 {
+        options = options.wrap_column(usize::from(*wrap));
+    }
+
+    LANGUAGES.with(|languages| {
+        let _ = languages.render_with("rust", source, &options);
+        let _ = languages.tokens("rust", source);
+        let _ = languages.positioned_tokens("rust", source, ColumnUnit::Utf16, flags & 8 != 0);
+
+        let theme = Theme::default();
+        let _ = languages.render_ansi_with("rust", source, &theme, ColorDepth::Ansi16);
+        let _ = languages.render_svg("rust", source, &theme, &SvgOptions::new());
+        let _ = languages.render_latex("rust", source, &theme);
+        let _ = languages.render_rtf("rust", source, &theme);
+    });
+});
@@ -1,12 +1,13 @@
 (fenced_code_block
   (info_string
-    (language) @language)
-  (#not-match? @language "elm")
-  (code_fence_content) @content (#exclude_children! @content))
+    (language) @injection.language)
+  (code_fence_content) @injection.content)
 
-((html_block) @html)
+((html_block) @injection.content
+ (#set! injection.language "html"))
 
-((minus_metadata) @yaml (#offset! @yaml 1 0 -1 0))
-((plus_metadata) @toml (#offset! @toml 1 0 -1 0))
+((minus_metadata) @injection.content
+ (#set! injection.language "yaml"))
 
-((inline) @markdown_inline (#exclude_children! @markdown_inline))
+((inline) @injection.content
+ (#set! injection.language "markdown_inline"))
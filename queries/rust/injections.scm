@@ -1,22 +1,33 @@
-(macro_invocation
-  (token_tree) @rust)
+((macro_invocation
+  (token_tree) @injection.content)
+ (#set! injection.language "rust")
+ (#set! injection.include-children))
 
-(macro_definition
+((macro_definition
   (macro_rule
-    left: (token_tree_pattern) @rust
-    right: (token_tree) @rust))
+    left: (token_tree_pattern) @injection.content))
+ (#set! injection.language "rust")
+ (#set! injection.include-children))
 
-[
+((macro_definition
+  (macro_rule
+    right: (token_tree) @injection.content))
+ (#set! injection.language "rust")
+ (#set! injection.include-children))
+
+([
   (line_comment)
   (block_comment)
-] @comment
+] @injection.content
+ (#set! injection.language "comment"))
 
 (
   (macro_invocation
     macro: ((identifier) @_html_def)
-    (token_tree) @html)
+    (token_tree) @injection.content)
 
     (#eq? @_html_def "html")
+    (#set! injection.language "html")
 )
 
 (call_expression
@@ -24,11 +35,13 @@
     path: (identifier) @_regex (#eq? @_regex "Regex")
     name: (identifier) @_new (#eq? @_new "new"))
   arguments: (arguments
-    (raw_string_literal) @regex))
+    (raw_string_literal) @injection.content)
+  (#set! injection.language "regex"))
 
 (call_expression
   function: (scoped_identifier
     path: (scoped_identifier (identifier) @_regex (#eq? @_regex "Regex").)
     name: (identifier) @_new (#eq? @_new "new"))
   arguments: (arguments
-    (raw_string_literal) @regex))
+    (raw_string_literal) @injection.content)
+  (#set! injection.language "regex"))
@@ -0,0 +1,478 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Color, Error, FontStyle, Languages, Style, Theme};
+
+/// Terminal color depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// 24-bit colors.
+    TrueColor,
+    /// The 256-color palette.
+    Ansi256,
+    /// The basic 16 colors.
+    Ansi16,
+    /// No styling at all.
+    None,
+}
+
+impl ColorDepth {
+    /// Detects the color depth from the `NO_COLOR`, `COLORTERM` and `TERM` variables.
+    pub fn detect() -> Self {
+        Self::from_env(|key| std::env::var(key).ok())
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        if var("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return Self::None;
+        }
+        if var("COLORTERM").is_some_and(|v| v == "truecolor" || v == "24bit") {
+            return Self::TrueColor;
+        }
+        match var("TERM") {
+            Some(term) if term == "dumb" => Self::None,
+            Some(term) if term.contains("256color") => Self::Ansi256,
+            _ => Self::Ansi16,
+        }
+    }
+}
+
+/// A terminal theme of 256-color palette indices, emitted as is, so colors follow the
+/// terminal's own palette and work without 24-bit support.
+///
+/// Capture names fall back to their dotted prefixes like [`Theme::resolve`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnsiTheme {
+    styles: BTreeMap<String, AnsiStyle>,
+}
+
+/// The style of a capture in an [`AnsiTheme`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AnsiStyle {
+    /// The palette index of the foreground.
+    pub fg: Option<u8>,
+    /// The palette index of the background.
+    pub bg: Option<u8>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl AnsiStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fg(mut self, index: u8) -> Self {
+        self.fg = Some(index);
+        self
+    }
+
+    pub fn bg(mut self, index: u8) -> Self {
+        self.bg = Some(index);
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    pub fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
+    pub fn strikethrough(mut self) -> Self {
+        self.strikethrough = true;
+        self
+    }
+
+    /// The SGR parameters, empty when unstyled.
+    fn sgr(&self) -> String {
+        let font = Style {
+            bold: self.bold,
+            italic: self.italic,
+            underline: self.underline,
+            strikethrough: self.strikethrough,
+            ..Style::default()
+        };
+        let mut params = font_params(&font);
+        for (index, offset) in [(self.fg, 0), (self.bg, 10)] {
+            if let Some(index) = index {
+                params.push(format!("{};5;{index}", 38 + offset));
+            }
+        }
+        params.join(";")
+    }
+}
+
+impl AnsiTheme {
+    pub fn new(styles: &[(&str, AnsiStyle)]) -> Self {
+        Self {
+            styles: styles
+                .iter()
+                .map(|(name, style)| (name.to_string(), *style))
+                .collect(),
+        }
+    }
+
+    /// The style of a capture name or of its longest dotted prefix with one, if any.
+    pub fn get(&self, name: &str) -> Option<AnsiStyle> {
+        let mut name = name;
+        loop {
+            if let Some(style) = self.styles.get(name) {
+                return Some(*style);
+            }
+            name = &name[..name.rfind('.')?];
+        }
+    }
+
+    /// Resolves the style of a capture name, see [`AnsiTheme::get`], or the default style.
+    pub fn resolve(&self, name: &str) -> AnsiStyle {
+        self.get(name).unwrap_or_default()
+    }
+}
+
+/// The downsampled colors of a theme, computed once per theme.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Palette {
+    colors: HashMap<Color, (u8, u8)>,
+}
+
+impl Palette {
+    pub(crate) fn new(colors: impl Iterator<Item = Color>) -> Self {
+        Self {
+            colors: colors
+                .map(|c| (c, (c.to_ansi256(), c.to_ansi16())))
+                .collect(),
+        }
+    }
+
+    fn get(&self, color: Color) -> (u8, u8) {
+        self.colors
+            .get(&color)
+            .copied()
+            .unwrap_or_else(|| (color.to_ansi256(), color.to_ansi16()))
+    }
+}
+
+/// The xterm values of the basic 16 colors.
+const ANSI16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The channel levels of the 6×6×6 color cube.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn nearest(color: Color, candidates: impl Iterator<Item = (u8, (u8, u8, u8))>) -> u8 {
+    let rgb = (color.r, color.g, color.b);
+    candidates
+        .min_by_key(|(_, c)| distance(rgb, *c))
+        .map(|(i, _)| i)
+        .unwrap_or_default()
+}
+
+impl Color {
+    /// The nearest entry of the 256-color cube or grayscale ramp (16–255).
+    pub fn to_ansi256(self) -> u8 {
+        let cube = (0..216u8).map(|i| {
+            let (r, g, b) = (i / 36, i / 6 % 6, i % 6);
+            (
+                16 + i,
+                (CUBE[r as usize], CUBE[g as usize], CUBE[b as usize]),
+            )
+        });
+        let gray = (0..24u8).map(|i| (232 + i, (8 + 10 * i, 8 + 10 * i, 8 + 10 * i)));
+        nearest(self, cube.chain(gray))
+    }
+
+    /// The nearest of the basic 16 colors (0–15).
+    pub fn to_ansi16(self) -> u8 {
+        nearest(self, (0..16u8).zip(ANSI16))
+    }
+}
+
+impl<'a> Languages<'a> {
+    /// Renders to ANSI escape sequences with 24-bit colors.
+    ///
+    /// Styles are reset after every token and before every line break, so the output is
+    /// safe to splice into other terminal output. Control characters in the source,
+    /// including escape sequences, are stripped.
+    pub fn render_ansi(&self, lang: &str, source: &[u8], theme: &Theme) -> Result<String, Error> {
+        self.render_ansi_with(lang, source, theme, ColorDepth::TrueColor)
+    }
+
+    /// Renders to ANSI escape sequences with the color depth detected from the environment,
+    /// emitting unstyled text when `NO_COLOR` is set.
+    pub fn render_ansi_auto(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &Theme,
+    ) -> Result<String, Error> {
+        self.render_ansi_with(lang, source, theme, ColorDepth::detect())
+    }
+
+    /// Renders to ANSI escape sequences, downsampling colors to the given depth.
+    pub fn render_ansi_with(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &Theme,
+        depth: ColorDepth,
+    ) -> Result<String, Error> {
+        let palette = theme.palette();
+        let mut out = String::with_capacity(source.len());
+        let mut cache = HashMap::new();
+        self.for_each_token(lang, source, |range, name| {
+            let sgr = match name {
+                Some(name) if depth != ColorDepth::None => cache
+                    .entry(name)
+                    .or_insert_with(|| sgr(&theme.resolve(name), depth, palette))
+                    .as_str(),
+                _ => "",
+            };
+            push_styled(&mut out, &String::from_utf8_lossy(&source[range]), sgr);
+        })?;
+        Ok(out)
+    }
+
+    /// Renders to ANSI escape sequences with the palette indices of an [`AnsiTheme`],
+    /// for 256-color terminals; see [`Languages::render_ansi`].
+    pub fn render_ansi_palette(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &AnsiTheme,
+    ) -> Result<String, Error> {
+        let mut out = String::with_capacity(source.len());
+        let mut cache = HashMap::new();
+        self.for_each_token(lang, source, |range, name| {
+            let sgr = match name {
+                Some(name) => cache
+                    .entry(name)
+                    .or_insert_with(|| theme.resolve(name).sgr())
+                    .as_str(),
+                None => "",
+            };
+            push_styled(&mut out, &String::from_utf8_lossy(&source[range]), sgr);
+        })?;
+        Ok(out)
+    }
+}
+
+/// The SGR parameters of the font styles of a style.
+fn font_params(style: &Style) -> Vec<String> {
+    style
+        .font_styles()
+        .map(|font_style| {
+            match font_style {
+                FontStyle::Bold => "1",
+                FontStyle::Italic => "3",
+                FontStyle::Underline => "4",
+                FontStyle::Strikethrough => "9",
+            }
+            .to_string()
+        })
+        .collect()
+}
+
+/// The SGR parameters of a style, empty when unstyled.
+fn sgr(style: &Style, depth: ColorDepth, palette: &Palette) -> String {
+    let mut params = font_params(style);
+    // The foreground and background parameters differ by 10.
+    for (c, offset) in [(style.fg, 0), (style.bg, 10)] {
+        let Some(c) = c else { continue };
+        match depth {
+            ColorDepth::TrueColor => {
+                params.push(format!("{};2;{};{};{}", 38 + offset, c.r, c.g, c.b))
+            }
+            ColorDepth::Ansi256 => params.push(format!("{};5;{}", 38 + offset, palette.get(c).0)),
+            ColorDepth::Ansi16 => match palette.get(c).1 {
+                i @ 0..=7 => params.push((30 + offset + i).to_string()),
+                i => params.push((82 + offset + i).to_string()),
+            },
+            ColorDepth::None => {}
+        }
+    }
+    params.join(";")
+}
+
+fn visible(c: &char) -> bool {
+    *c == '\t' || !c.is_control()
+}
+
+/// Pushes text wrapped in the SGR parameters, resetting before every line break.
+fn push_styled(out: &mut String, text: &str, sgr: &str) {
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if !line.chars().any(|c| visible(&c)) {
+            continue;
+        }
+        if !sgr.is_empty() {
+            out.push_str("\x1b[");
+            out.push_str(sgr);
+            out.push('m');
+        }
+        out.extend(line.chars().filter(visible));
+        if !sgr.is_empty() {
+            out.push_str("\x1b[0m");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, Color};
+
+    #[test]
+    fn styled() {
+        let mut out = String::new();
+        push_styled(&mut out, "a\x1b[31mb\r\n\nc", "1");
+        assert_eq!(out, "\x1b[1ma[31mb\x1b[0m\n\n\x1b[1mc\x1b[0m");
+    }
+
+    #[test]
+    fn render() {
+        let languages = rust();
+        let theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            ("string", Style::new().fg(Color::rgb(152, 195, 121))),
+        ]);
+
+        assert_eq!(
+            languages
+                .render_ansi("rust", b"let a = 1;\n", &theme)
+                .unwrap(),
+            "\x1b[1;38;2;198;120;221mlet\x1b[0m a = 1;\n"
+        );
+
+        let out = languages
+            .render_ansi("rust", b"let s = \"\x1b[31mred\";", &theme)
+            .unwrap();
+        assert!(!out.contains("\x1b[31m"));
+        assert!(out.contains("\x1b[38;2;152;195;121m\"[31mred\"\x1b[0m"));
+    }
+
+    #[test]
+    fn downsample() {
+        assert_eq!(Color::rgb(0, 0, 0).to_ansi256(), 16);
+        assert_eq!(Color::rgb(255, 0, 0).to_ansi256(), 196);
+        assert_eq!(Color::rgb(255, 255, 255).to_ansi256(), 231);
+        assert_eq!(Color::rgb(128, 128, 128).to_ansi256(), 244);
+        assert_eq!(Color::rgb(95, 135, 175).to_ansi256(), 67);
+        assert_eq!(Color::rgb(198, 120, 221).to_ansi256(), 176);
+
+        assert_eq!(Color::rgb(0, 0, 0).to_ansi16(), 0);
+        assert_eq!(Color::rgb(250, 10, 10).to_ansi16(), 9);
+        assert_eq!(Color::rgb(200, 200, 200).to_ansi16(), 7);
+    }
+
+    #[test]
+    fn backgrounds() {
+        let style = Style::new()
+            .fg(Color::rgb(255, 0, 0))
+            .bg(Color::rgb(0, 0, 238));
+        let palette = Palette::default();
+        assert_eq!(
+            sgr(&style, ColorDepth::TrueColor, &palette),
+            "38;2;255;0;0;48;2;0;0;238"
+        );
+        assert_eq!(
+            sgr(&style, ColorDepth::Ansi256, &palette),
+            "38;5;196;48;5;21"
+        );
+        assert_eq!(sgr(&style, ColorDepth::Ansi16, &palette), "91;44");
+        let style = Style::new().bg(Color::rgb(255, 255, 255));
+        assert_eq!(sgr(&style, ColorDepth::Ansi16, &palette), "107");
+    }
+
+    #[test]
+    fn palette() {
+        let theme = AnsiTheme::new(&[
+            ("keyword", AnsiStyle::new().fg(5).bold()),
+            ("string", AnsiStyle::new().fg(2).bg(236)),
+        ]);
+        assert_eq!(theme.resolve("keyword.function").sgr(), "1;38;5;5");
+        assert_eq!(theme.resolve("string").sgr(), "38;5;2;48;5;236");
+        assert_eq!(theme.resolve("comment").sgr(), "");
+
+        let out = rust()
+            .render_ansi_palette("rust", b"let a = 1;\n", &theme)
+            .unwrap();
+        assert_eq!(out, "\x1b[1;38;5;5mlet\x1b[0m a = 1;\n");
+    }
+
+    #[test]
+    fn detect() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            ColorDepth::from_env(move |key| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            })
+        };
+        assert_eq!(
+            env(&[("NO_COLOR", "1"), ("COLORTERM", "truecolor")]),
+            ColorDepth::None
+        );
+        assert_eq!(
+            env(&[("NO_COLOR", ""), ("COLORTERM", "truecolor")]),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(env(&[("TERM", "xterm-256color")]), ColorDepth::Ansi256);
+        assert_eq!(env(&[("TERM", "xterm")]), ColorDepth::Ansi16);
+        assert_eq!(env(&[("TERM", "dumb")]), ColorDepth::None);
+    }
+
+    #[test]
+    fn depths() {
+        let languages = rust();
+        let theme = Theme::new(&[("keyword", Style::new().fg(Color::rgb(255, 0, 0)).bold())]);
+
+        let out = languages
+            .render_ansi_with("rust", b"let a = 1;\n", &theme, ColorDepth::Ansi256)
+            .unwrap();
+        assert_eq!(out, "\x1b[1;38;5;196mlet\x1b[0m a = 1;\n");
+
+        let out = languages
+            .render_ansi_with("rust", b"let a = 1;\n", &theme, ColorDepth::Ansi16)
+            .unwrap();
+        assert_eq!(out, "\x1b[1;91mlet\x1b[0m a = 1;\n");
+
+        let source = "fn main() {\n    let s = \"日本語\";\n}\n";
+        let out = languages
+            .render_ansi_with("rust", source.as_bytes(), &theme, ColorDepth::None)
+            .unwrap();
+        assert_eq!(out, source);
+    }
+}
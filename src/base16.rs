@@ -0,0 +1,186 @@
+use crate::{Color, Style, Theme, ThemeError};
+
+/// The base16 slot coloring each capture name, after the base16 styling guidelines.
+///
+/// `0x03` colors comments, `0x08` variables and tags, `0x09` numbers and constants,
+/// `0x0A` types, `0x0B` strings, `0x0C` escapes and regexes, `0x0D` functions,
+/// `0x0E` keywords and `0x0F` embedded and deprecated code. Pass another table to
+/// [`Theme::from_base16_yaml_with`] to override it.
+pub const BASE16_SLOTS: &[(&str, u8)] = &[
+    ("annotation", 0x0F),
+    ("attribute", 0x0A),
+    ("boolean", 0x09),
+    ("character", 0x08),
+    ("character.special", 0x0C),
+    ("comment", 0x03),
+    ("conditional", 0x0E),
+    ("constant", 0x09),
+    ("constructor", 0x0A),
+    ("debug", 0x08),
+    ("define", 0x0E),
+    ("diff.delta", 0x0E),
+    ("diff.minus", 0x08),
+    ("diff.plus", 0x0B),
+    ("error", 0x08),
+    ("exception", 0x08),
+    ("field", 0x08),
+    ("float", 0x09),
+    ("function", 0x0D),
+    ("function.builtin", 0x0C),
+    ("include", 0x0D),
+    ("keyword", 0x0E),
+    ("label", 0x0A),
+    ("method", 0x0D),
+    ("namespace", 0x0A),
+    ("number", 0x09),
+    ("operator", 0x05),
+    ("parameter", 0x08),
+    ("preproc", 0x0A),
+    ("property", 0x08),
+    ("punctuation", 0x05),
+    ("punctuation.special", 0x0F),
+    ("repeat", 0x0E),
+    ("storageclass", 0x0E),
+    ("string", 0x0B),
+    ("string.escape", 0x0C),
+    ("string.regex", 0x0C),
+    ("string.special", 0x0C),
+    ("symbol", 0x0B),
+    ("tag", 0x08),
+    ("tag.attribute", 0x09),
+    ("tag.delimiter", 0x05),
+    ("text.danger", 0x08),
+    ("text.diff.add", 0x0B),
+    ("text.diff.delete", 0x08),
+    ("text.emphasis", 0x0E),
+    ("text.literal", 0x0B),
+    ("text.math", 0x0C),
+    ("text.note", 0x0D),
+    ("text.reference", 0x08),
+    ("text.strong", 0x0A),
+    ("text.title", 0x0D),
+    ("text.uri", 0x09),
+    ("text.warning", 0x0A),
+    ("todo", 0x0A),
+    ("type", 0x0A),
+    ("type.qualifier", 0x0E),
+    ("variable", 0x08),
+    ("variable.builtin", 0x09),
+];
+
+/// The sixteen colors of a scheme, from its `baseXX` keys.
+///
+/// Both the flat layout and the newer one nesting the keys under `palette:` are read, as
+/// the keys are looked up line by line whatever their indentation.
+fn colors(yaml: &str) -> Result<[Color; 16], ThemeError> {
+    let mut colors = [None; 16];
+    for line in yaml.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(slot) = key
+            .trim()
+            .strip_prefix("base0")
+            .filter(|slot| slot.len() == 1)
+            .and_then(|slot| u8::from_str_radix(slot, 16).ok())
+        else {
+            continue;
+        };
+        let value = value.trim();
+        let value = match value.strip_prefix(['"', '\'']) {
+            Some(quoted) => quoted.split(['"', '\'']).next().unwrap_or_default(),
+            None => value.split(" #").next().unwrap_or_default().trim(),
+        };
+        colors[slot as usize] = Some(value.parse()?);
+    }
+    let mut result = [Color::rgb(0, 0, 0); 16];
+    for (slot, color) in colors.into_iter().enumerate() {
+        result[slot] =
+            color.ok_or_else(|| ThemeError::Parse(format!("missing `base{slot:02X}`")))?;
+    }
+    Ok(result)
+}
+
+impl Theme {
+    /// Loads a base16 scheme, coloring captures with [`BASE16_SLOTS`].
+    ///
+    /// See [`Theme::from_base16_yaml_with`].
+    pub fn from_base16_yaml(yaml: &str) -> Result<Theme, ThemeError> {
+        Self::from_base16_yaml_with(yaml, BASE16_SLOTS)
+    }
+
+    /// Loads a base16 scheme, coloring each capture name with the scheme's color for its
+    /// slot in `slots`.
+    ///
+    /// `base00` is the background, `base05` the foreground, `base01` the line highlight,
+    /// `base04` the gutter color and `base02` the selection background. Schemes only have colors, so no capture is bold or italic.
+    pub fn from_base16_yaml_with(yaml: &str, slots: &[(&str, u8)]) -> Result<Theme, ThemeError> {
+        let colors = colors(yaml)?;
+        let styles = slots
+            .iter()
+            .map(|(name, slot)| {
+                let color = colors.get(*slot as usize).ok_or_else(|| {
+                    ThemeError::Parse(format!("invalid slot {slot} for `{name}`"))
+                })?;
+                Ok((*name, Style::new().fg(*color)))
+            })
+            .collect::<Result<Vec<_>, ThemeError>>()?;
+        let mut theme = Theme::new(&styles);
+        theme.background = Some(colors[0x00]);
+        theme.foreground = Some(colors[0x05]);
+        theme.line_highlight = Some(colors[0x01]);
+        theme.gutter_fg = Some(colors[0x04]);
+        theme.selection_bg = Some(colors[0x02]);
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_dark() {
+        let theme = Theme::from_base16_yaml(include_str!("../fixtures/default-dark.yaml")).unwrap();
+        assert_eq!(theme.background, Some(Color::rgb(0x18, 0x18, 0x18)));
+        assert_eq!(theme.foreground, Some(Color::rgb(0xd8, 0xd8, 0xd8)));
+        assert_eq!(theme.line_highlight, Some(Color::rgb(0x28, 0x28, 0x28)));
+        assert_eq!(theme.gutter_fg, Some(Color::rgb(0xb8, 0xb8, 0xb8)));
+        assert_eq!(theme.selection_bg, Some(Color::rgb(0x38, 0x38, 0x38)));
+
+        let fg = |name| theme.resolve(name).fg;
+        assert_eq!(fg("comment"), Some(Color::rgb(0x58, 0x58, 0x58)));
+        assert_eq!(fg("string"), Some(Color::rgb(0xa1, 0xb5, 0x6c)));
+        assert_eq!(fg("keyword.function"), Some(Color::rgb(0xba, 0x8b, 0xaf)));
+        assert_eq!(fg("function.call"), Some(Color::rgb(0x7c, 0xaf, 0xc2)));
+        assert_eq!(fg("type.builtin"), Some(Color::rgb(0xf7, 0xca, 0x88)));
+        assert_eq!(fg("number"), Some(Color::rgb(0xdc, 0x96, 0x56)));
+    }
+
+    #[test]
+    fn layouts() {
+        let flat = (0..16)
+            .map(|i| format!("base{i:02X}: \"{:06x}\" # slot {i}\n", i * 0x111111))
+            .collect::<String>();
+        let nested = (0..16)
+            .map(|i| format!("  base{i:02X}: '#{:06x}'\n", i * 0x111111))
+            .collect::<String>();
+        let nested = format!("system: \"base16\"\nname: \"Gray\"\npalette:\n{nested}");
+        for yaml in [flat, nested] {
+            assert_eq!(colors(&yaml).unwrap()[0x0B], Color::rgb(0xbb, 0xbb, 0xbb));
+        }
+        assert!(colors("base00: \"000000\"").is_err());
+    }
+
+    #[test]
+    fn overrides() {
+        let yaml = include_str!("../fixtures/default-dark.yaml");
+        let theme = Theme::from_base16_yaml_with(yaml, &[("string", 0x08)]).unwrap();
+        assert_eq!(
+            theme.resolve("string").fg,
+            Some(Color::rgb(0xab, 0x46, 0x42))
+        );
+        assert_eq!(theme.resolve("comment"), Style::default());
+        assert!(Theme::from_base16_yaml_with(yaml, &[("string", 0x10)]).is_err());
+    }
+}
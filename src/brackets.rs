@@ -0,0 +1,158 @@
+use std::fmt::Write;
+
+use tree_sitter_highlight::{Highlight, HighlightEvent};
+
+/// The family of a bracket and whether it opens.
+fn bracket(text: &[u8]) -> Option<(u8, bool)> {
+    match text {
+        b"(" => Some((b'(', true)),
+        b")" => Some((b'(', false)),
+        b"[" => Some((b'[', true)),
+        b"]" => Some((b'[', false)),
+        b"{" => Some((b'{', true)),
+        b"}" => Some((b'{', false)),
+        _ => None,
+    }
+}
+
+/// The source of the highlight opened by `events[0]`, if it is one piece.
+fn text<'s>(events: &[HighlightEvent], source: &'s [u8]) -> Option<&'s [u8]> {
+    match events.get(1..3)? {
+        [HighlightEvent::Source { start, end }, HighlightEvent::HighlightEnd] => {
+            source.get(*start..*end)
+        }
+        _ => None,
+    }
+}
+
+/// Adds `data-pair="pN"` attributes to both brackets of each pair of `punctuation.bracket`
+/// highlights, with `names` the capture names of the highlight indices.
+///
+/// Brackets pair up with a stack per family, so a stray `]` does not break the pairs of
+/// parentheses around it. Unbalanced brackets get no attribute. The attributes are
+/// appended to `classes`.
+pub(crate) fn pair(
+    names: &[String],
+    source: &[u8],
+    mut events: Vec<HighlightEvent>,
+    classes: &mut Vec<String>,
+) -> Vec<HighlightEvent> {
+    // The indices of the open brackets of each family, and the pairs found.
+    let mut open: Vec<(u8, usize)> = Vec::new();
+    let mut pairs = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        let HighlightEvent::HighlightStart(h) = event else {
+            continue;
+        };
+        if names.get(h.0).map(String::as_str) != Some("punctuation.bracket") {
+            continue;
+        }
+        let Some((family, opens)) = text(&events[i..], source).and_then(bracket) else {
+            continue;
+        };
+        if opens {
+            open.push((family, i));
+        } else if let Some(j) = open.iter().rposition(|(f, _)| *f == family) {
+            pairs.push((open.remove(j).1, i));
+        }
+    }
+    pairs.sort_unstable();
+
+    for (id, (start, end)) in (1..).zip(pairs) {
+        for i in [start, end] {
+            let HighlightEvent::HighlightStart(h) = events[i] else {
+                continue;
+            };
+            let mut attrs = classes.get(h.0).cloned().unwrap_or_default();
+            if !attrs.is_empty() {
+                attrs.push(' ');
+            }
+            let _ = write!(attrs, "data-pair=\"p{id}\"");
+            classes.push(attrs);
+            events[i] = HighlightEvent::HighlightStart(Highlight(classes.len() - 1));
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, RenderOptions};
+
+    /// The events of bracket highlights around plain text.
+    fn events(source: &[u8]) -> Vec<HighlightEvent> {
+        (0..source.len())
+            .flat_map(|i| {
+                let piece = HighlightEvent::Source {
+                    start: i,
+                    end: i + 1,
+                };
+                match bracket(&source[i..i + 1]) {
+                    Some(_) => vec![
+                        HighlightEvent::HighlightStart(Highlight(0)),
+                        piece,
+                        HighlightEvent::HighlightEnd,
+                    ],
+                    None => vec![piece],
+                }
+            })
+            .collect()
+    }
+
+    /// The id of each bracket, `0` for unpaired ones.
+    fn ids(source: &[u8]) -> Vec<usize> {
+        let names = [String::from("punctuation.bracket")];
+        let mut classes = vec![String::from("class=punctuation.bracket")];
+        pair(&names, source, events(source), &mut classes)
+            .into_iter()
+            .filter_map(|event| match event {
+                HighlightEvent::HighlightStart(h) => Some(h.0),
+                _ => None,
+            })
+            .map(|h| {
+                classes[h]
+                    .split("data-pair=\"p")
+                    .nth(1)
+                    .map_or(0, |id| id.trim_end_matches('"').parse().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(ids(b"f(a[0], {b})"), [1, 2, 2, 3, 3, 1]);
+        assert_eq!(ids(b"(()())"), [1, 2, 2, 3, 3, 1]);
+    }
+
+    #[test]
+    fn unbalanced() {
+        assert_eq!(ids(b"(]"), [0, 0]);
+        assert_eq!(ids(b"((a)"), [0, 1, 1]);
+        assert_eq!(ids(b"(a))"), [1, 1, 0]);
+        assert_eq!(ids(b"([)]"), [1, 2, 1, 2]);
+        assert_eq!(ids(b"}{"), [0, 0]);
+    }
+
+    #[test]
+    fn render() {
+        let languages = rust();
+        let options = RenderOptions::new().pair_brackets(true);
+        let html = languages
+            .render_with("rust", b"fn f() { g(1); }", &options)
+            .unwrap();
+        for (id, count) in [("p1", 2), ("p2", 2), ("p3", 2)] {
+            assert_eq!(
+                html.matches(&format!("data-pair=\"{id}\"")).count(),
+                count,
+                "{html}"
+            );
+        }
+        assert!(html.contains("<span class=punctuation.bracket data-pair=\"p2\">{</span>"));
+
+        let html = languages
+            .render_with("rust", b"fn f() { g(1; ]", &options)
+            .unwrap();
+        assert_eq!(html.matches("data-pair=\"p1\"").count(), 2, "{html}");
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{Error, Fingerprint, Languages, RenderOptions};
+
+/// The number of independently locked parts of a cache.
+const SHARDS: usize = 16;
+
+/// A registry whose renders are cached, for sites rendering the same snippets again and
+/// again, such as the unchanged code blocks of an edited wiki page.
+///
+/// Renders are keyed by language, source, options and the generation of the registry,
+/// which every [`CachedLanguages::update`] bumps, and the least recently used are evicted
+/// past the capacity or the byte budget. The cache is locked in shards, so it can be
+/// shared between threads, such as in an `Arc`.
+///
+/// ```ignore
+/// let cached = CachedLanguages::new(languages, 1024).max_bytes(64 << 20);
+/// let html = cached.render_with("rust", source, &options)?;
+/// ```
+pub struct CachedLanguages<'a> {
+    inner: Languages<'a>,
+    /// The number of changes to `inner`, so renders of an older registry are not served.
+    generation: u64,
+    /// The capacity of each shard.
+    capacity: usize,
+    /// The byte budget of each shard, see [`CachedLanguages::max_bytes`].
+    max_bytes: usize,
+    shards: Vec<Mutex<Shard>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// The counts of a [`CachedLanguages`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The number of renders evicted for room.
+    pub evictions: u64,
+    /// The number of cached renders.
+    pub entries: usize,
+    /// The total length of the cached renders and their sources.
+    pub bytes: usize,
+}
+
+/// A cached render with what it was rendered from, compared on a hit so a render is
+/// never served for another request of the same key.
+struct Cached {
+    generation: u64,
+    lang: String,
+    source: Vec<u8>,
+    /// The fingerprint of the options.
+    options: u64,
+    html: String,
+    /// The tick it was last used at.
+    used: u64,
+}
+
+impl Cached {
+    /// The bytes it holds.
+    fn len(&self) -> usize {
+        self.html.len() + self.source.len()
+    }
+}
+
+/// Renders by key.
+#[derive(Default)]
+struct Shard {
+    renders: HashMap<u64, Cached>,
+    /// The total length of `renders`, see [`Cached::len`].
+    bytes: usize,
+    tick: u64,
+}
+
+impl<'a> CachedLanguages<'a> {
+    /// Caches the renders of a registry, keeping at most about `capacity` of them.
+    pub fn new(languages: Languages<'a>, capacity: usize) -> Self {
+        Self {
+            inner: languages,
+            generation: 0,
+            capacity: capacity.div_ceil(SHARDS).max(1),
+            max_bytes: usize::MAX,
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Keeps the cached renders and their sources to about `bytes` in total, unbounded
+    /// by default.
+    ///
+    /// Renders larger than a shard's share of the budget are not cached.
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = bytes.div_ceil(SHARDS).max(1);
+        self
+    }
+
+    pub fn languages(&self) -> &Languages<'a> {
+        &self.inner
+    }
+
+    /// Changes the registry, after which renders of the previous one are no longer served
+    /// from the cache; they age out as others are cached.
+    pub fn update(&mut self, f: impl FnOnce(&mut Languages<'a>)) {
+        f(&mut self.inner);
+        self.generation += 1;
+    }
+
+    /// Renders like [`Languages::render`], from the cache when possible.
+    pub fn render(&self, lang: &str, source: &[u8]) -> Option<String> {
+        self.render_with(lang, source, &RenderOptions::default())
+            .ok()
+    }
+
+    /// Renders like [`Languages::render_with`], from the cache when possible.
+    ///
+    /// Failed renders are not cached.
+    pub fn render_with(
+        &self,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+    ) -> Result<String, Error> {
+        let mut hasher = Fingerprint::new();
+        options.fingerprint(&mut hasher);
+        let fingerprint = hasher.finish();
+        let mut hasher = Fingerprint::new();
+        self.generation.hash(&mut hasher);
+        lang.hash(&mut hasher);
+        source.hash(&mut hasher);
+        fingerprint.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let shard = &self.shards[key as usize % SHARDS];
+        {
+            let mut shard = shard.lock().unwrap_or_else(|e| e.into_inner());
+            shard.tick += 1;
+            let tick = shard.tick;
+            // Keys are not collision-resistant, and sources may come from users.
+            if let Some(cached) = shard.renders.get_mut(&key).filter(|cached| {
+                cached.generation == self.generation
+                    && cached.options == fingerprint
+                    && cached.lang == lang
+                    && cached.source == source
+            }) {
+                cached.used = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(cached.html.clone());
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        // Rendered unlocked, so a slow render does not hold up the shard.
+        let html = self.inner.render_with(lang, source, options)?;
+        if html.len() + source.len() > self.max_bytes {
+            return Ok(html);
+        }
+        let mut shard = shard.lock().unwrap_or_else(|e| e.into_inner());
+        shard.tick += 1;
+        let cached = Cached {
+            generation: self.generation,
+            lang: lang.to_string(),
+            source: source.to_vec(),
+            options: fingerprint,
+            html: html.clone(),
+            used: shard.tick,
+        };
+        shard.bytes += cached.len();
+        if let Some(old) = shard.renders.insert(key, cached) {
+            shard.bytes -= old.len();
+        }
+        while shard.renders.len() > self.capacity || shard.bytes > self.max_bytes {
+            let oldest = shard
+                .renders
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(key, _)| *key);
+            if let Some(old) = oldest.and_then(|key| shard.renders.remove(&key)) {
+                shard.bytes -= old.len();
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(html)
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        let mut stats = CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            ..CacheStats::default()
+        };
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap_or_else(|e| e.into_inner());
+            stats.entries += shard.renders.len();
+            stats.bytes += shard.bytes;
+        }
+        stats
+    }
+
+    /// Drops every cached render, keeping the counts.
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap_or_else(|e| e.into_inner());
+            shard.renders.clear();
+            shard.bytes = 0;
+        }
+    }
+}
+
+impl<'a> From<Languages<'a>> for CachedLanguages<'a> {
+    fn from(languages: Languages<'a>) -> Self {
+        Self::new(languages, 1024)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, ClassStyle};
+
+    #[test]
+    fn hits() {
+        let cached = CachedLanguages::new(rust(), 64);
+        let source = b"fn main() {}\n";
+        let html = cached.render("rust", source).unwrap();
+        assert_eq!(cached.render("rust", source).unwrap(), html);
+        assert_eq!(
+            cached.cache_stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                evictions: 0,
+                entries: 1,
+                bytes: html.len() + source.len(),
+            }
+        );
+
+        // One byte more.
+        cached.render("rust", b"fn main() {}\n\n").unwrap();
+        assert_eq!(cached.cache_stats().misses, 2);
+        // Other options.
+        let options = RenderOptions::new().start_line(3);
+        cached.render_with("rust", source, &options).unwrap();
+        assert_eq!(cached.cache_stats().misses, 3);
+        cached.render_with("rust", source, &options).unwrap();
+        assert_eq!(cached.cache_stats().hits, 2);
+        // Failures are not cached.
+        assert!(cached.render("cobol", source).is_none());
+        assert_eq!(cached.cache_stats().entries, 3);
+    }
+
+    #[test]
+    fn collisions() {
+        let cached = CachedLanguages::new(rust(), 64);
+        let html = cached.render("rust", b"let a;").unwrap();
+        // What a key collision with another source would leave under the key.
+        for shard in &cached.shards {
+            for render in shard.lock().unwrap().renders.values_mut() {
+                render.source = b"let b;".to_vec();
+                render.html = "<forged>".to_string();
+            }
+        }
+        assert_eq!(cached.render("rust", b"let a;").unwrap(), html);
+        assert_eq!(cached.cache_stats().misses, 2);
+        assert_eq!(cached.render("rust", b"let a;").unwrap(), html);
+        assert_eq!(cached.cache_stats().hits, 1);
+    }
+
+    #[test]
+    fn registry_changes() {
+        let mut cached = CachedLanguages::new(rust(), 64);
+        let source = b"fn main() {}\n";
+        let html = cached.render("rust", source).unwrap();
+        cached.update(|languages| {
+            languages.set_class_style("rust", &ClassStyle::new().prefix("hl-"));
+        });
+        let prefixed = cached.render("rust", source).unwrap();
+        assert_ne!(prefixed, html);
+        assert_eq!(cached.cache_stats().misses, 2);
+
+        // Any change counts, even one the renders do not show.
+        cached.update(|languages| {
+            languages.alias("rs", "rust");
+        });
+        assert_eq!(cached.render("rust", source).unwrap(), prefixed);
+        assert_eq!(cached.cache_stats().misses, 3);
+        assert!(cached.render("rs", source).is_some());
+    }
+
+    #[test]
+    fn eviction() {
+        let cached = CachedLanguages::new(rust(), 1);
+        for i in 0..100 {
+            cached.render("rust", format!("{i};").as_bytes()).unwrap();
+        }
+        let stats = cached.cache_stats();
+        assert!(stats.entries <= SHARDS);
+        assert_eq!(stats.evictions, 100 - stats.entries as u64);
+        cached.clear();
+        assert_eq!(cached.cache_stats().entries, 0);
+        assert_eq!(cached.cache_stats().bytes, 0);
+    }
+
+    #[test]
+    fn max_bytes() {
+        let html = rust().render("rust", b"0;").unwrap();
+        let cached = CachedLanguages::new(rust(), 1024).max_bytes(SHARDS * html.len() * 2);
+        for i in 0..100 {
+            cached.render("rust", format!("{i};").as_bytes()).unwrap();
+        }
+        let stats = cached.cache_stats();
+        assert!(stats.bytes <= SHARDS * html.len() * 2, "{stats:?}");
+        assert!(stats.evictions > 0);
+
+        // Renders over a shard's budget are never cached.
+        let big = b"let a = 1;\n".repeat(20);
+        cached.render("rust", &big).unwrap();
+        cached.render("rust", &big).unwrap();
+        assert_eq!(cached.cache_stats().misses, stats.misses + 2);
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt;
+
+/// A built-in capture name, checked at compile time.
+///
+/// `Capture::ALL` lists the captures in the same order as [`NAMES`](crate::NAMES).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capture {
+    Annotation,
+    Attribute,
+    Boolean,
+    Character,
+    CharacterSpecial,
+    Comment,
+    Conditional,
+    Constant,
+    ConstantBuiltin,
+    ConstantMacro,
+    Constructor,
+    Debug,
+    Define,
+    DiffDelta,
+    DiffMinus,
+    DiffPlus,
+    Error,
+    Exception,
+    Field,
+    Float,
+    Function,
+    FunctionCall,
+    FunctionBuiltin,
+    FunctionMacro,
+    Include,
+    Keyword,
+    KeywordFunction,
+    KeywordOperator,
+    KeywordReturn,
+    Label,
+    Method,
+    MethodCall,
+    Namespace,
+    None,
+    Number,
+    Operator,
+    Parameter,
+    ParameterReference,
+    Preproc,
+    Property,
+    PunctuationDelimiter,
+    PunctuationBracket,
+    PunctuationSpecial,
+    Repeat,
+    Storageclass,
+    String,
+    StringRegex,
+    StringEscape,
+    StringSpecial,
+    Symbol,
+    Tag,
+    TagAttribute,
+    TagDelimiter,
+    Text,
+    TextStrong,
+    TextEmphasis,
+    TextUnderline,
+    TextStrike,
+    TextTitle,
+    TextLiteral,
+    TextUri,
+    TextMath,
+    TextReference,
+    TextEnvironment,
+    TextEnvironmentName,
+    TextNote,
+    TextWarning,
+    TextDanger,
+    TextDiffAdd,
+    TextDiffDelete,
+    Todo,
+    Type,
+    TypeBuiltin,
+    TypeQualifier,
+    TypeDefinition,
+    Variable,
+    VariableBuiltin,
+}
+
+impl Capture {
+    pub const ALL: &'static [Capture] = &[
+        Self::Annotation,
+        Self::Attribute,
+        Self::Boolean,
+        Self::Character,
+        Self::CharacterSpecial,
+        Self::Comment,
+        Self::Conditional,
+        Self::Constant,
+        Self::ConstantBuiltin,
+        Self::ConstantMacro,
+        Self::Constructor,
+        Self::Debug,
+        Self::Define,
+        Self::DiffDelta,
+        Self::DiffMinus,
+        Self::DiffPlus,
+        Self::Error,
+        Self::Exception,
+        Self::Field,
+        Self::Float,
+        Self::Function,
+        Self::FunctionCall,
+        Self::FunctionBuiltin,
+        Self::FunctionMacro,
+        Self::Include,
+        Self::Keyword,
+        Self::KeywordFunction,
+        Self::KeywordOperator,
+        Self::KeywordReturn,
+        Self::Label,
+        Self::Method,
+        Self::MethodCall,
+        Self::Namespace,
+        Self::None,
+        Self::Number,
+        Self::Operator,
+        Self::Parameter,
+        Self::ParameterReference,
+        Self::Preproc,
+        Self::Property,
+        Self::PunctuationDelimiter,
+        Self::PunctuationBracket,
+        Self::PunctuationSpecial,
+        Self::Repeat,
+        Self::Storageclass,
+        Self::String,
+        Self::StringRegex,
+        Self::StringEscape,
+        Self::StringSpecial,
+        Self::Symbol,
+        Self::Tag,
+        Self::TagAttribute,
+        Self::TagDelimiter,
+        Self::Text,
+        Self::TextStrong,
+        Self::TextEmphasis,
+        Self::TextUnderline,
+        Self::TextStrike,
+        Self::TextTitle,
+        Self::TextLiteral,
+        Self::TextUri,
+        Self::TextMath,
+        Self::TextReference,
+        Self::TextEnvironment,
+        Self::TextEnvironmentName,
+        Self::TextNote,
+        Self::TextWarning,
+        Self::TextDanger,
+        Self::TextDiffAdd,
+        Self::TextDiffDelete,
+        Self::Todo,
+        Self::Type,
+        Self::TypeBuiltin,
+        Self::TypeQualifier,
+        Self::TypeDefinition,
+        Self::Variable,
+        Self::VariableBuiltin,
+    ];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Annotation => "annotation",
+            Self::Attribute => "attribute",
+            Self::Boolean => "boolean",
+            Self::Character => "character",
+            Self::CharacterSpecial => "character.special",
+            Self::Comment => "comment",
+            Self::Conditional => "conditional",
+            Self::Constant => "constant",
+            Self::ConstantBuiltin => "constant.builtin",
+            Self::ConstantMacro => "constant.macro",
+            Self::Constructor => "constructor",
+            Self::Debug => "debug",
+            Self::Define => "define",
+            Self::DiffDelta => "diff.delta",
+            Self::DiffMinus => "diff.minus",
+            Self::DiffPlus => "diff.plus",
+            Self::Error => "error",
+            Self::Exception => "exception",
+            Self::Field => "field",
+            Self::Float => "float",
+            Self::Function => "function",
+            Self::FunctionCall => "function.call",
+            Self::FunctionBuiltin => "function.builtin",
+            Self::FunctionMacro => "function.macro",
+            Self::Include => "include",
+            Self::Keyword => "keyword",
+            Self::KeywordFunction => "keyword.function",
+            Self::KeywordOperator => "keyword.operator",
+            Self::KeywordReturn => "keyword.return",
+            Self::Label => "label",
+            Self::Method => "method",
+            Self::MethodCall => "method.call",
+            Self::Namespace => "namespace",
+            Self::None => "none",
+            Self::Number => "number",
+            Self::Operator => "operator",
+            Self::Parameter => "parameter",
+            Self::ParameterReference => "parameter.reference",
+            Self::Preproc => "preproc",
+            Self::Property => "property",
+            Self::PunctuationDelimiter => "punctuation.delimiter",
+            Self::PunctuationBracket => "punctuation.bracket",
+            Self::PunctuationSpecial => "punctuation.special",
+            Self::Repeat => "repeat",
+            Self::Storageclass => "storageclass",
+            Self::String => "string",
+            Self::StringRegex => "string.regex",
+            Self::StringEscape => "string.escape",
+            Self::StringSpecial => "string.special",
+            Self::Symbol => "symbol",
+            Self::Tag => "tag",
+            Self::TagAttribute => "tag.attribute",
+            Self::TagDelimiter => "tag.delimiter",
+            Self::Text => "text",
+            Self::TextStrong => "text.strong",
+            Self::TextEmphasis => "text.emphasis",
+            Self::TextUnderline => "text.underline",
+            Self::TextStrike => "text.strike",
+            Self::TextTitle => "text.title",
+            Self::TextLiteral => "text.literal",
+            Self::TextUri => "text.uri",
+            Self::TextMath => "text.math",
+            Self::TextReference => "text.reference",
+            Self::TextEnvironment => "text.environment",
+            Self::TextEnvironmentName => "text.environment.name",
+            Self::TextNote => "text.note",
+            Self::TextWarning => "text.warning",
+            Self::TextDanger => "text.danger",
+            Self::TextDiffAdd => "text.diff.add",
+            Self::TextDiffDelete => "text.diff.delete",
+            Self::Todo => "todo",
+            Self::Type => "type",
+            Self::TypeBuiltin => "type.builtin",
+            Self::TypeQualifier => "type.qualifier",
+            Self::TypeDefinition => "type.definition",
+            Self::Variable => "variable",
+            Self::VariableBuiltin => "variable.builtin",
+        }
+    }
+}
+
+impl AsRef<str> for Capture {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HighlightConfiguration, Languages, NAMES};
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            Capture::ALL.iter().map(Capture::as_str).collect::<Vec<_>>(),
+            NAMES
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let mut languages = Languages::new();
+        languages.insert(
+            "diff",
+            HighlightConfiguration::new(
+                tree_sitter_diff::language(),
+                include_str!("../queries/diff/highlights.scm"),
+                "",
+                "",
+            )
+            .unwrap(),
+        );
+        let hunk = b"--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,2 @@\n same\n-old\n+new\n";
+        let html = languages.render("diff", hunk).unwrap();
+        assert!(html.contains("<span class=diff.minus>-old"), "{html}");
+        assert!(html.contains("<span class=diff.plus>+new"), "{html}");
+        assert!(
+            html.contains("<span class=attribute>@@ -1,2 +1,2 @@"),
+            "{html}"
+        );
+        assert!(!html.contains("<span class=diff.plus> same"), "{html}");
+    }
+
+    #[test]
+    fn typed_names() {
+        let mut languages = Languages::new();
+        languages.insert_with_names(
+            "rust",
+            HighlightConfiguration::new(
+                tree_sitter_rust::language(),
+                include_str!("../queries/rust/highlights.scm"),
+                include_str!("../queries/rust/injections.scm"),
+                include_str!("../queries/rust/locals.scm"),
+            )
+            .unwrap(),
+            &[Capture::Keyword, Capture::Variable],
+        );
+
+        assert_eq!(
+            languages.render("rust", b"let a = 1;").unwrap(),
+            "<pre class=language-rust><code><span class=line><span class=keyword>let</span> <span class=variable>a</span> = 1;\n</span></code></pre>"
+        );
+    }
+}
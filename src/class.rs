@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use crate::html::escape;
+
+/// How capture names map to HTML classes.
+///
+/// By default a capture is one class with its dotted name, like `keyword.function`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClassStyle {
+    /// Prepended to every class, like `hl-`.
+    pub prefix: String,
+    /// Joins the parts of dotted names with `-`, so `keyword.function` is
+    /// `keyword-function`.
+    pub hyphenate: bool,
+    /// Emits BEM classes for this block, one per dotted prefix, so `function.call` is
+    /// `token token--function token--function-call` for the `token` block.
+    pub bem: Option<String>,
+}
+
+impl ClassStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    pub fn hyphenate(mut self, hyphenate: bool) -> Self {
+        self.hyphenate = hyphenate;
+        self
+    }
+
+    pub fn bem(mut self, block: &str) -> Self {
+        self.bem = Some(block.to_string());
+        self
+    }
+
+    /// The class of a capture name, or its space-separated classes in BEM style.
+    pub fn class(&self, name: &str) -> String {
+        if let Some(block) = &self.bem {
+            let mut classes = format!("{}{block}", self.prefix);
+            let mut modifier = String::new();
+            for part in name.split('.') {
+                if !modifier.is_empty() {
+                    modifier.push('-');
+                }
+                modifier.push_str(part);
+                let _ = write!(classes, " {}{block}--{modifier}", self.prefix);
+            }
+            return classes;
+        }
+        let mut class = self.prefix.clone();
+        if self.hyphenate {
+            class.extend(name.chars().map(|c| if c == '.' { '-' } else { c }));
+        } else {
+            class.push_str(name);
+        }
+        class
+    }
+
+    /// The `class=` attribute of a capture name.
+    pub(crate) fn attr(&self, name: &str) -> String {
+        attr(&self.class(name))
+    }
+
+    /// The `.class` selector of a capture name, with CSS special characters escaped.
+    ///
+    /// In BEM style it selects the most specific modifier.
+    pub(crate) fn selector(&self, name: &str) -> String {
+        let mut selector = String::from(".");
+        let class = self.class(name);
+        for c in class.rsplit(' ').next().unwrap_or_default().chars() {
+            if !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()) {
+                selector.push('\\');
+            }
+            selector.push(c);
+        }
+        selector
+    }
+}
+
+/// The `class=` attribute of a class, quoted and escaped when it has to be.
+pub(crate) fn attr(class: &str) -> String {
+    let mut s = String::with_capacity(class.len() + 8);
+    s.push_str("class=");
+    if class.is_empty() || class.contains(|c: char| "\"'=<>`".contains(c) || c.is_whitespace()) {
+        s.push('"');
+        class.chars().for_each(|c| escape(&mut s, c));
+        s.push('"');
+    } else {
+        s.push_str(class);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        let style = ClassStyle::new();
+        assert_eq!(style.class("keyword.function"), "keyword.function");
+        assert_eq!(style.selector("keyword.function"), ".keyword\\.function");
+
+        let style = ClassStyle::new().prefix("hl-").hyphenate(true);
+        assert_eq!(style.class("keyword.function"), "hl-keyword-function");
+        assert_eq!(style.selector("keyword.function"), ".hl-keyword-function");
+        assert_eq!(ClassStyle::new().selector("a+b"), ".a\\+b");
+    }
+
+    #[test]
+    fn bem() {
+        let style = ClassStyle::new().bem("token");
+        assert_eq!(
+            style.class("function.call"),
+            "token token--function token--function-call"
+        );
+        assert_eq!(style.class("string"), "token token--string");
+        assert_eq!(style.selector("function.call"), ".token--function-call");
+        assert_eq!(
+            style.attr("function.call"),
+            "class=\"token token--function token--function-call\""
+        );
+
+        let style = ClassStyle::new().prefix("hl-").bem("t");
+        assert_eq!(
+            style.class("keyword.return"),
+            "hl-t hl-t--keyword hl-t--keyword-return"
+        );
+    }
+
+    #[test]
+    fn attrs() {
+        assert_eq!(attr("keyword.function"), "class=keyword.function");
+        assert_eq!(attr("a b"), "class=\"a b\"");
+        assert_eq!(attr("a<b"), "class=\"a&lt;b\"");
+        assert_eq!(attr(""), "class=\"\"");
+    }
+}
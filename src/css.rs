@@ -0,0 +1,430 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{ClassStyle, Color, FontStyle, Style, Theme, NAMES};
+
+/// The selector of the `class=name` attribute emitted for a capture.
+///
+/// Capture names contain dots, which a `.class` selector would read as several classes,
+/// so this matches the whole class with `[class~="name"]`.
+pub(crate) fn selector(name: &str) -> String {
+    format!("[class~=\"{name}\"]")
+}
+
+/// The recognized capture names and the names of the theme, sorted, whose resolved
+/// style is not the default one.
+fn styled_names(theme: &Theme) -> impl Iterator<Item = &str> {
+    NAMES
+        .iter()
+        .copied()
+        .chain(theme.names())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|name| theme.resolve(name) != Style::default())
+}
+
+/// The CSS declarations of a style, shared by stylesheets and inline `style` attributes.
+pub(crate) fn declarations(style: &Style) -> Vec<(&'static str, String)> {
+    let mut declarations = Vec::new();
+    if let Some(fg) = style.fg {
+        declarations.push(("color", fg.to_string()));
+    }
+    if let Some(bg) = style.bg {
+        declarations.push(("background-color", bg.to_string()));
+    }
+    let mut decorations = Vec::new();
+    for font_style in style.font_styles() {
+        match font_style {
+            FontStyle::Bold => declarations.push(("font-weight", "bold".to_string())),
+            FontStyle::Italic => declarations.push(("font-style", "italic".to_string())),
+            FontStyle::Underline => decorations.push("underline"),
+            FontStyle::Strikethrough => decorations.push("line-through"),
+        }
+    }
+    if !decorations.is_empty() {
+        declarations.push(("text-decoration", decorations.join(" ")));
+    }
+    declarations
+}
+
+/// Writes a rule with the declarations of a style.
+fn rule(css: &mut String, selector: &str, style: &Style) {
+    let _ = write!(css, "{selector} {{");
+    for (property, value) in declarations(style) {
+        let _ = write!(css, " {property}: {value};");
+    }
+    css.push_str(" }\n");
+}
+
+/// Generates a stylesheet for the HTML output from a theme.
+///
+/// Every recognized capture name and every name of the theme gets a rule with its
+/// resolved style, so dotted names fall back to their prefixes as in [`Theme::resolve`].
+pub fn theme_css(theme: &Theme) -> String {
+    let mut css = String::new();
+    if theme.foreground.is_some() || theme.background.is_some() {
+        css.push_str("pre[class|=\"language\"] {");
+        if let Some(fg) = theme.foreground {
+            let _ = write!(css, " color: {fg};");
+        }
+        if let Some(bg) = theme.background {
+            let _ = write!(css, " background-color: {bg};");
+        }
+        css.push_str(" }\n");
+    }
+
+    for name in styled_names(theme) {
+        rule(&mut css, &selector(name), &theme.resolve(name));
+    }
+    css
+}
+
+/// The name of a custom property, with the dots of `name` mapped to dashes.
+fn variable(prefix: &str, name: &str) -> String {
+    let name = name.replace('.', "-");
+    if prefix.is_empty() {
+        format!("--{name}")
+    } else {
+        format!("--{prefix}-{name}")
+    }
+}
+
+/// A themed property: its CSS name, the suffix of its variable and its light and dark
+/// values.
+type Property = (&'static str, &'static str, String, String);
+
+/// The properties of a capture set by either theme, with neutral values where the other
+/// one leaves them unset.
+fn properties(light: &Style, dark: &Style) -> Vec<Property> {
+    let color = |c: Option<Color>, default: &str| c.map_or(default.to_string(), |c| c.to_string());
+    let flag =
+        |set: bool, value: &str, default: &str| if set { value } else { default }.to_string();
+    let decoration = |s: &Style| match (s.underline, s.strikethrough) {
+        (true, true) => "underline line-through",
+        (true, false) => "underline",
+        (false, true) => "line-through",
+        (false, false) => "none",
+    };
+
+    let mut properties = Vec::new();
+    if light.fg.is_some() || dark.fg.is_some() {
+        properties.push((
+            "color",
+            "",
+            color(light.fg, "inherit"),
+            color(dark.fg, "inherit"),
+        ));
+    }
+    if light.bg.is_some() || dark.bg.is_some() {
+        let (l, d) = (
+            color(light.bg, "transparent"),
+            color(dark.bg, "transparent"),
+        );
+        properties.push(("background-color", "-bg", l, d));
+    }
+    if light.bold || dark.bold {
+        let (l, d) = (
+            flag(light.bold, "bold", "normal"),
+            flag(dark.bold, "bold", "normal"),
+        );
+        properties.push(("font-weight", "-weight", l, d));
+    }
+    if light.italic || dark.italic {
+        let (l, d) = (
+            flag(light.italic, "italic", "normal"),
+            flag(dark.italic, "italic", "normal"),
+        );
+        properties.push(("font-style", "-style", l, d));
+    }
+    let (l, d) = (decoration(light), decoration(dark));
+    if l != "none" || d != "none" {
+        properties.push((
+            "text-decoration",
+            "-decoration",
+            l.to_string(),
+            d.to_string(),
+        ));
+    }
+    properties
+}
+
+/// Generates a stylesheet whose colors are custom properties, switching between a light
+/// and a dark theme.
+///
+/// The variables, like `--hl-keyword-function` for `keyword.function` with the prefix
+/// `hl`, are defined under `:root` for the light theme, and under
+/// `@media (prefers-color-scheme: dark)` and `[data-theme=dark]` for the dark one. The
+/// rules for the default `.class` selectors and the `<pre>` then use them.
+pub fn css_variables(light: &Theme, dark: &Theme, prefix: &str) -> String {
+    let defaults = |t: &Theme| Style {
+        fg: t.foreground,
+        bg: t.background,
+        ..Style::default()
+    };
+    let highlight = |t: &Theme| Style {
+        bg: t.line_highlight,
+        ..Style::default()
+    };
+    let mut rules = vec![
+        (
+            "pre[class|=\"language\"]".to_string(),
+            variable(prefix, "pre"),
+            properties(&defaults(light), &defaults(dark)),
+        ),
+        (
+            ".line.highlighted".to_string(),
+            variable(prefix, "line-highlight"),
+            properties(&highlight(light), &highlight(dark)),
+        ),
+    ];
+    let names = styled_names(light)
+        .chain(styled_names(dark))
+        .collect::<BTreeSet<_>>();
+    for name in names {
+        rules.push((
+            ClassStyle::default().selector(name),
+            variable(prefix, name),
+            properties(&light.resolve(name), &dark.resolve(name)),
+        ));
+    }
+
+    let block = |css: &mut String, indent: &str, dark: bool| {
+        for (_, var, properties) in &rules {
+            for (_, suffix, l, d) in properties {
+                let value = if dark { d } else { l };
+                let _ = writeln!(css, "{indent}{var}{suffix}: {value};");
+            }
+        }
+    };
+    let mut css = String::from(":root {\n");
+    block(&mut css, "  ", false);
+    css.push_str("}\n@media (prefers-color-scheme: dark) {\n  :root {\n");
+    block(&mut css, "    ", true);
+    css.push_str("  }\n}\n[data-theme=dark] {\n");
+    block(&mut css, "  ", true);
+    css.push_str("}\n");
+    for (selector, var, properties) in &rules {
+        if properties.is_empty() {
+            continue;
+        }
+        let _ = write!(css, "{selector} {{");
+        for (property, suffix, _, _) in properties {
+            let _ = write!(css, " {property}: var({var}{suffix});");
+        }
+        css.push_str(" }\n");
+    }
+    css
+}
+
+impl Theme {
+    /// Generates a stylesheet with `.class` selectors for the classes of `class_style`.
+    ///
+    /// Besides a rule for every styled capture name, as in [`theme_css`], this styles the
+    /// `<pre>` with the default colors, `.line.highlighted` with the line highlight
+    /// background, `.line-number` with the gutter color and selections with the selection
+    /// background. With a `selector_scope` like `.docs`, every selector is prefixed with
+    /// it, for pages with other styles.
+    pub fn to_css(&self, selector_scope: Option<&str>, class_style: &ClassStyle) -> String {
+        let scope = selector_scope.map(|s| format!("{s} ")).unwrap_or_default();
+        let mut css = String::new();
+        if self.foreground.is_some() || self.background.is_some() {
+            let pre = Style {
+                fg: self.foreground,
+                bg: self.background,
+                ..Style::default()
+            };
+            rule(&mut css, &format!("{scope}pre[class|=\"language\"]"), &pre);
+        }
+        if let Some(bg) = self.line_highlight {
+            rule(
+                &mut css,
+                &format!("{scope}.line.highlighted"),
+                &Style::new().bg(bg),
+            );
+        }
+        if let Some(fg) = self.gutter_fg {
+            rule(
+                &mut css,
+                &format!("{scope}.line-number"),
+                &Style::new().fg(fg),
+            );
+        }
+        if let Some(bg) = self.selection_bg {
+            rule(
+                &mut css,
+                &format!("{scope}pre[class|=\"language\"] ::selection"),
+                &Style::new().bg(bg),
+            );
+        }
+        for name in styled_names(self) {
+            let selector = format!("{scope}{}", class_style.selector(name));
+            rule(&mut css, &selector, &self.resolve(name));
+        }
+        css
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    #[test]
+    fn css() {
+        let mut theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            ("punctuation", Style::new().fg(Color::rgb(171, 178, 191))),
+        ]);
+        theme.background = Some(Color::rgb(40, 44, 52));
+        let css = theme_css(&theme);
+        assert!(css.starts_with("pre[class|=\"language\"] { background-color: #282c34; }\n"));
+        assert!(css.contains("[class~=\"keyword\"] { color: #c678dd; font-weight: bold; }\n"));
+        assert!(
+            css.contains("[class~=\"keyword.function\"] { color: #c678dd; font-weight: bold; }\n")
+        );
+        assert!(css.contains("[class~=\"punctuation.delimiter\"] { color: #abb2bf; }\n"));
+        assert!(!css.contains("string"));
+    }
+
+    #[test]
+    fn declarations() {
+        let style = Style::new()
+            .fg(Color::rgb(198, 120, 221))
+            .strikethrough()
+            .italic()
+            .underline();
+        assert_eq!(
+            super::declarations(&style),
+            [
+                ("color", "#c678dd".to_string()),
+                ("font-style", "italic".to_string()),
+                ("text-decoration", "underline line-through".to_string()),
+            ]
+        );
+        assert!(super::declarations(&Style::default()).is_empty());
+    }
+
+    #[test]
+    fn to_css() {
+        let mut theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            ("keyword.return", Style::new().fg(Color::rgb(224, 108, 117))),
+            (
+                "string",
+                Style::new().fg(Color::rgb(152, 195, 121)).italic(),
+            ),
+            ("text.strike", Style::new().strikethrough()),
+        ]);
+        theme.background = Some(Color::rgb(40, 44, 52));
+        theme.foreground = Some(Color::rgb(171, 178, 191));
+        theme.line_highlight = Some(Color::rgb(44, 49, 58));
+
+        assert_eq!(
+            theme.to_css(Some(".docs"), &ClassStyle::new()),
+            include_str!("../fixtures/theme.css")
+        );
+        let css = theme.to_css(None, &ClassStyle::new().prefix("hl-").hyphenate(true));
+        assert!(css.starts_with("pre[class|=\"language\"] {"));
+        assert!(css.contains("\n.hl-keyword-function { color: #c678dd; font-weight: bold; }\n"));
+    }
+
+    #[test]
+    fn backgrounds() {
+        let mut theme = Theme::new(&[
+            ("diff.plus", Style::new().bg(Color::rgb(218, 251, 225))),
+            (
+                "diff.minus",
+                Style::new()
+                    .fg(Color::rgb(130, 7, 30))
+                    .bg(Color::rgb(255, 235, 233)),
+            ),
+        ]);
+        theme.line_highlight = Some(Color::rgb(255, 248, 197));
+        theme.gutter_fg = Some(Color::rgb(140, 149, 159));
+        theme.selection_bg = Some(Color::rgb(84, 174, 255));
+
+        let css = theme.to_css(None, &ClassStyle::new());
+        assert!(css.contains(".line.highlighted { background-color: #fff8c5; }\n"));
+        assert!(css.contains(".line-number { color: #8c959f; }\n"));
+        assert!(
+            css.contains("pre[class|=\"language\"] ::selection { background-color: #54aeff; }\n")
+        );
+        assert!(css.contains(".diff\\.plus { background-color: #dafbe1; }\n"));
+        assert!(css.contains(".diff\\.minus { color: #82071e; background-color: #ffebe9; }\n"));
+    }
+
+    #[test]
+    fn variables() {
+        let mut light = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(166, 38, 164)).bold()),
+            ("string", Style::new().fg(Color::rgb(80, 161, 79))),
+        ]);
+        light.foreground = Some(Color::rgb(56, 58, 66));
+        let mut dark = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221))),
+            ("comment", Style::new().italic()),
+        ]);
+        dark.foreground = Some(Color::rgb(171, 178, 191));
+        dark.background = Some(Color::rgb(40, 44, 52));
+
+        let css = css_variables(&light, &dark, "hl");
+        fn declarations(block: &str) -> Vec<(&str, &str)> {
+            block
+                .lines()
+                .map(str::trim)
+                .filter(|line| line.starts_with("--"))
+                .map(|line| line.split_once(": ").unwrap())
+                .collect()
+        }
+        let root = &css[css.find(":root {").unwrap()..css.find("@media").unwrap()];
+        let media = &css[css.find("@media (prefers-color-scheme: dark)").unwrap()
+            ..css.find("[data-theme=dark]").unwrap()];
+        let attribute = &css[css.find("[data-theme=dark] {").unwrap()..];
+        let (light, dark) = (declarations(root), declarations(media));
+        assert_eq!(dark, declarations(attribute));
+        let names = |d: &[(&str, &str)]| d.iter().map(|(n, _)| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(names(&light), names(&dark));
+
+        assert!(light.contains(&("--hl-keyword-function", "#a626a4;")));
+        assert!(dark.contains(&("--hl-keyword-function", "#c678dd;")));
+        assert!(light.contains(&("--hl-keyword-weight", "bold;")));
+        assert!(dark.contains(&("--hl-keyword-weight", "normal;")));
+        assert!(light.contains(&("--hl-pre-bg", "transparent;")));
+        assert!(dark.contains(&("--hl-comment-style", "italic;")));
+        assert!(css.contains(
+            "\n.keyword\\.function { color: var(--hl-keyword-function); font-weight: var(--hl-keyword-function-weight); }\n"
+        ));
+        assert!(css.contains(
+            "\npre[class|=\"language\"] { color: var(--hl-pre); background-color: var(--hl-pre-bg); }\n"
+        ));
+        assert!(!css.contains(".line.highlighted"));
+    }
+
+    #[test]
+    fn selectors_match_markup() {
+        let theme = Theme::new(&[("punctuation", Style::new().bold())]);
+        let css = theme_css(&theme);
+        let html = rust().render("rust", b"fn f(a: u8) {}").unwrap();
+        let classes = html
+            .split("<span class=")
+            .skip(1)
+            .filter_map(|s| s.split('>').next())
+            .filter(|class| *class != "line")
+            .collect::<Vec<_>>();
+        assert!(classes.contains(&"punctuation.bracket"));
+
+        // `[class~="v"]` matches when `v` is one of the whitespace-separated classes.
+        let values = css
+            .lines()
+            .filter_map(|rule| rule.strip_prefix("[class~=\""))
+            .filter_map(|rule| rule.split('"').next())
+            .collect::<Vec<_>>();
+        for class in classes {
+            let styled = theme.resolve(class) != Default::default();
+            let matched = values
+                .iter()
+                .any(|v| class.split_ascii_whitespace().any(|c| c == *v));
+            assert_eq!(matched, styled, "{class}");
+        }
+        assert!(!css.contains(".punctuation"));
+    }
+}
@@ -0,0 +1,206 @@
+use std::iter;
+
+use tree_sitter_highlight::HighlightEvent;
+
+use crate::{
+    html::{normalize_source, Buffers},
+    write_events, Languages, RenderOptions, ZeroWidth,
+};
+
+/// The language of each file extension, lowercase.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("bash", "bash"),
+    ("c", "c"),
+    ("cc", "cpp"),
+    ("cjs", "javascript"),
+    ("cpp", "cpp"),
+    ("cs", "c_sharp"),
+    ("css", "css"),
+    ("cxx", "cpp"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("go", "go"),
+    ("h", "c"),
+    ("hpp", "cpp"),
+    ("hs", "haskell"),
+    ("htm", "html"),
+    ("html", "html"),
+    ("java", "java"),
+    ("js", "javascript"),
+    ("json", "json"),
+    ("jsx", "javascript"),
+    ("kt", "kotlin"),
+    ("lua", "lua"),
+    ("md", "markdown"),
+    ("mjs", "javascript"),
+    ("php", "php"),
+    ("pl", "perl"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("scala", "scala"),
+    ("sh", "bash"),
+    ("sql", "sql"),
+    ("swift", "swift"),
+    ("toml", "toml"),
+    ("ts", "typescript"),
+    ("tsx", "tsx"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("zig", "zig"),
+];
+
+/// The language of each file name without a telling extension.
+const FILENAMES: &[(&str, &str)] = &[
+    ("Dockerfile", "dockerfile"),
+    ("GNUmakefile", "make"),
+    ("Makefile", "make"),
+    ("makefile", "make"),
+];
+
+/// The language of each interpreter, without its version.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("bash", "bash"),
+    ("node", "javascript"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("python", "python"),
+    ("ruby", "ruby"),
+    ("sh", "bash"),
+    ("zsh", "bash"),
+];
+
+fn lookup(table: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, lang)| *lang)
+}
+
+/// The language of each common name that is not its conventional one, lowercase.
+const FALLBACKS: &[(&str, &str)] = &[
+    ("c#", "c_sharp"),
+    ("c++", "cpp"),
+    ("console", "bash"),
+    ("csharp", "c_sharp"),
+    ("ecmascript", "javascript"),
+    ("golang", "go"),
+    ("jsonc", "json"),
+    ("node", "javascript"),
+    ("python3", "python"),
+    ("shell", "bash"),
+    ("xhtml", "html"),
+    ("zsh", "bash"),
+];
+
+/// The conventional language of a lowercase name, looked up as a common name, then as a
+/// file extension.
+pub(crate) fn fallback_language(name: &str) -> Option<&'static str> {
+    lookup(FALLBACKS, name).or_else(|| lookup(EXTENSIONS, name))
+}
+
+/// Detects the language of a file from its name, then from its `#!` line.
+///
+/// The names are the conventional ones, like `rust` for `.rs` files and `python` for a
+/// `#!/usr/bin/env python3` script, so they match the registered names only when
+/// languages are registered under those.
+pub fn detect_language(filename: Option<&str>, source: &[u8]) -> Option<&'static str> {
+    filename
+        .and_then(|path| {
+            let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+            lookup(FILENAMES, name).or_else(|| {
+                let (stem, ext) = name.rsplit_once('.')?;
+                (!stem.is_empty()).then_some(())?;
+                lookup(EXTENSIONS, &ext.to_ascii_lowercase())
+            })
+        })
+        .or_else(|| shebang(source))
+}
+
+/// The language of the interpreter of a `#!` line.
+fn shebang(source: &[u8]) -> Option<&'static str> {
+    let line = source.strip_prefix(b"#!")?;
+    let line = &line[..line.iter().position(|b| *b == b'\n').unwrap_or(line.len())];
+    let line = std::str::from_utf8(line).ok()?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    lookup(INTERPRETERS, name)
+}
+
+impl<'a> Languages<'a> {
+    /// Highlights a file in its detected language, see [`detect_language`].
+    ///
+    /// Files in undetected or unregistered languages, and files that fail to highlight,
+    /// are rendered as escaped plain text in a `language-plaintext` block with the same
+    /// line spans.
+    pub fn render_auto(&self, filename: Option<&str>, source: &[u8]) -> String {
+        detect_language(filename, source)
+            .and_then(|lang| self.render(lang, source))
+            .unwrap_or_else(|| render_plain(source))
+    }
+}
+
+/// Renders the source without highlights.
+pub(crate) fn render_plain(source: &[u8]) -> String {
+    let source = &normalize_source(source, ZeroWidth::Keep);
+    let events = iter::once(Ok(HighlightEvent::Source {
+        start: 0,
+        end: source.len(),
+    }));
+    let mut s = String::with_capacity(source.len() + 64);
+    // Without highlights, rendering can only fail on writing, which a `String` does not.
+    let _ = write_events(
+        "plaintext",
+        source,
+        events,
+        &RenderOptions::default(),
+        &[],
+        &mut Buffers::default(),
+        &mut s,
+    );
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    #[test]
+    fn detect() {
+        assert_eq!(detect_language(Some("src/main.rs"), b""), Some("rust"));
+        assert_eq!(
+            detect_language(Some("C:\\x\\App.JSX"), b""),
+            Some("javascript")
+        );
+        assert_eq!(detect_language(Some("Makefile"), b""), Some("make"));
+        assert_eq!(detect_language(Some(".rs"), b""), None);
+        assert_eq!(detect_language(Some("a.xyz"), b""), None);
+
+        let script = b"#!/usr/bin/env -S python3.11 -u\nprint(1)\n";
+        assert_eq!(detect_language(None, script), Some("python"));
+        assert_eq!(detect_language(Some("run"), b"#!/bin/sh\n"), Some("bash"));
+        assert_eq!(detect_language(Some("a.rs"), b"#!/bin/sh\n"), Some("rust"));
+        assert_eq!(detect_language(None, b"#!/usr/bin/tclsh\n"), None);
+        assert_eq!(detect_language(None, b"fn main() {}\n"), None);
+    }
+
+    #[test]
+    fn render_auto() {
+        let languages = rust();
+        let source = b"fn main() {}\n";
+        assert_eq!(
+            languages.render_auto(Some("main.rs"), source),
+            languages.render("rust", source).unwrap()
+        );
+        assert_eq!(
+            languages.render_auto(Some("notes.xyz"), b"a < b\n\nc"),
+            "<pre class=language-plaintext><code><span class=line>a &lt; b\n</span><span class=line>\n</span><span class=line>c\n</span></code></pre>"
+        );
+        assert_eq!(
+            languages.render_auto(None, b""),
+            "<pre class=language-plaintext><code><span class=line>\n</span></code></pre>"
+        );
+    }
+}
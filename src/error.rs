@@ -0,0 +1,45 @@
+use std::{fmt, ops::Range};
+
+/// Errors
+#[derive(Debug)]
+pub enum Error {
+    /// The language is not registered.
+    UnknownLanguage(String),
+    /// The highlighter failed.
+    Highlight(tree_sitter_highlight::Error),
+    /// A highlight index has no class, in strict mode.
+    UnmappedIndex(usize),
+    /// The output writer failed.
+    Write(fmt::Error),
+    /// An override range is outside the source or names an unknown capture, with
+    /// [`RenderOptions::strict_overrides`](crate::RenderOptions::strict_overrides).
+    InvalidOverride(Range<usize>, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownLanguage(lang) => write!(f, "unknown language `{lang}`"),
+            Self::Highlight(e) => write!(f, "highlight failed: {e:?}"),
+            Self::UnmappedIndex(i) => write!(f, "highlight index {i} has no class"),
+            Self::Write(e) => write!(f, "write failed: {e}"),
+            Self::InvalidOverride(range, name) => {
+                write!(f, "invalid override `{name}` at {range:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<tree_sitter_highlight::Error> for Error {
+    fn from(e: tree_sitter_highlight::Error) -> Self {
+        Self::Highlight(e)
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(e: fmt::Error) -> Self {
+        Self::Write(e)
+    }
+}
@@ -0,0 +1,131 @@
+use std::ops::Range;
+
+use tree_sitter::Tree;
+use tree_sitter_highlight::{Highlight, HighlightEvent};
+
+/// The byte ranges of the outermost `ERROR` nodes of a tree and the offsets of its
+/// `MISSING` nodes, in source order.
+fn errors(tree: &Tree) -> (Vec<Range<usize>>, Vec<usize>) {
+    let (mut errors, mut missing) = (Vec::new(), Vec::new());
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() {
+            errors.push(node.byte_range());
+        } else if node.is_missing() {
+            missing.push(node.start_byte());
+        } else if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return (errors, missing);
+            }
+        }
+    }
+}
+
+/// Wraps the text of the `ERROR` nodes of a tree in `parse-error` highlights and adds an
+/// empty `parse-missing` highlight where a `MISSING` node was inserted.
+///
+/// Each piece of text is wrapped on its own, inside the highlights around it, so the
+/// nesting of the others is kept. The classes are appended to `classes`.
+pub(crate) fn mark(
+    tree: &Tree,
+    events: Vec<HighlightEvent>,
+    classes: &mut Vec<String>,
+) -> Vec<HighlightEvent> {
+    let (errors, missing) = errors(tree);
+    if errors.is_empty() && missing.is_empty() {
+        return events;
+    }
+    classes.push(String::from("class=parse-error"));
+    let error = Highlight(classes.len() - 1);
+    classes.push(String::from("class=parse-missing"));
+    let marker = [
+        HighlightEvent::HighlightStart(Highlight(classes.len() - 1)),
+        HighlightEvent::HighlightEnd,
+    ];
+
+    let mut cuts = errors
+        .iter()
+        .flat_map(|r| [r.start, r.end])
+        .chain(missing.iter().copied())
+        .collect::<Vec<_>>();
+    cuts.sort_unstable();
+    cuts.dedup();
+
+    let mut missing = missing.into_iter().peekable();
+    let mut out = Vec::with_capacity(events.len() + cuts.len() * 3);
+    for event in events {
+        let HighlightEvent::Source { start, end } = event else {
+            out.push(event);
+            continue;
+        };
+        let from = cuts.partition_point(|cut| *cut <= start);
+        let mut points = vec![start];
+        points.extend(cuts[from..].iter().take_while(|cut| **cut < end));
+        points.push(end);
+        for w in points.windows(2) {
+            let (start, end) = (w[0], w[1]);
+            while missing.next_if(|m| *m <= start).is_some() {
+                out.extend(marker);
+            }
+            let piece = HighlightEvent::Source { start, end };
+            if errors.iter().any(|r| r.start <= start && end <= r.end) {
+                out.extend([
+                    HighlightEvent::HighlightStart(error),
+                    piece,
+                    HighlightEvent::HighlightEnd,
+                ]);
+            } else {
+                out.push(piece);
+            }
+        }
+    }
+    // Missing nodes at the end of the source.
+    for _ in missing {
+        out.extend(marker);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::rust, RenderOptions};
+
+    /// The text of the `parse-error` spans of a render.
+    fn marked(html: &str) -> String {
+        html.split("<span class=parse-error>")
+            .skip(1)
+            .filter_map(|s| s.split("</span>").next())
+            .collect()
+    }
+
+    #[test]
+    fn mark_errors() {
+        let languages = rust();
+        let options = RenderOptions::new().mark_errors(true);
+        let source = b"let a = 1;\nlet b = $$$;\nlet c = 2;\n";
+        let html = languages.render_with("rust", source, &options).unwrap();
+        let marked = marked(&html);
+        assert!(marked.contains("$$$"), "{html}");
+        assert!(!marked.contains("let a") && !marked.contains('2'), "{html}");
+        assert_eq!(
+            crate::extract_text(&html),
+            String::from_utf8_lossy(source),
+            "{html}"
+        );
+
+        let html = languages
+            .render_with("rust", b"fn f() { g(1 }\n", &options)
+            .unwrap();
+        assert!(html.contains("parse-"), "{html}");
+
+        let valid = b"let a = 1;\n";
+        assert_eq!(
+            languages.render_with("rust", valid, &options).unwrap(),
+            languages.render("rust", valid).unwrap()
+        );
+    }
+}
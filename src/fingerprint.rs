@@ -0,0 +1,51 @@
+use std::hash::Hasher;
+
+/// A 64-bit FNV-1a hasher, stable across builds and platforms.
+pub(crate) struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fingerprint {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// The digest as 16 lowercase hex digits.
+    pub(crate) fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+impl Hasher for Fingerprint {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write(&(n as u64).to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv() {
+        let mut h = Fingerprint::new();
+        assert_eq!(h.hex(), "cbf29ce484222325");
+        h.write(b"a");
+        assert_eq!(h.hex(), "af63dc4c8601ec8c");
+    }
+}
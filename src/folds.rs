@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+use tree_sitter::{Node, Query, QueryCursor, QueryError, Tree};
+
+use crate::{Error, Languages};
+
+impl<'a> Languages<'a> {
+    /// Sets the folds query of a language, whose `@fold` captures are the foldable nodes,
+    /// as in nvim-treesitter's `folds.scm`; returns `false` when the language is unknown.
+    pub fn set_folds_query(&mut self, lang: &str, query: &str) -> Result<bool, QueryError> {
+        let Some(entry) = self.lookup_mut(lang) else {
+            return Ok(false);
+        };
+        entry.folds = Some(Query::new(entry.inner.0.language, query)?);
+        Ok(true)
+    }
+
+    /// The foldable line ranges of a source, after the language's folds query, empty
+    /// without one.
+    ///
+    /// Ranges are 1-based and end-exclusive like [`RenderOptions::collapse`](crate::RenderOptions::collapse),
+    /// sorted by start with enclosing ranges first, so nested folds follow their parent.
+    /// Nodes on a single line are left out, and nodes spanning the same lines give one
+    /// range.
+    pub fn fold_ranges(&self, lang: &str, source: &[u8]) -> Result<Vec<Range<usize>>, Error> {
+        let entry = self.entry(lang)?;
+        let Some(query) = &entry.folds else {
+            return Ok(Vec::new());
+        };
+        let tree = self.tree(entry, source)?;
+        let mut ranges = folds(query, &tree, source)
+            .into_iter()
+            .filter(|node| last_row(node) > node.start_position().row)
+            .map(|node| node.start_position().row + 1..last_row(&node) + 2)
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|r| (r.start, usize::MAX - r.end));
+        ranges.dedup();
+        Ok(ranges)
+    }
+}
+
+/// The nodes a folds query captures as `@fold`.
+pub(crate) fn folds<'t>(query: &Query, tree: &'t Tree, source: &[u8]) -> Vec<Node<'t>> {
+    let fold = query.capture_index_for_name("fold");
+    let mut cursor = QueryCursor::new();
+    cursor
+        .matches(query, tree.root_node(), source)
+        .flat_map(|m| {
+            m.captures
+                .iter()
+                .filter(|c| Some(c.index) == fold)
+                .map(|c| c.node)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The 0-based last row of a node, which is the row before when it ends at the start of
+/// a line.
+pub(crate) fn last_row(node: &Node<'_>) -> usize {
+    let end = node.end_position();
+    match end.column {
+        0 => end.row.saturating_sub(1),
+        _ => end.row,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::rust;
+
+    #[test]
+    fn fold_ranges() {
+        let mut languages = rust();
+        let source = b"fn outer() {\n    fn inner() {\n        let a = 1;\n    }\n}\n\nfn other() {}\n/* a\n   b */\n";
+        assert_eq!(languages.fold_ranges("rust", source).unwrap(), []);
+
+        let folds = include_str!("../queries/rust/folds.scm");
+        assert!(languages.set_folds_query("rs", folds).unwrap());
+        assert!(!languages.set_folds_query("cobol", folds).unwrap());
+        assert_eq!(
+            languages.fold_ranges("rust", source).unwrap(),
+            [1..6, 2..5, 8..10]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use toml::{Table, Value};
+
+use crate::{Color, Style, Theme, ThemeError, NAMES};
+
+/// The Helix scope of the capture names that Helix spells differently.
+///
+/// Other capture names are looked up as they are, so `keyword.function` and `diff.plus`
+/// need no entry. A scope falls back to its dotted prefixes, so a `keyword.control` key
+/// styles `conditional` and `repeat` unless they have a key of their own.
+const HELIX_SCOPES: &[(&str, &str)] = &[
+    ("boolean", "constant.builtin.boolean"),
+    ("character", "constant.character"),
+    ("conditional", "keyword.control.conditional"),
+    ("define", "keyword.directive"),
+    ("exception", "keyword.control.exception"),
+    ("field", "variable.other.member"),
+    ("float", "constant.numeric.float"),
+    ("include", "keyword.control.import"),
+    ("keyword.return", "keyword.control.return"),
+    ("method", "function.method"),
+    ("number", "constant.numeric"),
+    ("parameter", "variable.parameter"),
+    ("preproc", "keyword.directive"),
+    ("property", "variable.other.member"),
+    ("repeat", "keyword.control.repeat"),
+    ("storageclass", "keyword.storage.modifier"),
+    ("string.escape", "constant.character.escape"),
+    ("string.regex", "string.regexp"),
+    ("symbol", "string.special.symbol"),
+    ("tag.attribute", "attribute"),
+    ("text.diff.add", "diff.plus"),
+    ("text.diff.delete", "diff.minus"),
+    ("text.emphasis", "markup.italic"),
+    ("text.literal", "markup.raw"),
+    ("text.reference", "markup.link.text"),
+    ("text.strike", "markup.strikethrough"),
+    ("text.strong", "markup.bold"),
+    ("text.title", "markup.heading"),
+    ("text.uri", "markup.link.url"),
+    ("type.qualifier", "keyword.storage.modifier"),
+];
+
+/// The terminal colors Helix accepts by name, with xterm's values.
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("black", Color::rgb(0, 0, 0)),
+    ("red", Color::rgb(205, 0, 0)),
+    ("green", Color::rgb(0, 205, 0)),
+    ("yellow", Color::rgb(205, 205, 0)),
+    ("blue", Color::rgb(0, 0, 238)),
+    ("magenta", Color::rgb(205, 0, 205)),
+    ("cyan", Color::rgb(0, 205, 205)),
+    ("gray", Color::rgb(229, 229, 229)),
+    ("light-red", Color::rgb(255, 0, 0)),
+    ("light-green", Color::rgb(0, 255, 0)),
+    ("light-yellow", Color::rgb(255, 255, 0)),
+    ("light-blue", Color::rgb(92, 92, 255)),
+    ("light-magenta", Color::rgb(255, 0, 255)),
+    ("light-cyan", Color::rgb(0, 255, 255)),
+    ("light-gray", Color::rgb(127, 127, 127)),
+    ("white", Color::rgb(255, 255, 255)),
+];
+
+/// The keys that style the editor rather than the source, which are not warned about.
+fn is_editor(key: &str) -> bool {
+    key.starts_with("ui.")
+        || key.starts_with("diagnostic")
+        || key.starts_with("rainbow")
+        || matches!(key, "warning" | "info" | "hint")
+}
+
+/// Resolves a color through the palette, then the named terminal colors.
+fn color(palette: &HashMap<&str, &str>, value: &str) -> Result<Color, ThemeError> {
+    let value = palette.get(value).copied().unwrap_or(value);
+    if value.starts_with('#') {
+        return Ok(value.parse()?);
+    }
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, color)| *color)
+        .ok_or_else(|| ThemeError::Parse(format!("unknown color `{value}`")))
+}
+
+/// The style of a key, either a foreground color or a table of `fg`, `bg`, `modifiers`
+/// and `underline`.
+fn style(palette: &HashMap<&str, &str>, value: &Value) -> Result<Style, ThemeError> {
+    let mut style = Style::new();
+    let table = match value {
+        Value::String(fg) => {
+            style.fg = Some(color(palette, fg)?);
+            return Ok(style);
+        }
+        Value::Table(table) => table,
+        _ => return Err(ThemeError::Parse(format!("invalid style `{value:?}`"))),
+    };
+    let field = |key: &str| table.get(key).and_then(Value::as_str);
+    style.fg = field("fg").map(|c| color(palette, c)).transpose()?;
+    style.bg = field("bg").map(|c| color(palette, c)).transpose()?;
+    for modifier in table
+        .get("modifiers")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        match modifier {
+            "bold" => style.bold = true,
+            "italic" => style.italic = true,
+            "underlined" => style.underline = true,
+            "crossed_out" => style.strikethrough = true,
+            _ => {}
+        }
+    }
+    if table.get("underline").is_some() {
+        style.underline = true;
+    }
+    Ok(style)
+}
+
+impl Theme {
+    /// Loads a Helix TOML theme.
+    ///
+    /// See [`Theme::from_helix_toml_with_warnings`].
+    pub fn from_helix_toml(toml: &str) -> Result<Theme, ThemeError> {
+        Self::from_helix_toml_with_warnings(toml).map(|(theme, _)| theme)
+    }
+
+    /// Loads a Helix TOML theme, with a warning for each key it skips.
+    ///
+    /// Colors are hex colors, names from the `[palette]` table or terminal color names.
+    /// The `bold`, `italic`, `underlined` and `crossed_out` modifiers are kept. The
+    /// `ui.background` and `ui.text` keys become the defaults, `ui.cursorline.primary` the
+    /// line highlight, `ui.linenr` the gutter color and `ui.selection` the selection
+    /// background; other editor keys are dropped silently. Keys that style no
+    /// capture name, and `inherits`, which needs the parent theme, are skipped with a
+    /// warning.
+    pub fn from_helix_toml_with_warnings(toml: &str) -> Result<(Theme, Vec<String>), ThemeError> {
+        let table: Table = toml::from_str(toml).map_err(|e| ThemeError::Parse(e.to_string()))?;
+        let palette = table
+            .get("palette")
+            .and_then(Value::as_table)
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, value)| Some((name.as_str(), value.as_str()?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut warnings = Vec::new();
+        let mut editor = HashMap::new();
+        let mut scopes = HashMap::new();
+        for (key, value) in &table {
+            match key.as_str() {
+                "palette" => {}
+                "inherits" => warnings.push(format!(
+                    "`inherits = \"{}\"` is not supported",
+                    value.as_str().unwrap_or_default()
+                )),
+                key if is_editor(key) => {
+                    editor.insert(key, style(&palette, value)?);
+                }
+                key => {
+                    scopes.insert(key, style(&palette, value)?);
+                }
+            }
+        }
+
+        // The key styling a scope, falling back to its dotted prefixes.
+        let find = |mut scope: &str| loop {
+            if let Some((key, style)) = scopes.get_key_value(scope) {
+                return Some((*key, *style));
+            }
+            scope = &scope[..scope.rfind('.')?];
+        };
+        let mut used = Vec::new();
+        let mut styles = Vec::new();
+        for name in NAMES {
+            let scope = HELIX_SCOPES
+                .iter()
+                .find(|(n, _)| n == name)
+                .map_or(*name, |(_, scope)| scope);
+            if let Some((key, style)) = find(name).filter(|(key, _)| key == name).or(find(scope)) {
+                used.push(key);
+                styles.push((*name, style));
+            }
+        }
+        let mut unused = scopes
+            .keys()
+            .filter(|key| !used.contains(key))
+            .collect::<Vec<_>>();
+        unused.sort();
+        warnings.extend(unused.into_iter().map(|key| format!("unused key `{key}`")));
+
+        let mut theme = Theme::new(&styles);
+        theme.background = editor.get("ui.background").and_then(|s| s.bg);
+        theme.foreground = editor.get("ui.text").and_then(|s| s.fg);
+        theme.line_highlight = editor
+            .get("ui.cursorline.primary")
+            .or(editor.get("ui.cursorline"))
+            .and_then(|s| s.bg);
+        theme.gutter_fg = editor.get("ui.linenr").and_then(|s| s.fg);
+        theme.selection_bg = editor.get("ui.selection").and_then(|s| s.bg);
+        Ok((theme, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        let palette = HashMap::from([("red", "#e06c75"), ("bad", "e06c75")]);
+        assert_eq!(color(&palette, "red"), Ok(Color::rgb(224, 108, 117)));
+        assert_eq!(color(&palette, "#fff"), Ok(Color::rgb(255, 255, 255)));
+        assert_eq!(color(&palette, "light-cyan"), Ok(Color::rgb(0, 255, 255)));
+        assert!(color(&palette, "bad").is_err());
+        assert!(color(&palette, "orange").is_err());
+    }
+
+    #[test]
+    fn onedark() {
+        let (theme, warnings) =
+            Theme::from_helix_toml_with_warnings(include_str!("../fixtures/onedark.toml")).unwrap();
+        assert_eq!(theme.background, Some(Color::rgb(0x28, 0x2c, 0x34)));
+        assert_eq!(theme.foreground, Some(Color::rgb(0xab, 0xb2, 0xbf)));
+        assert_eq!(theme.line_highlight, Some(Color::rgb(0x2c, 0x32, 0x3c)));
+        assert_eq!(theme.gutter_fg, Some(Color::rgb(0x4b, 0x52, 0x63)));
+        assert_eq!(theme.selection_bg, Some(Color::rgb(0x3b, 0x40, 0x48)));
+
+        let fg = |name| theme.resolve(name).fg;
+        assert_eq!(fg("keyword"), Some(Color::rgb(0xe0, 0x6c, 0x75)));
+        assert_eq!(fg("conditional"), Some(Color::rgb(0xc6, 0x78, 0xdd)));
+        assert_eq!(fg("include"), Some(Color::rgb(0xe0, 0x6c, 0x75)));
+        assert_eq!(fg("string"), Some(Color::rgb(0x98, 0xc3, 0x79)));
+        assert_eq!(fg("number"), Some(Color::rgb(0xd1, 0x9a, 0x66)));
+        assert_eq!(fg("diff.plus"), Some(Color::rgb(0x98, 0xc3, 0x79)));
+        assert!(theme.resolve("comment").italic);
+        assert!(theme.resolve("text.strong").bold);
+        assert!(theme.resolve("text.strike").strikethrough);
+        assert!(theme.resolve("text.uri").underline);
+
+        assert_eq!(warnings, ["unused key `special`"]);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Theme::from_helix_toml("keyword = ").is_err());
+        assert!(Theme::from_helix_toml("keyword = \"orange\"").is_err());
+    }
+}
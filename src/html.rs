@@ -0,0 +1,1431 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Write as _},
+    ops::Range,
+};
+
+use tree_sitter_highlight::{Highlight, HighlightEvent};
+
+use crate::{width::char_width, Error, HtmlContext, LineHeat, RenderOptions, ZeroWidth};
+
+/// Renders highlight events to HTML lines.
+///
+/// The output matches `tree_sitter_highlight::HtmlRenderer`: text is escaped, carriage
+/// returns are dropped and open spans are closed and re-opened at every line break.
+/// On top of that, search matches are wrapped in `<mark>` as the innermost element and
+/// lines can be hard wrapped at a display column.
+pub(crate) struct HtmlWriter<'a> {
+    pub(crate) html: String,
+    pub(crate) line_offsets: Vec<usize>,
+    /// Whether each line continues the previous one after a hard wrap.
+    continuations: Vec<bool>,
+    classes: Cow<'a, [String]>,
+    marks: Vec<Range<usize>>,
+    mark_open: bool,
+    wrap_column: Option<usize>,
+    column: usize,
+    strict: bool,
+    /// The attributes of highlights without a class.
+    unknown: String,
+    /// The element of highlights.
+    element: String,
+    show_whitespace: bool,
+    show_zero_width: bool,
+    /// Whether source text is escaped.
+    escape: bool,
+    /// The indent width of `indent-guide` spans.
+    indent_guides: Option<usize>,
+    /// Whether the text so far on the line is whitespace.
+    leading: bool,
+    guide_open: bool,
+    /// The columns of the current indent level so far.
+    guide_columns: usize,
+    /// The open highlights.
+    highlights: Vec<Highlight>,
+    /// The number of source lines drained so far.
+    drained: usize,
+    /// The end of the source written so far.
+    written: usize,
+}
+
+/// The output buffers of an [`HtmlWriter`], kept between renders so that they are not
+/// allocated again.
+#[derive(Default)]
+pub(crate) struct Buffers {
+    html: String,
+    line_offsets: Vec<usize>,
+    continuations: Vec<bool>,
+}
+
+#[cfg(test)]
+impl Buffers {
+    /// The allocations of the buffers, which stay the same while they are reused.
+    pub(crate) fn allocations(&self) -> [(*const u8, usize); 3] {
+        [
+            (self.html.as_ptr(), self.html.capacity()),
+            (
+                self.line_offsets.as_ptr().cast(),
+                self.line_offsets.capacity(),
+            ),
+            (
+                self.continuations.as_ptr().cast(),
+                self.continuations.capacity(),
+            ),
+        ]
+    }
+}
+
+/// A rendered line.
+pub(crate) struct Line<'a> {
+    /// 1-based source line number.
+    pub(crate) number: usize,
+    /// Whether this line continues the previous one after a hard wrap.
+    pub(crate) continuation: bool,
+    pub(crate) html: &'a str,
+}
+
+impl<'a> HtmlWriter<'a> {
+    pub(crate) fn new(classes: impl Into<Cow<'a, [String]>>) -> Self {
+        Self {
+            html: String::new(),
+            line_offsets: vec![0],
+            continuations: vec![false],
+            classes: classes.into(),
+            marks: Vec::new(),
+            mark_open: false,
+            wrap_column: None,
+            column: 0,
+            strict: false,
+            unknown: String::new(),
+            element: String::from("span"),
+            show_whitespace: false,
+            show_zero_width: false,
+            escape: true,
+            indent_guides: None,
+            leading: true,
+            guide_open: false,
+            guide_columns: 0,
+            highlights: Vec::new(),
+            drained: 0,
+            written: 0,
+        }
+    }
+
+    /// Writes into the buffers of an earlier writer, see [`HtmlWriter::recycle`]. They are
+    /// cleared first, so whatever a failed render left in them is gone.
+    pub(crate) fn buffers(mut self, buffers: &mut Buffers) -> Self {
+        let Buffers {
+            mut html,
+            mut line_offsets,
+            mut continuations,
+        } = std::mem::take(buffers);
+        html.clear();
+        line_offsets.clear();
+        line_offsets.push(0);
+        continuations.clear();
+        continuations.push(false);
+        (self.html, self.line_offsets, self.continuations) = (html, line_offsets, continuations);
+        self
+    }
+
+    /// Hands the buffers back for the next writer, see [`HtmlWriter::buffers`].
+    pub(crate) fn recycle(self, buffers: &mut Buffers) {
+        *buffers = Buffers {
+            html: self.html,
+            line_offsets: self.line_offsets,
+            continuations: self.continuations,
+        };
+    }
+
+    /// Sorted, non-overlapping byte ranges wrapped in `<mark>`.
+    pub(crate) fn marks(mut self, marks: Vec<Range<usize>>) -> Self {
+        self.marks = marks;
+        self
+    }
+
+    /// Hard wraps lines wider than `column` display columns.
+    pub(crate) fn wrap(mut self, column: Option<usize>) -> Self {
+        self.wrap_column = column;
+        self
+    }
+
+    /// Fails on highlights without a class instead of emitting a bare `<span>`.
+    pub(crate) fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Gives highlights without a class this class instead of a bare `<span>`.
+    pub(crate) fn unknown_class(mut self, class: Option<&str>) -> Self {
+        self.unknown = class.map(crate::class::attr).unwrap_or_default();
+        self
+    }
+
+    /// Uses this element for highlights instead of `<span>`, unless it is not a valid
+    /// element name.
+    pub(crate) fn token_element(mut self, element: Option<&str>) -> Self {
+        if let Some(element) = element.filter(|e| is_element_name(e)) {
+            self.element = element.to_string();
+        }
+        self
+    }
+
+    /// Replaces spaces and tabs with visible `whitespace` marker spans.
+    pub(crate) fn show_whitespace(mut self, show: bool) -> Self {
+        self.show_whitespace = show;
+        self
+    }
+
+    /// Replaces zero-width characters with visible `zero-width` marker spans.
+    pub(crate) fn zero_width(mut self, zero_width: ZeroWidth) -> Self {
+        self.show_zero_width = zero_width == ZeroWidth::Show;
+        self
+    }
+
+    /// Writes source text as is when `escape` is false, see [`RenderOptions::escape`].
+    pub(crate) fn escape(mut self, escape: bool) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// Wraps each `width` columns of leading whitespace in an `indent-guide` span.
+    pub(crate) fn indent_guides(mut self, width: Option<usize>) -> Self {
+        self.indent_guides = width.filter(|w| *w > 0);
+        self
+    }
+
+    pub(crate) fn render(
+        &mut self,
+        events: impl Iterator<Item = Result<HighlightEvent, tree_sitter_highlight::Error>>,
+        source: &[u8],
+    ) -> Result<(), Error> {
+        for event in events {
+            self.event(event?, source)?;
+        }
+        self.finish();
+        Ok(())
+    }
+
+    pub(crate) fn event(&mut self, event: HighlightEvent, source: &[u8]) -> Result<(), Error> {
+        match event {
+            HighlightEvent::HighlightStart(h) => {
+                if self.strict && h.0 >= self.classes.len() {
+                    return Err(Error::UnmappedIndex(h.0));
+                }
+                self.highlights.push(h);
+                self.start_highlight(h);
+            }
+            HighlightEvent::HighlightEnd => {
+                self.highlights.pop();
+                self.end_highlight();
+            }
+            HighlightEvent::Source { start, end } => {
+                // A range ending inside a character takes the rest of it, and the next
+                // range starts after it, so characters are never split in two.
+                let start = start.max(self.written);
+                let mut end = end.min(source.len());
+                while source.get(end).is_some_and(|b| b & 0xc0 == 0x80) {
+                    end += 1;
+                }
+                if start >= end {
+                    return Ok(());
+                }
+                self.written = end;
+                let highlights = std::mem::take(&mut self.highlights);
+                self.add_source(source, start..end, &highlights);
+                self.highlights = highlights;
+            }
+        }
+        Ok(())
+    }
+
+    /// Terminates the last line, after the last event.
+    pub(crate) fn finish(&mut self) {
+        self.close_guide();
+        // An empty buffer after draining means the last drained line was terminated.
+        if !(self.html.ends_with('\n') || self.html.is_empty() && self.drained > 0) {
+            self.html.push('\n');
+        }
+        if self.line_offsets.last() == Some(&self.html.len()) {
+            self.line_offsets.pop();
+            self.continuations.pop();
+        }
+    }
+
+    pub(crate) fn lines(&self) -> impl Iterator<Item = Line<'_>> {
+        let mut number = self.drained;
+        self.line_offsets.iter().enumerate().map(move |(i, start)| {
+            let end = self
+                .line_offsets
+                .get(i + 1)
+                .copied()
+                .unwrap_or(self.html.len());
+            let continuation = self.continuations[i];
+            if !continuation {
+                number += 1;
+            }
+            Line {
+                number,
+                continuation,
+                html: &self.html[*start..end],
+            }
+        })
+    }
+
+    /// Calls `f` with the completed lines and removes them, keeping the line in progress.
+    pub(crate) fn drain_lines(&mut self, mut f: impl FnMut(Line<'_>)) {
+        let complete = self.line_offsets.len() - 1;
+        if complete == 0 {
+            return;
+        }
+        let mut drained = 0;
+        for line in self.lines().take(complete) {
+            drained += usize::from(!line.continuation);
+            f(line);
+        }
+        self.drained += drained;
+
+        let end = self.line_offsets[complete];
+        self.html.drain(..end);
+        self.line_offsets.drain(..complete);
+        self.line_offsets
+            .iter_mut()
+            .for_each(|offset| *offset -= end);
+        self.continuations.drain(..complete);
+    }
+
+    fn start_highlight(&mut self, h: Highlight) {
+        self.close_guide();
+        let attrs = self.classes.get(h.0).unwrap_or(&self.unknown);
+        self.html.push('<');
+        self.html.push_str(&self.element);
+        if !attrs.is_empty() {
+            self.html.push(' ');
+            self.html.push_str(attrs);
+        }
+        self.html.push('>');
+    }
+
+    fn end_highlight(&mut self) {
+        self.close_guide();
+        self.html.push_str("</");
+        self.html.push_str(&self.element);
+        self.html.push('>');
+    }
+
+    /// Splits a source range at mark boundaries.
+    fn add_source(&mut self, source: &[u8], range: Range<usize>, highlights: &[Highlight]) {
+        let mut start = range.start;
+        let first = self.marks.partition_point(|m| m.end <= start);
+        for i in first..self.marks.len() {
+            let mark = self.marks[i].clone();
+            if mark.start >= range.end {
+                break;
+            }
+            if start < mark.start {
+                self.add_text(&source[start..mark.start], highlights, false);
+                start = mark.start;
+            }
+            let end = mark.end.min(range.end);
+            self.add_text(&source[start..end], highlights, true);
+            start = end;
+        }
+        if start < range.end {
+            self.add_text(&source[start..range.end], highlights, false);
+        }
+    }
+
+    fn add_text(&mut self, text: &[u8], highlights: &[Highlight], marked: bool) {
+        for c in String::from_utf8_lossy(text).chars() {
+            match c {
+                '\r' => {}
+                '\n' => self.break_line(highlights, false),
+                c => {
+                    let shown = self.show_zero_width && is_zero_width(c);
+                    // The code point in angle brackets.
+                    let width = if shown { 6 } else { char_width(c) };
+                    if self
+                        .wrap_column
+                        .is_some_and(|wrap| self.column > 0 && self.column + width > wrap)
+                    {
+                        self.break_line(highlights, true);
+                    }
+                    if marked && !self.mark_open {
+                        self.close_guide();
+                        self.html.push_str("<mark>");
+                        self.mark_open = true;
+                    }
+                    self.column += width;
+                    self.open_guide(c);
+                    match c {
+                        ' ' if self.show_whitespace => {
+                            self.html.push_str("<span class=whitespace>·</span>")
+                        }
+                        '\t' if self.show_whitespace => {
+                            self.html.push_str("<span class=whitespace>→</span>")
+                        }
+                        c if shown => {
+                            let _ = write!(
+                                self.html,
+                                "<span class=zero-width>&lt;{:04x}&gt;</span>",
+                                c as u32
+                            );
+                        }
+                        c if self.escape => escape(&mut self.html, c),
+                        c => self.html.push(c),
+                    }
+                    self.end_guide(c);
+                }
+            }
+        }
+        self.close_mark();
+    }
+
+    /// Opens an indent guide before leading whitespace, or ends the leading whitespace.
+    fn open_guide(&mut self, c: char) {
+        if !self.leading || self.indent_guides.is_none() {
+            return;
+        }
+        match c {
+            ' ' | '\t' => {
+                // A tab is an indent level of its own.
+                if c == '\t' && self.guide_columns > 0 {
+                    self.close_guide();
+                    self.guide_columns = 0;
+                }
+                if !self.guide_open {
+                    self.html.push_str("<span class=indent-guide>");
+                    self.guide_open = true;
+                }
+            }
+            _ => {
+                self.leading = false;
+                self.close_guide();
+            }
+        }
+    }
+
+    /// Closes the indent guide after whitespace that completes an indent level.
+    fn end_guide(&mut self, c: char) {
+        let Some(indent) = self.indent_guides.filter(|_| self.leading) else {
+            return;
+        };
+        self.guide_columns += if c == '\t' { indent } else { 1 };
+        if self.guide_columns >= indent {
+            self.close_guide();
+            self.guide_columns = 0;
+        }
+    }
+
+    fn close_guide(&mut self) {
+        if self.guide_open {
+            self.html.push_str("</span>");
+            self.guide_open = false;
+        }
+    }
+
+    fn close_mark(&mut self) {
+        if self.mark_open {
+            self.close_guide();
+            self.html.push_str("</mark>");
+            self.mark_open = false;
+        }
+    }
+
+    /// Closes the open elements, ends the line and re-opens the highlights on the next one.
+    fn break_line(&mut self, highlights: &[Highlight], continuation: bool) {
+        self.close_mark();
+        self.close_guide();
+        self.guide_columns = 0;
+        self.leading = !continuation;
+        highlights.iter().for_each(|_| self.end_highlight());
+        self.html.push('\n');
+        self.line_offsets.push(self.html.len());
+        self.continuations.push(continuation);
+        self.column = 0;
+        highlights.iter().for_each(|h| self.start_highlight(*h));
+    }
+}
+
+/// Whether `name` can be written as an element name, such as `span` or a custom element
+/// like `hl-token`.
+fn is_element_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Writes the opening tag of a line span, quoting the class list when needed.
+pub(crate) fn open_line<S: AsRef<str>>(html: &mut String, classes: &[S], attrs: &[(&str, String)]) {
+    html.push_str("<span class=");
+    match classes {
+        [class] => html.push_str(class.as_ref()),
+        classes => {
+            html.push('"');
+            for (i, class) in classes.iter().enumerate() {
+                if i > 0 {
+                    html.push(' ');
+                }
+                html.push_str(class.as_ref());
+            }
+            html.push('"');
+        }
+    }
+    for (name, value) in attrs {
+        html.push(' ');
+        html.push_str(name);
+        html.push_str("=\"");
+        value.chars().for_each(|c| escape(html, c));
+        html.push('"');
+    }
+    html.push('>');
+}
+
+/// Wraps rendered lines in their line spans, per the [`RenderOptions`].
+pub(crate) struct Rows<'o> {
+    bare: bool,
+    options: &'o RenderOptions,
+    heat: Option<(&'o LineHeat, (f64, f64))>,
+    conflicts: Vec<Option<&'static str>>,
+}
+
+impl<'o> Rows<'o> {
+    pub(crate) fn new(source: &[u8], options: &'o RenderOptions) -> Self {
+        Self {
+            bare: options.bare_lines,
+            options,
+            heat: options.line_heat.as_ref().map(|heat| (heat, heat.bounds())),
+            conflicts: if options.mark_conflicts {
+                conflict_markers(source)
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Appends a line, in its line span unless the lines are bare, ending with the
+    /// [`RenderOptions::line_separator`] instead of its line break.
+    pub(crate) fn push(&self, s: &mut String, line: &Line<'_>) {
+        let (html, separator) = match line.html.strip_suffix('\n') {
+            Some(html) => (html, self.options.line_separator.as_str()),
+            None => (line.html, ""),
+        };
+        if self.bare {
+            s.push_str(html);
+            s.push_str(separator);
+            return;
+        }
+
+        let mut classes = vec![Cow::Borrowed("line")];
+        let mut attrs = Vec::new();
+        if line.continuation {
+            classes.push("line-continuation".into());
+        } else if let Some((bucket, value)) = self
+            .heat
+            .and_then(|(heat, bounds)| heat.lookup(line.number, bounds))
+        {
+            classes.push(format!("heat-{bucket}").into());
+            attrs.push(("data-value", value.to_string()));
+        }
+        if let Some(class) = self.conflicts.get(line.number - 1).copied().flatten() {
+            classes.push(class.into());
+        }
+        if self.options.is_highlighted(line.number) {
+            classes.push("highlighted".into());
+        }
+        if self.options.start_line > 0 && !line.continuation {
+            attrs.push((
+                "data-line",
+                (self.options.start_line + line.number - 1).to_string(),
+            ));
+        }
+        open_line(s, &classes, &attrs);
+        s.push_str(html);
+        if let Some(note) = self
+            .options
+            .annotations
+            .get(&line.number)
+            .filter(|_| !line.continuation)
+        {
+            s.push_str("<span class=line-note>");
+            note.chars().for_each(|c| escape(s, c));
+            s.push_str("</span>");
+        }
+        s.push_str(separator);
+        s.push_str("</span>");
+    }
+}
+
+/// Opens and closes the wrappers of collapsed line ranges.
+pub(crate) struct Collapse {
+    ranges: Vec<Range<usize>>,
+    next: usize,
+    open: bool,
+    details: bool,
+    /// The number of source lines.
+    count: usize,
+}
+
+impl Collapse {
+    pub(crate) fn new(options: &RenderOptions, count: usize) -> Self {
+        Self {
+            ranges: options.collapsed_ranges(),
+            next: 0,
+            open: false,
+            details: options.collapse_details,
+            count,
+        }
+    }
+
+    fn tag(&self) -> &'static str {
+        if self.details {
+            "details"
+        } else {
+            "span"
+        }
+    }
+
+    /// Writes the wrapper tags due before source line `number`.
+    pub(crate) fn line(&mut self, out: &mut impl fmt::Write, number: usize) -> fmt::Result {
+        if self.open && self.ranges.get(self.next).is_some_and(|r| number >= r.end) {
+            self.close(out)?;
+            self.next += 1;
+        }
+        // Ranges ending before the line never open.
+        while !self.open && self.ranges.get(self.next).is_some_and(|r| r.end <= number) {
+            self.next += 1;
+        }
+        if let Some(range) = self
+            .ranges
+            .get(self.next)
+            .filter(|r| !self.open && r.contains(&number))
+        {
+            let lines = range.end.min(self.count + 1) - number;
+            write!(out, "<{} class=line-collapsed>", self.tag())?;
+            if self.details {
+                write!(out, "<summary>{lines} lines</summary>")?;
+            }
+            self.open = true;
+        }
+        Ok(())
+    }
+
+    /// Closes the open wrapper, after the last line.
+    pub(crate) fn close(&mut self, out: &mut impl fmt::Write) -> fmt::Result {
+        if self.open {
+            write!(out, "</{}>", self.tag())?;
+            self.open = false;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a character is a zero-width space or joiner, or a byte order mark.
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}'
+    )
+}
+
+/// The source as highlighted: without a leading byte order mark, and without
+/// zero-width characters if they are removed.
+pub(crate) fn normalize_source(source: &[u8], zero_width: ZeroWidth) -> Cow<'_, [u8]> {
+    let source = source.strip_prefix(b"\xef\xbb\xbf").unwrap_or(source);
+    if zero_width != ZeroWidth::Remove {
+        return Cow::Borrowed(source);
+    }
+    // Every zero-width character is three bytes long in UTF-8.
+    let at = |i: usize| {
+        source
+            .get(i..i + 3)
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .and_then(|s| s.chars().next())
+            .is_some_and(is_zero_width)
+    };
+    if !(0..source.len()).any(at) {
+        return Cow::Borrowed(source);
+    }
+    let mut normalized = Vec::with_capacity(source.len());
+    let mut i = 0;
+    while i < source.len() {
+        if at(i) {
+            i += 3;
+        } else {
+            normalized.push(source[i]);
+            i += 1;
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+/// Escapes a character for HTML text and attribute values.
+pub(crate) fn escape(html: &mut String, c: char) {
+    match c {
+        '>' => html.push_str("&gt;"),
+        '<' => html.push_str("&lt;"),
+        '&' => html.push_str("&amp;"),
+        '\'' => html.push_str("&#39;"),
+        '"' => html.push_str("&quot;"),
+        c => html.push(c),
+    }
+}
+
+/// Writes rendered markup escaped for the context it is embedded in.
+pub(crate) struct Embed<'w, W> {
+    pub(crate) out: &'w mut W,
+    pub(crate) context: HtmlContext,
+}
+
+impl<W: fmt::Write> fmt::Write for Embed<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.context {
+            HtmlContext::Normal => self.out.write_str(s),
+            HtmlContext::Attribute => s.chars().try_for_each(|c| match c {
+                '>' => self.out.write_str("&gt;"),
+                '<' => self.out.write_str("&lt;"),
+                '&' => self.out.write_str("&amp;"),
+                '\'' => self.out.write_str("&#39;"),
+                '"' => self.out.write_str("&quot;"),
+                c => self.out.write_char(c),
+            }),
+            HtmlContext::Template => {
+                let mut parts = s.split('&');
+                self.out.write_str(parts.next().unwrap_or_default())?;
+                parts.try_for_each(|part| {
+                    self.out.write_str("&amp;")?;
+                    self.out.write_str(part)
+                })
+            }
+        }
+    }
+}
+
+/// Escapes rendered markup for the context it is embedded in.
+pub(crate) fn embed(context: HtmlContext, html: String) -> String {
+    if context == HtmlContext::Normal {
+        return html;
+    }
+    let mut s = String::with_capacity(html.len() + html.len() / 4);
+    let _ = fmt::Write::write_str(
+        &mut Embed {
+            out: &mut s,
+            context,
+        },
+        &html,
+    );
+    s
+}
+
+/// The visible text of rendered HTML, with the tags stripped and character references
+/// decoded.
+///
+/// Rendered with the default options, this is the source with carriage returns dropped
+/// and a line break added after the last line, so tests can check that highlighting
+/// neither drops nor duplicates characters.
+pub fn extract_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode(&rest[..start]));
+        rest = rest[start..]
+            .find('>')
+            .map_or("", |end| &rest[start + end + 1..]);
+    }
+    text.push_str(&decode(rest));
+    text
+}
+
+/// Canonicalizes HTML for snapshot comparisons.
+///
+/// Tag and attribute names are lowercased, attributes are sorted by name and double
+/// quoted, whitespace inside tags is collapsed, the classes of a `class` attribute are
+/// sorted, and character references are decoded and re-escaped consistently. Text
+/// whitespace is kept, since it is significant in code.
+pub fn normalize_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('<') {
+        push_text(&mut out, &rest[..i]);
+        rest = &rest[i..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").map_or(comment.len(), |i| i + 3);
+            out.push_str("<!--");
+            out.push_str(&comment[..end]);
+            rest = &comment[end..];
+            continue;
+        }
+        let (tag, tail) = split_tag(&rest[1..]);
+        push_tag(&mut out, tag);
+        rest = tail;
+    }
+    push_text(&mut out, rest);
+    out
+}
+
+/// Splits at the `>` closing a tag, skipping quoted attribute values.
+fn split_tag(s: &str) -> (&str, &str) {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return (&s[..i], &s[i + 1..]),
+            _ => {}
+        }
+    }
+    (s, "")
+}
+
+fn push_tag(out: &mut String, tag: &str) {
+    let tag = tag.trim();
+    let (tag, closing) = match tag.strip_suffix('/') {
+        Some(tag) => (tag.trim_end(), true),
+        None => (tag, false),
+    };
+    let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+    out.push('<');
+    out.push_str(&tag[..name_end].to_ascii_lowercase());
+
+    let mut attrs = Vec::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let name = rest[..end].to_ascii_lowercase();
+        rest = rest[end..].trim_start();
+        let mut value = None;
+        if let Some(tail) = rest.strip_prefix('=') {
+            let tail = tail.trim_start();
+            let (raw, tail) = match tail.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let end = tail[1..].find(q).map_or(tail.len(), |i| i + 1);
+                    (&tail[1..end], tail.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = tail.find(char::is_whitespace).unwrap_or(tail.len());
+                    tail.split_at(end)
+                }
+            };
+            let mut decoded = decode(raw);
+            if name == "class" {
+                let mut classes = decoded.split_whitespace().collect::<Vec<_>>();
+                classes.sort_unstable();
+                decoded = classes.join(" ");
+            }
+            value = Some(decoded);
+            rest = tail;
+        }
+        attrs.push((name, value));
+        rest = rest.trim_start();
+    }
+    attrs.sort();
+    for (name, value) in attrs {
+        out.push(' ');
+        out.push_str(&name);
+        if let Some(value) = value {
+            out.push_str("=\"");
+            value.chars().for_each(|c| escape(out, c));
+            out.push('"');
+        }
+    }
+    if closing {
+        out.push_str(" /");
+    }
+    out.push('>');
+}
+
+fn push_text(out: &mut String, text: &str) {
+    decode(text).chars().for_each(|c| escape(out, c));
+}
+
+/// Decodes the character references produced by HTML escapers.
+fn decode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                entity => {
+                    let n = entity.strip_prefix('#')?;
+                    let n = match n.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => n.parse().ok()?,
+                    };
+                    char::from_u32(n)?
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The class of each source line that is a git conflict marker.
+pub(crate) fn conflict_markers(source: &[u8]) -> Vec<Option<&'static str>> {
+    source
+        .split(|b| *b == b'\n')
+        .map(|line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let class = match line.get(..7)? {
+                b"<<<<<<<" => "conflict-ours",
+                b"|||||||" => "conflict-base",
+                b"=======" => "conflict-sep",
+                b">>>>>>>" => "conflict-theirs",
+                _ => return None,
+            };
+            match line.get(7) {
+                None => Some(class),
+                Some(b' ') if class != "conflict-sep" => Some(class),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Finds case-insensitive, non-overlapping occurrences of the terms.
+///
+/// Characters are compared by their lowercase mappings, so `É` matches `é`, and matches
+/// start and end on characters of the source. At each position the longest matching
+/// term wins.
+pub(crate) fn find_terms<S: AsRef<str>>(source: &[u8], terms: &[S]) -> Vec<Range<usize>> {
+    let mut terms = terms
+        .iter()
+        .map(|t| {
+            t.as_ref()
+                .chars()
+                .flat_map(char::to_lowercase)
+                .collect::<Vec<_>>()
+        })
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+    terms.sort_by_key(|t| std::cmp::Reverse(t.len()));
+
+    // The lowercased source, each character with the offset of the source character it
+    // comes from and whether it is the first of its mapping. Invalid UTF-8 folds to U+FFFD.
+    let mut folded = Vec::with_capacity(source.len());
+    let mut offset = 0;
+    for chunk in source.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            folded.extend(
+                c.to_lowercase()
+                    .enumerate()
+                    .map(|(i, lower)| (lower, offset, i == 0)),
+            );
+            offset += c.len_utf8();
+        }
+        if !chunk.invalid().is_empty() {
+            folded.push((char::REPLACEMENT_CHARACTER, offset, true));
+            offset += chunk.invalid().len();
+        }
+    }
+    // The source offset a match ending before `i` ends at, if it ends on a character.
+    let end = |i: usize| {
+        folded
+            .get(i)
+            .map_or(Some(source.len()), |&(_, offset, first)| {
+                first.then_some(offset)
+            })
+    };
+
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < folded.len() {
+        let (_, start, first) = folded[i];
+        let found = first
+            .then(|| {
+                terms.iter().find_map(|t| {
+                    let chars = folded.get(i..i + t.len())?;
+                    if chars.iter().map(|f| f.0).eq(t.iter().copied()) {
+                        Some((t.len(), end(i + t.len())?))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .flatten();
+        match found {
+            Some((len, end)) => {
+                ranges.push(start..end);
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, LineSeparator, RenderOptions};
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_html(
+                "<SPAN  data-value=3 class=\"line heat-1\"><span class='keyword'>let</span> &apos;&#x3c;&#62;</span>"
+            ),
+            normalize_html(
+                "<span class=\"heat-1 line\" data-value=\"3\"><span class=keyword >let</span> &#39;&lt;&gt;</span>"
+            )
+        );
+        assert_eq!(
+            normalize_html("<a href='x\"y' b>&amp;&unknown; <br/> <!-- <x> --></A>"),
+            "<a b href=\"x&quot;y\">&amp;&amp;unknown; <br /> <!-- <x> --></a>"
+        );
+        assert_ne!(
+            normalize_html("<span class=a>x</span>"),
+            normalize_html("<span class=a>x </span>")
+        );
+
+        let html = rust().render("rust", b"let a = 1;").unwrap();
+        assert_eq!(
+            normalize_html(&normalize_html(&html)),
+            normalize_html(&html)
+        );
+    }
+
+    #[test]
+    fn reused_buffers() {
+        let source = b"a\nbc\n";
+        let events = || {
+            [
+                HighlightEvent::HighlightStart(Highlight(0)),
+                HighlightEvent::Source { start: 0, end: 4 },
+                HighlightEvent::HighlightEnd,
+                HighlightEvent::Source { start: 4, end: 5 },
+            ]
+            .into_iter()
+            .map(Ok)
+        };
+        let classes = [String::from("class=a")];
+        let render = |buffers: &mut Buffers| {
+            let mut writer = HtmlWriter::new(&classes[..]).buffers(buffers);
+            writer.render(events(), source).unwrap();
+            let html = writer.lines().map(|line| line.html).collect::<String>();
+            writer.recycle(buffers);
+            html
+        };
+
+        let mut buffers = Buffers::default();
+        let html = render(&mut buffers);
+        let allocations = buffers.allocations();
+        for _ in 0..3 {
+            assert_eq!(render(&mut buffers), html);
+            assert_eq!(buffers.allocations(), allocations);
+        }
+
+        // A failed render leaves nothing behind for the next one.
+        let mut writer = HtmlWriter::new(&classes[..])
+            .strict(true)
+            .buffers(&mut buffers);
+        let failing = [
+            HighlightEvent::Source { start: 0, end: 3 },
+            HighlightEvent::HighlightStart(Highlight(7)),
+        ];
+        assert!(writer.render(failing.into_iter().map(Ok), source).is_err());
+        writer.recycle(&mut buffers);
+        assert_eq!(render(&mut buffers), html);
+    }
+
+    #[test]
+    fn split_characters() {
+        let source = "漢字 😀 é".as_bytes();
+        let mut writer = HtmlWriter::new(vec![String::from("class=a")]);
+        // The ranges end inside characters, and the one before covers the last.
+        let events = [
+            (0, 1),
+            (1, 4),
+            (4, 8),
+            (8, 10),
+            (10, 13),
+            (13, source.len()),
+        ]
+        .into_iter()
+        .flat_map(|(start, end)| {
+            [
+                HighlightEvent::HighlightStart(Highlight(0)),
+                HighlightEvent::Source { start, end },
+                HighlightEvent::HighlightEnd,
+            ]
+        })
+        .map(Ok);
+        writer.render(events, source).unwrap();
+        let html = writer.lines().map(|line| line.html).collect::<String>();
+        assert!(!html.contains('\u{fffd}'), "{html}");
+        assert_eq!(super::extract_text(&html), "漢字 😀 é\n");
+
+        let languages = rust();
+        let source = "let s = \"漢字😀\"; // 注釈 é\nfn 関数() {}\n";
+        let html = languages.render("rust", source.as_bytes()).unwrap();
+        assert_eq!(super::extract_text(&html), source);
+    }
+
+    #[test]
+    fn extract_text() {
+        assert_eq!(
+            super::extract_text(
+                "<pre class=language-x><code><span class=line>a &lt; <b>&#39;b&#39;</b> &amp;amp;\n</span></code></pre>"
+            ),
+            "a < 'b' &amp;\n"
+        );
+        assert_eq!(super::extract_text("a <b"), "a ");
+
+        let languages = rust();
+        for source in [
+            "fn main() {}\n",
+            "let s = \"<a href='x'>&amp;</a>\";\n",
+            "/* 日本語 😀\n   nested */\n\n",
+            "let r = 'a' < 'b' && c > d;\n",
+            "#[derive(Debug)]\nstruct A<'a>(&'a str);\n",
+        ] {
+            let html = languages.render_str("rust", source).unwrap();
+            assert_eq!(super::extract_text(&html), source);
+        }
+        let html = languages.render("rust", b"let a;\r\nlet b;").unwrap();
+        assert_eq!(super::extract_text(&html), "let a;\nlet b;\n");
+    }
+
+    #[test]
+    fn conflicts() {
+        assert_eq!(
+            conflict_markers(b"<<<<<<< HEAD\r\na\n|||||||\n=======\n========\n>>>>>>> b\n>>>>>>>>"),
+            [
+                Some("conflict-ours"),
+                None,
+                Some("conflict-base"),
+                Some("conflict-sep"),
+                None,
+                Some("conflict-theirs"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn terms() {
+        assert_eq!(
+            find_terms(b"Foo foo FOO", &["foo"]),
+            vec![0..3, 4..7, 8..11]
+        );
+        assert_eq!(find_terms(b"aaaa", &["aa"]), vec![0..2, 2..4]);
+        assert_eq!(find_terms(b"foobar", &["foo", "foobar"]), vec![0..6]);
+        assert_eq!(find_terms(b"foo", &[""]), vec![]);
+        assert_eq!(
+            find_terms("caf\u{e9} CAF\u{c9}".as_bytes(), &["caf\u{e9}"]),
+            vec![0..5, 6..11]
+        );
+        assert_eq!(
+            find_terms("\u{c9}T\u{c9}".as_bytes(), &["\u{e9}t\u{e9}"]),
+            vec![0..5]
+        );
+        // `İ` lowercases to `i` and a combining dot, so `i` would end inside it.
+        assert_eq!(find_terms("\u{130}".as_bytes(), &["i"]), vec![]);
+        assert_eq!(
+            find_terms("\u{130}x".as_bytes(), &["i\u{307}X"]),
+            vec![0..3]
+        );
+        assert_eq!(find_terms(b"\xffFoo", &["foo"]), vec![1..4]);
+    }
+
+    #[test]
+    fn search() {
+        let languages = rust();
+        let html = languages
+            .render_with_search("rust", br#"let s = "Hello Viz";"#, &["viz"])
+            .unwrap();
+        assert!(html.contains("<span class=string>&quot;Hello <mark>Viz</mark>&quot;</span>"));
+
+        let html = languages
+            .render_with_search("rust", b"let hello = 1;", &["LET H"])
+            .unwrap();
+        assert!(html.contains(
+            "<span class=keyword><mark>let</mark></span><mark> </mark><span class=variable><mark>h</mark>ello</span>"
+        ));
+    }
+
+    #[test]
+    fn context() {
+        let html = "<span class=string>&quot;a&#39; &amp; b&quot;</span>";
+        assert_eq!(
+            embed(HtmlContext::Attribute, html.to_string()),
+            "&lt;span class=string&gt;&amp;quot;a&amp;#39; &amp;amp; b&amp;quot;&lt;/span&gt;"
+        );
+        assert_eq!(
+            embed(HtmlContext::Template, html.to_string()),
+            "<span class=string>&amp;quot;a&amp;#39; &amp;amp; b&amp;quot;</span>"
+        );
+        assert_eq!(embed(HtmlContext::Normal, html.to_string()), html);
+
+        let languages = rust();
+        let source = br#"let s = "a & 'b'";"#;
+        let options = RenderOptions::new().context(HtmlContext::Attribute);
+        let html = languages.render_with("rust", source, &options).unwrap();
+        assert!(!html.contains(['<', '>', '"', '\'']));
+        assert_eq!(
+            html,
+            embed(
+                HtmlContext::Attribute,
+                languages.render("rust", source).unwrap()
+            )
+        );
+        let lines = languages
+            .render_iter("rust", source, &options)
+            .unwrap()
+            .collect::<String>();
+        assert_eq!(lines, html);
+    }
+
+    #[test]
+    fn bom() {
+        let languages = rust();
+        let source = b"fn f() {}\nlet a;\n";
+        let bom = [b"\xef\xbb\xbf".as_slice(), source].concat();
+        let options = RenderOptions::new().highlight_lines(2..3);
+        let html = languages.render_with("rust", &bom, &options).unwrap();
+        assert!(!html.contains('\u{FEFF}'));
+        assert_eq!(
+            html,
+            languages.render_with("rust", source, &options).unwrap()
+        );
+        let lines = languages.render_lines("rust", &bom, &options).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("fn"));
+        assert!(lines[1].contains("highlighted"));
+        assert!(!languages.render_auto(None, &bom).contains('\u{FEFF}'));
+    }
+
+    #[test]
+    fn zero_width() {
+        let source = "a\u{200B}b\u{2060}\n\u{FEFF}c\u{00E9}".as_bytes();
+        assert_eq!(
+            normalize_source(b"\xef\xbb\xbfa", ZeroWidth::Keep),
+            &b"a"[..]
+        );
+        assert_eq!(normalize_source(source, ZeroWidth::Keep), source);
+        assert_eq!(
+            normalize_source(source, ZeroWidth::Remove),
+            "ab\nc\u{00E9}".as_bytes()
+        );
+        assert!(matches!(
+            normalize_source(b"a\xe2\x80", ZeroWidth::Remove),
+            Cow::Borrowed(b"a\xe2\x80")
+        ));
+
+        let languages = rust();
+        let options = RenderOptions::new().zero_width(ZeroWidth::Remove);
+        assert_eq!(
+            languages
+                .render_with("rust", "let a\u{200B}b;".as_bytes(), &options)
+                .ok(),
+            languages.render("rust", b"let ab;")
+        );
+        let options = RenderOptions::new().zero_width(ZeroWidth::Show);
+        let html = languages
+            .render_with("rust", "let a\u{200B}b;".as_bytes(), &options)
+            .unwrap();
+        assert!(html.contains("<span class=zero-width>&lt;200b&gt;</span>"));
+    }
+
+    #[test]
+    fn token_element() {
+        assert!(is_element_name("hl-token"));
+        assert!(!is_element_name("x><script"));
+        assert!(!is_element_name("-x"));
+
+        let languages = rust();
+        let source = b"let s = \"a\nb\";";
+        let options = RenderOptions::new().token_element("hl-token");
+        let html = languages.render_with("rust", source, &options).unwrap();
+        assert!(html.contains("<hl-token class=keyword>let</hl-token>"));
+        assert!(html.contains("<hl-token class=string>&quot;a\n</hl-token></span>"));
+        assert_eq!(html.matches("<span class=line>").count(), 2);
+        assert!(!html.contains("<span class=keyword>"));
+        assert_eq!(
+            languages
+                .render_with("rust", source, &RenderOptions::new().token_element("<b>"))
+                .ok(),
+            languages.render("rust", source)
+        );
+    }
+
+    #[test]
+    fn wrap() {
+        let languages = rust();
+        let options = RenderOptions::new().wrap_column(12);
+        let html = languages
+            .render_with(
+                "rust",
+                "let s = \"日本語日本語日本語\";\nlet t;\n".as_bytes(),
+                &options,
+            )
+            .unwrap();
+        assert_eq!(html.matches("<span class=line>").count(), 2);
+        assert_eq!(
+            html.matches("<span class=\"line line-continuation\">")
+                .count(),
+            2
+        );
+        assert!(html.contains("<span class=string>&quot;日</span>\n</span><span class=\"line line-continuation\"><span class=string>本語日本語日</span>\n</span><span class=\"line line-continuation\"><span class=string>本語&quot;</span>"));
+    }
+
+    #[test]
+    fn show_whitespace() {
+        let languages = rust();
+        let options = RenderOptions::new().show_whitespace(true);
+        let html = languages
+            .render_with("rust", b"fn f() {\n    let\ta;\n}\n", &options)
+            .unwrap();
+        assert!(html.contains(&format!(
+            "{}<span class=keyword>let</span><span class=whitespace>→</span>a",
+            "<span class=whitespace>·</span>".repeat(4)
+        )));
+        assert!(!html.contains("    "));
+        assert!(!html.contains('\t'));
+
+        let html = languages
+            .render("rust", b"fn f() {\n    let\ta;\n}\n")
+            .unwrap();
+        assert!(!html.contains("whitespace"));
+    }
+
+    #[test]
+    fn highlight_lines() {
+        let languages = rust();
+        let html = languages
+            .render_with(
+                "rust",
+                b"a;\nb;\nc;\n",
+                &RenderOptions::new().highlight_lines(2..4),
+            )
+            .unwrap();
+        assert_eq!(html.matches("<span class=\"line highlighted\">").count(), 2);
+        assert!(html.starts_with("<pre class=language-rust><code><span class=line>"));
+    }
+
+    #[test]
+    fn start_line() {
+        let languages = rust();
+        let options = RenderOptions::new().start_line(100).wrap_column(4);
+        let html = languages
+            .render_with("rust", b"a;\nlet bb;\nc;\n", &options)
+            .unwrap();
+        assert!(html.starts_with(
+            "<pre class=language-rust><code><span class=line data-line=\"100\">a;\n</span>"
+        ));
+        assert!(html.contains("data-line=\"101\""));
+        assert!(html.contains("<span class=line data-line=\"102\">c;\n</span>"));
+        assert_eq!(html.matches("data-line").count(), 3);
+        let html = languages.render("rust", b"a;\n").unwrap();
+        assert!(!html.contains("data-line"));
+    }
+
+    #[test]
+    fn annotations() {
+        let languages = rust();
+        let options = RenderOptions::new().annotate(2, "why <this>?");
+        let html = languages
+            .render_with("rust", b"a;\nb;\nc;\n", &options)
+            .unwrap();
+        assert_eq!(html.matches("line-note").count(), 1);
+        assert!(html.contains(
+            "<span class=line>b;<span class=line-note>why &lt;this&gt;?</span>\n</span>"
+        ));
+        let html = languages
+            .render_with("rust", b"a;\nb;\n", &options.bare_lines(true))
+            .unwrap();
+        assert!(!html.contains("line-note"));
+    }
+
+    #[test]
+    fn line_separator() {
+        let languages = rust();
+        let source = b"a;\nb;\n";
+        let render = |separator| {
+            let options = RenderOptions::new().line_separator(separator);
+            languages.render_with("rust", source, &options).unwrap()
+        };
+
+        let html = render(LineSeparator::Newline);
+        assert_eq!(html, languages.render("rust", source).unwrap());
+        assert_eq!(html.matches("\n</span>").count(), 2, "{html}");
+
+        let html = render(LineSeparator::Br);
+        assert_eq!(html.matches("<br></span>").count(), 2, "{html}");
+        assert!(!html.contains('\n'), "{html}");
+
+        let html = render(LineSeparator::None);
+        assert_eq!(html.matches("<span class=line>").count(), 2, "{html}");
+        assert!(!html.contains('\n') && !html.contains("<br>"), "{html}");
+        assert_eq!(super::extract_text(&html), "a;b;");
+
+        let options = RenderOptions::new()
+            .line_separator(LineSeparator::Br)
+            .bare_lines(true);
+        let html = languages.render_with("rust", source, &options).unwrap();
+        assert_eq!(html.matches("<br>").count(), 2, "{html}");
+    }
+
+    #[test]
+    fn escape() {
+        let languages = rust();
+        let source = b"let a = b < c;\n";
+        let html = languages.render("rust", source).unwrap();
+        assert!(html.contains(" &lt; "), "{html}");
+        let html = languages
+            .render_with("rust", source, &RenderOptions::new().escape(false))
+            .unwrap();
+        assert!(html.contains(" < "), "{html}");
+        assert!(!html.contains("&lt;"), "{html}");
+    }
+
+    #[test]
+    fn indent_guides() {
+        let languages = rust();
+        let options = RenderOptions::new().indent_guides(4);
+        let html = languages
+            .render_with(
+                "rust",
+                b"fn f() {\n        let a;\n  \tlet b;\n}\n",
+                &options,
+            )
+            .unwrap();
+        let guide = |ws: &str| format!("<span class=indent-guide>{ws}</span>");
+        assert!(html.contains(&format!(
+            "<span class=line>{}{}<span class=keyword>let</span> a;",
+            guide("    "),
+            guide("    ")
+        )));
+        assert!(html.contains(&format!(
+            "<span class=line>{}{}<span class=keyword>let</span> b;",
+            guide("  "),
+            guide("\t")
+        )));
+        assert!(html.contains("<span class=line>fn"));
+        assert_eq!(html.matches("indent-guide").count(), 4);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    css,
+    html::{embed, normalize_source, Buffers},
+    html_lines, Error, Languages, RenderOptions, Style, Theme, ZeroWidth,
+};
+
+/// The inline `style` declarations of a style, empty for the default style.
+fn declarations(style: &Style) -> String {
+    css::declarations(style)
+        .iter()
+        .map(|(property, value)| format!("{property}:{value}"))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+impl<'a> Languages<'a> {
+    /// Renders HTML with the theme inlined as `style` attributes and no classes, for
+    /// email clients and sanitizers that strip stylesheets.
+    ///
+    /// The `<pre>` carries the theme background and foreground, and the lines of
+    /// `options.highlight_lines` are wrapped in spans with the line highlight background.
+    /// Options that only add classes, the line spans, heat, conflicts, collapsing,
+    /// `unknown_class`, `show_whitespace`, `indent_guides` and showing zero-width
+    /// characters, are ignored; search marks, wrapping and the context apply.
+    pub fn render_inline_styles(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &Theme,
+        options: &RenderOptions,
+    ) -> Result<String, Error> {
+        let entry = self.entry(lang)?;
+
+        // Each distinct style is formatted once, however many captures share it. The
+        // names of every language are styled, for the captures of injected languages.
+        let mut cache = HashMap::new();
+        let attrs = self
+            .names
+            .iter()
+            .map(|name| {
+                let style = theme.resolve(name);
+                cache
+                    .entry(style)
+                    .or_insert_with(|| match declarations(&style) {
+                        css if css.is_empty() => css,
+                        css => format!("style=\"{css}\""),
+                    })
+                    .clone()
+            })
+            .collect::<Vec<_>>();
+
+        let options = RenderOptions {
+            line_heat: None,
+            collapse: Vec::new(),
+            mark_conflicts: false,
+            bare_lines: true,
+            unknown_class: None,
+            show_whitespace: false,
+            zero_width: match options.zero_width {
+                ZeroWidth::Show => ZeroWidth::Keep,
+                zero_width => zero_width,
+            },
+            indent_guides: None,
+            ..options.clone()
+        };
+        let mut highlighter = self.highlighter();
+        let source = &normalize_source(source, options.zero_width);
+        let events =
+            self.highlight(&mut highlighter, entry, source, options.max_injection_depth)?;
+        let lines = html_lines(source, events, &options, &attrs, &mut Buffers::default())?;
+
+        let pre = declarations(&Style {
+            fg: theme.foreground,
+            bg: theme.background,
+            ..Style::default()
+        });
+        let mut s = String::with_capacity(lines.iter().map(String::len).sum::<usize>() + 64);
+        if pre.is_empty() {
+            s.push_str("<pre><code>");
+        } else {
+            let _ = write!(s, "<pre style=\"{pre}\"><code>");
+        }
+        let highlight = theme
+            .line_highlight
+            .map(|bg| declarations(&Style::new().bg(bg)));
+        for (number, line) in (1..).zip(&lines) {
+            match &highlight {
+                Some(bg) if options.is_highlighted(number) => {
+                    let _ = write!(s, "<span style=\"{bg}\">{line}</span>");
+                }
+                _ => s.push_str(line),
+            }
+        }
+        s.push_str("</code></pre>");
+        Ok(embed(options.context, s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, Color};
+
+    #[test]
+    fn render() {
+        let languages = rust();
+        let mut theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            (
+                "string",
+                Style::new().fg(Color::rgb(152, 195, 121)).italic(),
+            ),
+        ]);
+        theme.background = Some(Color::rgb(40, 44, 52));
+        theme.foreground = Some(Color::rgb(171, 178, 191));
+
+        let html = languages
+            .render_inline_styles(
+                "rust",
+                b"let s = \"<&>\";\nlet t;\n",
+                &theme,
+                &RenderOptions::new()
+                    .collapse(1..2)
+                    .mark_conflicts(true)
+                    .show_whitespace(true),
+            )
+            .unwrap();
+        assert!(html.starts_with("<pre style=\"color:#abb2bf;background-color:#282c34\"><code>"));
+        assert!(html.contains("<span style=\"color:#c678dd;font-weight:bold\">let</span>"));
+        assert!(html.contains(
+            "<span style=\"color:#98c379;font-style:italic\">&quot;&lt;&amp;&gt;&quot;</span>"
+        ));
+        assert!(!html.contains("class="));
+    }
+
+    #[test]
+    fn backgrounds() {
+        let languages = rust();
+        let mut theme = Theme::new(&[(
+            "string",
+            Style::new()
+                .fg(Color::rgb(130, 7, 30))
+                .bg(Color::rgb(255, 235, 233)),
+        )]);
+        theme.line_highlight = Some(Color::rgb(255, 248, 197));
+
+        let html = languages
+            .render_inline_styles(
+                "rust",
+                b"let a = \"-\";\nlet b = \"+\";\n",
+                &theme,
+                &RenderOptions::new().highlight_lines(2..3),
+            )
+            .unwrap();
+        assert!(html.contains(
+            "<span style=\"color:#82071e;background-color:#ffebe9\">&quot;-&quot;</span>"
+        ));
+        assert!(html.contains("<span style=\"background-color:#fff8c5\">let b = "));
+        assert_eq!(html.matches("#fff8c5").count(), 1);
+    }
+
+    #[test]
+    fn declarations() {
+        assert_eq!(super::declarations(&Style::new()), "");
+        assert_eq!(
+            super::declarations(
+                &Style::new()
+                    .bg(Color::rgb(0, 0, 0))
+                    .underline()
+                    .strikethrough()
+            ),
+            "background-color:#000000;text-decoration:underline line-through"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Languages, Token};
+
+/// The JSON token stream of [`Languages::render_json`].
+///
+/// The field names are stable; new fields may be added.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TokenStream<'a> {
+    pub language: Cow<'a, str>,
+    pub tokens: Vec<StreamToken<'a>>,
+}
+
+/// A token of a [`TokenStream`], covering the bytes `start..end` of the source.
+///
+/// The field names are stable; new fields may be added.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct StreamToken<'a> {
+    pub start: usize,
+    pub end: usize,
+    /// The capture name, `null` for unhighlighted text.
+    pub name: Option<Cow<'a, str>>,
+}
+
+impl<'a> From<&Token<'a>> for StreamToken<'a> {
+    fn from(token: &Token<'a>) -> Self {
+        Self {
+            start: token.range.start,
+            end: token.range.end,
+            name: token.name.map(Cow::Borrowed),
+        }
+    }
+}
+
+impl<'a> Languages<'a> {
+    /// Renders to a JSON [`TokenStream`], like
+    /// `{"language":"rust","tokens":[{"start":0,"end":3,"name":"include"},…]}`.
+    ///
+    /// The tokens follow [`Languages::tokens`]: gaps are not omitted but are tokens with
+    /// a `null` name, so the tokens cover the whole source.
+    pub fn render_json(&self, lang: &str, source: &[u8]) -> Result<String, Error> {
+        let stream = TokenStream {
+            language: Cow::Borrowed(lang),
+            tokens: self
+                .tokens(lang, source)?
+                .iter()
+                .map(StreamToken::from)
+                .collect(),
+        };
+        Ok(serde_json::to_string(&stream).expect("token streams serialize"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    #[test]
+    fn round_trip() {
+        let languages = rust();
+        let json = languages.render_json("rust", b"let a = 1;").unwrap();
+        assert!(json.starts_with(
+            r#"{"language":"rust","tokens":[{"start":0,"end":3,"name":"keyword"},{"start":3,"end":4,"name":null},"#
+        ));
+
+        let stream = serde_json::from_str::<TokenStream>(&json).unwrap();
+        assert_eq!(stream.language, "rust");
+        assert_eq!(stream.tokens.last().unwrap().end, 10);
+        assert_eq!(serde_json::to_string(&stream).unwrap(), json);
+    }
+
+    #[test]
+    fn golden() {
+        let json = rust()
+            .render_json("rust", include_bytes!("../fixtures/sample.rs"))
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<TokenStream>(&json).unwrap(),
+            serde_json::from_str::<TokenStream>(include_str!("../fixtures/sample.json")).unwrap()
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt::Write;
+
+use crate::{Color, Error, FontStyle, Languages, Theme};
+
+/// The name of a color defined by [`latex_preamble`].
+fn color_name(color: Color) -> String {
+    format!("hl{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// The LaTeX preamble defining the `Highlighting` environment and the theme colors used
+/// by [`Languages::render_latex`].
+pub fn latex_preamble(theme: &Theme) -> String {
+    let mut s = String::new();
+    s.push_str("\\usepackage{xcolor}\n");
+    s.push_str("\\usepackage{fancyvrb}\n");
+    s.push_str("\\usepackage[normalem]{ulem}\n");
+    for color in theme.colors() {
+        let _ = writeln!(
+            s,
+            "\\definecolor{{{}}}{{HTML}}{{{:02X}{:02X}{:02X}}}",
+            color_name(color),
+            color.r,
+            color.g,
+            color.b
+        );
+    }
+    s.push_str("\\DefineVerbatimEnvironment{Highlighting}{Verbatim}{commandchars=\\\\\\{\\}");
+    if let Some(fg) = theme.foreground {
+        let _ = write!(s, ",formatcom=\\color{{{}}}", color_name(fg));
+    }
+    s.push_str("}\n");
+    s
+}
+
+/// Escapes LaTeX special characters.
+fn escape(s: &mut String, c: char) {
+    match c {
+        '\\' => s.push_str("\\textbackslash{}"),
+        '^' => s.push_str("\\textasciicircum{}"),
+        '~' => s.push_str("\\textasciitilde{}"),
+        '{' | '}' | '%' | '&' | '#' | '_' | '$' => {
+            s.push('\\');
+            s.push(c);
+        }
+        c => s.push(c),
+    }
+}
+
+impl<'a> Languages<'a> {
+    /// Renders to a `Highlighting` environment, see [`latex_preamble`].
+    ///
+    /// Tokens are styled with `\textcolor`, `\textbf`, `\textit`, `\underline` and `ulem`'s
+    /// `\sout`, and line breaks are real newlines inside the environment.
+    pub fn render_latex(&self, lang: &str, source: &[u8], theme: &Theme) -> Result<String, Error> {
+        let mut s = String::from("\\begin{Highlighting}[]\n");
+        self.for_each_token(lang, source, |range, name| {
+            let style = name.map(|name| theme.resolve(name)).unwrap_or_default();
+            let mut open = String::new();
+            let mut close = 0;
+            if let Some(fg) = style.fg {
+                let _ = write!(open, "\\textcolor{{{}}}{{", color_name(fg));
+                close += 1;
+            }
+            for font_style in style.font_styles() {
+                open.push_str(match font_style {
+                    FontStyle::Bold => "\\textbf{",
+                    FontStyle::Italic => "\\textit{",
+                    FontStyle::Underline => "\\underline{",
+                    FontStyle::Strikethrough => "\\sout{",
+                });
+                close += 1;
+            }
+            for (i, text) in String::from_utf8_lossy(&source[range])
+                .split('\n')
+                .enumerate()
+            {
+                if i > 0 {
+                    s.push('\n');
+                }
+                if text.is_empty() {
+                    continue;
+                }
+                s.push_str(&open);
+                text.chars()
+                    .filter(|c| *c == '\t' || !c.is_control())
+                    .for_each(|c| escape(&mut s, c));
+                s.extend(std::iter::repeat_n('}', close));
+            }
+        })?;
+        if !s.ends_with('\n') {
+            s.push('\n');
+        }
+        s.push_str("\\end{Highlighting}\n");
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, Style};
+
+    #[test]
+    fn preamble() {
+        let mut theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            (
+                "keyword.function",
+                Style::new().fg(Color::rgb(198, 120, 221)),
+            ),
+            ("string", Style::new().fg(Color::rgb(152, 195, 121))),
+        ]);
+        theme.foreground = Some(Color::rgb(171, 178, 191));
+        assert_eq!(
+            latex_preamble(&theme),
+            "\\usepackage{xcolor}
+\\usepackage{fancyvrb}
+\\usepackage[normalem]{ulem}
+\\definecolor{hl98c379}{HTML}{98C379}
+\\definecolor{hlabb2bf}{HTML}{ABB2BF}
+\\definecolor{hlc678dd}{HTML}{C678DD}
+\\DefineVerbatimEnvironment{Highlighting}{Verbatim}{commandchars=\\\\\\{\\},formatcom=\\color{hlabb2bf}}
+"
+        );
+    }
+
+    #[test]
+    fn render() {
+        let languages = rust();
+        let theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            (
+                "string",
+                Style::new().fg(Color::rgb(152, 195, 121)).italic(),
+            ),
+        ]);
+        let latex = languages
+            .render_latex("rust", br#"let s = r"100% \ {done}";"#, &theme)
+            .unwrap();
+        assert_eq!(
+            latex,
+            r#"\begin{Highlighting}[]
+\textcolor{hlc678dd}{\textbf{let}} s = \textcolor{hl98c379}{\textit{r"100\% \textbackslash{} \{done\}"}};
+\end{Highlighting}
+"#
+        );
+    }
+}
@@ -1,8 +1,93 @@
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Write as _},
+    hash::{Hash, Hasher},
+    ops::{Index, Range},
+    rc::Rc,
+    sync::{Mutex, OnceLock},
+};
 
-use tree_sitter_highlight::{Highlighter, HtmlRenderer};
+use tree_sitter_highlight::Highlighter;
 
-pub use tree_sitter_highlight::HighlightConfiguration;
+mod ansi;
+#[cfg(feature = "themes-base16")]
+mod base16;
+mod brackets;
+#[cfg(feature = "cache")]
+mod cache;
+mod capture;
+mod class;
+mod css;
+mod detect;
+mod error;
+mod errors;
+mod fingerprint;
+mod folds;
+#[cfg(feature = "themes-helix")]
+mod helix;
+mod html;
+mod inline;
+#[cfg(feature = "serde")]
+mod json;
+mod latex;
+mod locals;
+mod markdown;
+mod options;
+mod outline;
+mod overlay;
+mod pango;
+mod renderer;
+mod rtf;
+mod session;
+mod shiki;
+mod stream;
+mod svg;
+mod theme;
+#[cfg(feature = "themes")]
+pub mod themes;
+mod token;
+mod typst;
+#[cfg(feature = "themes-vscode")]
+mod vscode;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod width;
+
+use fingerprint::Fingerprint;
+use html::{find_terms, normalize_source, Buffers, Collapse, Embed, HtmlWriter, Rows};
+
+pub use ansi::{AnsiStyle, AnsiTheme, ColorDepth};
+#[cfg(feature = "themes-base16")]
+pub use base16::BASE16_SLOTS;
+#[cfg(feature = "cache")]
+pub use cache::{CacheStats, CachedLanguages};
+pub use capture::Capture;
+pub use class::ClassStyle;
+pub use css::{css_variables, theme_css};
+pub use detect::detect_language;
+pub use error::Error;
+pub use html::{extract_text, normalize_html};
+#[cfg(feature = "serde")]
+pub use json::{StreamToken, TokenStream};
+pub use latex::latex_preamble;
+pub use options::{HtmlContext, LineHeat, LineSeparator, RenderOptions, ZeroWidth};
+pub use outline::{ContextFrame, Symbol, SymbolKind};
+pub use renderer::{Html, Renderer};
+pub use session::{LineDiff, Session};
+pub use shiki::ShikiToken;
+pub use svg::SvgOptions;
+pub use theme::{
+    Color, Coverage, FontStyle, ParseColorError, Style, Theme, ThemeBuilder, ThemeError,
+};
+pub use token::{ColumnUnit, PositionedToken, Token};
+pub use tree_sitter_highlight::{Highlight, HighlightConfiguration, HighlightEvent};
+pub use typst::{TypstMode, TypstOptions};
+#[cfg(feature = "themes-vscode")]
+pub use vscode::VSCODE_SCOPES;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmLanguages;
 
 pub const NAMES: &[&str] = &[
     "annotation",
@@ -18,6 +103,9 @@ pub const NAMES: &[&str] = &[
     "constructor",
     "debug",
     "define",
+    "diff.delta",
+    "diff.minus",
+    "diff.plus",
     "error",
     "exception",
     "field",
@@ -70,6 +158,8 @@ pub const NAMES: &[&str] = &[
     "text.note",
     "text.warning",
     "text.danger",
+    "text.diff.add",
+    "text.diff.delete",
     "todo",
     "type",
     "type.builtin",
@@ -82,7 +172,128 @@ pub const NAMES: &[&str] = &[
 /// Languages
 #[derive(Default)]
 pub struct Languages<'a> {
-    inner: HashMap<&'a str, (HighlightConfiguration, Vec<String>)>,
+    inner: HashMap<&'a str, Entry>,
+    /// The languages built on first use, see [`Languages::insert_lazy`].
+    lazy: HashMap<&'a str, Lazy>,
+    /// The capture names recognized by any language, in `Highlight` index order.
+    ///
+    /// Every language is configured in this one index space, with the names it does not
+    /// recognize masked, so the captures of injected languages can be told apart.
+    names: Vec<String>,
+    /// The registered language of each alias, lowercase.
+    aliases: HashMap<String, &'a str>,
+    /// Resolves injected language names before the tables, see
+    /// [`Languages::set_injection_resolver`].
+    injection_resolver: Option<Box<InjectionResolver>>,
+    /// The registered language of injected language names, lowercase, see
+    /// [`Languages::set_injection_remap`].
+    injection_remap: HashMap<String, &'a str>,
+    /// How deeply injections nest, see [`Languages::set_max_injection_depth`].
+    max_injection_depth: Option<usize>,
+    /// The number of injection resolvers and overrides set, which closures cannot be
+    /// hashed, see [`Languages::fingerprint`].
+    hooks: u64,
+    /// Idle highlighters and buffers, reused across renders, see
+    /// [`Languages::with_scratch`].
+    pool: Mutex<Vec<Scratch>>,
+    /// The number of highlighters created, to test their reuse.
+    #[cfg(test)]
+    highlighters: std::sync::atomic::AtomicUsize,
+}
+
+/// How deeply injected languages nest by default, see
+/// [`Languages::set_max_injection_depth`].
+pub const MAX_INJECTION_DEPTH: usize = 8;
+
+/// A highlighter and the buffers of an [`HtmlWriter`], reused across renders.
+pub(crate) struct Scratch {
+    pub(crate) highlighter: Highlighter,
+    pub(crate) buffers: Buffers,
+    /// The number of highlights the highlighter started in the last render.
+    pub(crate) highlights: usize,
+}
+
+/// How many idle highlighters a registry keeps for reuse.
+const MAX_POOLED: usize = 8;
+
+/// The injections of one render, to limit how deeply they nest.
+///
+/// tree-sitter-highlight does not say which layer an injection is found in, so an
+/// injection counts as nested in another while a highlight opened after the other was
+/// resolved is still open, such as the capture of the code block it sits in. Injections
+/// nested outside any highlight, such as the token trees of nested macros, cannot be told
+/// from siblings, so a render also resolves at most [`INJECTIONS_PER_LEVEL`] injections
+/// per level of depth allowed.
+#[derive(Default)]
+struct Depth {
+    /// The number of open highlights when each injection still open was resolved.
+    open: Vec<usize>,
+    /// The number of open highlights.
+    highlights: usize,
+    /// The number of injections resolved.
+    resolved: usize,
+}
+
+/// How many injections a render resolves per level of its maximum depth.
+const INJECTIONS_PER_LEVEL: usize = 256;
+
+/// Maps an injected language name to the name of a registered language.
+type InjectionResolver = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Decides how the injections of a document are resolved.
+type InjectionOverride = dyn Fn(&str) -> InjectionDecision + Send + Sync;
+
+/// How an injection is resolved, see [`Languages::set_injection_override`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InjectionDecision {
+    /// Highlights the content in this language, resolved like an injected name.
+    UseLanguage(String),
+    /// Leaves the content plain.
+    PlainText,
+    /// Resolves the injected name through the registry.
+    Default,
+}
+
+/// A render with the warnings it raised, see [`Languages::render_report`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderReport {
+    pub html: String,
+    pub warnings: Vec<RenderWarning>,
+}
+
+/// Something suspicious about a render, which still succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RenderWarning {
+    /// The grammar highlighted nothing in a source that is not blank, as when its
+    /// highlights query matches none of its nodes.
+    NoHighlights,
+}
+
+/// Builds the configuration of a language.
+type Factory = dyn Fn() -> Result<HighlightConfiguration, tree_sitter::QueryError> + Send + Sync;
+
+/// A language built on first use.
+struct Lazy {
+    factory: Box<Factory>,
+    /// The language once built, `None` when the factory failed.
+    entry: OnceLock<Option<Entry>>,
+}
+
+/// A registered language.
+struct Entry {
+    /// The configuration and the `class=` attributes, indexed by `Highlight`.
+    inner: (HighlightConfiguration, Vec<String>),
+    /// The recognized capture names, indexed by `Highlight`, empty for the names only
+    /// other languages recognize.
+    names: Vec<String>,
+    /// Decides the injections of documents in this language, see
+    /// [`Languages::set_injection_override`].
+    injection_override: Option<Box<InjectionOverride>>,
+    /// The folds query, see [`Languages::set_folds_query`].
+    folds: Option<tree_sitter::Query>,
+    /// The classes of each profile, indexed by `Highlight`, see [`Languages::set_profile`].
+    profiles: HashMap<String, Vec<String>>,
 }
 
 impl<'a> Languages<'a> {
@@ -94,59 +305,1223 @@ impl<'a> Languages<'a> {
         self.insert_with_names(lang, config, NAMES)
     }
 
+    /// Inserts a language recognizing the given capture names, either `&str`s or
+    /// [`Capture`]s.
     pub fn insert_with_names(
         &mut self,
         lang: &'a str,
+        config: HighlightConfiguration,
+        names: &[impl AsRef<str>],
+    ) -> &mut Self {
+        let names = names.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        let style = ClassStyle::default();
+        self.add(lang, config, &names, |name| style.attr(name));
+        self
+    }
+
+    /// Registers a language built on first use, for registries of many grammars of which
+    /// few are used.
+    ///
+    /// `factory` runs at most once, the first time the language is rendered or looked up,
+    /// and the language recognizes the names of [`NAMES`] like [`Languages::insert`].
+    /// When the factory fails, the language stays unknown.
+    pub fn insert_lazy(
+        &mut self,
+        lang: &'a str,
+        factory: impl Fn() -> Result<HighlightConfiguration, tree_sitter::QueryError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> &mut Self {
+        self.extend_names(NAMES);
+        self.inner.remove(lang);
+        self.lazy.insert(
+            lang,
+            Lazy {
+                factory: Box::new(factory),
+                entry: OnceLock::new(),
+            },
+        );
+        self
+    }
+
+    /// Adds capture names to the shared index space.
+    fn extend_names(&mut self, names: &[&str]) {
+        for name in names {
+            if !self.names.iter().any(|n| n == name) {
+                self.names.push(name.to_string());
+            }
+        }
+    }
+
+    /// Configures a language in the shared index space, which must have its names, with
+    /// `class` giving the attributes of each of its names.
+    fn configure(
+        &self,
         mut config: HighlightConfiguration,
         names: &[&str],
+        class: impl Fn(&str) -> String,
+    ) -> Entry {
+        let names = self
+            .names
+            .iter()
+            .map(|n| match names.contains(&n.as_str()) {
+                true => n.clone(),
+                false => String::new(),
+            })
+            .collect::<Vec<_>>();
+        // Capture names cannot contain spaces, so masked names never match.
+        config.configure(
+            &names
+                .iter()
+                .map(|n| if n.is_empty() { " " } else { n })
+                .collect::<Vec<_>>(),
+        );
+        let classes = names
+            .iter()
+            .map(|n| {
+                if n.is_empty() {
+                    String::new()
+                } else {
+                    class(n)
+                }
+            })
+            .collect();
+        Entry {
+            inner: (config, classes),
+            names,
+            injection_override: None,
+            folds: None,
+            profiles: HashMap::new(),
+        }
+    }
+
+    /// Configures and inserts a language in the shared index space, see
+    /// [`Languages::configure`].
+    fn add(
+        &mut self,
+        lang: &'a str,
+        config: HighlightConfiguration,
+        names: &[&str],
+        class: impl Fn(&str) -> String,
+    ) {
+        self.extend_names(names);
+        let mut entry = self.configure(config, names, class);
+        // Overrides belong to the language name, so they outlive its configuration.
+        entry.injection_override = self.remove(lang).and_then(|entry| entry.injection_override);
+        self.inner.insert(lang, entry);
+    }
+
+    /// Removes a language, if it is registered and built.
+    fn remove(&mut self, lang: &str) -> Option<Entry> {
+        self.inner
+            .remove(lang)
+            .or_else(|| self.lazy.remove(lang)?.entry.into_inner()?)
+    }
+
+    /// Builds a lazy language, the first time only.
+    fn build<'s>(&self, lazy: &'s Lazy) -> Option<&'s Entry> {
+        lazy.entry
+            .get_or_init(|| {
+                let config = (lazy.factory)().ok()?;
+                let style = ClassStyle::default();
+                Some(self.configure(config, NAMES, |name| style.attr(name)))
+            })
+            .as_ref()
+    }
+
+    /// Moves the languages of `other` in; on a name conflict, the language of `other`
+    /// replaces the registered one.
+    ///
+    /// The languages of `other` are configured again, keeping their classes.
+    pub fn merge(&mut self, other: Languages<'a>) -> &mut Self {
+        let mut built = Vec::new();
+        for (lang, Lazy { factory, entry }) in other.lazy {
+            match entry.into_inner() {
+                Some(Some(entry)) => built.push((lang, entry)),
+                unbuilt => {
+                    self.extend_names(NAMES);
+                    self.inner.remove(lang);
+                    let entry = unbuilt.map(OnceLock::from).unwrap_or_default();
+                    self.lazy.insert(lang, Lazy { factory, entry });
+                }
+            }
+        }
+        for (lang, entry) in other.inner.into_iter().chain(built) {
+            let Entry {
+                inner: (config, classes),
+                names,
+                injection_override,
+                folds,
+                profiles,
+            } = entry;
+            let own = names
+                .iter()
+                .filter(|n| !n.is_empty())
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            self.add(lang, config, &own, |name| {
+                names
+                    .iter()
+                    .position(|n| n == name)
+                    .and_then(|i| classes.get(i))
+                    .cloned()
+                    .unwrap_or_default()
+            });
+            if let Some(entry) = self.inner.get_mut(lang) {
+                entry.injection_override = injection_override.or(entry.injection_override.take());
+                entry.folds = folds;
+                // Profiles follow the names into the new index space.
+                entry.profiles = profiles
+                    .into_iter()
+                    .map(|(profile, classes)| {
+                        let classes = entry
+                            .names
+                            .iter()
+                            .map(|name| {
+                                names
+                                    .iter()
+                                    .position(|n| !name.is_empty() && n == name)
+                                    .and_then(|i| classes.get(i))
+                                    .cloned()
+                                    .unwrap_or_default()
+                            })
+                            .collect();
+                        (profile, classes)
+                    })
+                    .collect();
+            }
+        }
+        self.aliases.extend(other.aliases);
+        self.injection_remap.extend(other.injection_remap);
+        self.hooks += other.hooks;
+        self
+    }
+
+    /// Makes `alias`, in any case, name a registered language.
+    pub fn alias(&mut self, alias: &str, lang: &'a str) -> &mut Self {
+        self.aliases.insert(alias.to_ascii_lowercase(), lang);
+        self
+    }
+
+    /// Sets a hook mapping the language names of injections to registered languages,
+    /// for names the aliases and built-in fallbacks cannot express.
+    ///
+    /// It is consulted first; when it returns `None` or an unknown name, the injected
+    /// name is resolved as usual.
+    pub fn set_injection_resolver(
+        &mut self,
+        resolver: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
     ) -> &mut Self {
-        config.configure(names);
-        self.inner.insert(lang, (config, names_to_classes(names)));
+        self.injection_resolver = Some(Box::new(resolver));
+        self.hooks += 1;
+        self
+    }
+
+    /// Redirects injected language names, in any case, to registered languages, such as
+    /// the `html` of a grammar's injections to a language registered as `htmlx`.
+    ///
+    /// Unlike aliases, the names are only redirected in injections. They are looked up
+    /// after the [injection resolver](Languages::set_injection_resolver) and before the
+    /// registry, and replace those of an earlier call.
+    pub fn set_injection_remap(&mut self, remap: HashMap<&str, &'a str>) -> &mut Self {
+        self.injection_remap = remap
+            .into_iter()
+            .map(|(name, lang)| (name.to_lowercase(), lang))
+            .collect();
         self
     }
 
+    /// Decides the injections of documents in a language, returning `false` when the
+    /// language is unknown.
+    ///
+    /// `decide` is given each injected language name while rendering a document in
+    /// `lang`, at any depth, but not while `lang` is itself injected. Injections it
+    /// leaves to [`InjectionDecision::Default`] are resolved as usual, and the depth limit
+    /// applies whatever it decides.
+    pub fn set_injection_override(
+        &mut self,
+        lang: &str,
+        decide: impl Fn(&str) -> InjectionDecision + Send + Sync + 'static,
+    ) -> bool {
+        let Some(entry) = self.lookup_mut(lang) else {
+            return false;
+        };
+        entry.injection_override = Some(Box::new(decide));
+        self.hooks += 1;
+        true
+    }
+
+    /// Sets how deeply injected languages nest, [`MAX_INJECTION_DEPTH`] by default;
+    /// [`RenderOptions::max_injection_depth`] overrides it for a render.
+    ///
+    /// Deeper injections are not resolved, so their content is plain text inside the
+    /// captures of the language around it. `0` leaves out injections altogether.
+    ///
+    /// Nesting is only seen inside the highlights of the language around an injection, so
+    /// each render also resolves at most 256 injections per level, which bounds the work
+    /// of injections nested in ways it cannot see, such as nested macros.
+    pub fn set_max_injection_depth(&mut self, depth: usize) -> &mut Self {
+        self.max_injection_depth = Some(depth);
+        self
+    }
+
+    /// The registered name a language name resolves to: as is, lowercase, as an alias, or
+    /// through the built-in fallbacks, such as `c++` for `cpp` and `js` for `javascript`.
+    fn canonical(&self, lang: &str) -> Option<&'a str> {
+        let key = |lang: &str| {
+            (self.inner.get_key_value(lang).map(|(key, _)| *key))
+                .or_else(|| self.lazy.get_key_value(lang).map(|(key, _)| *key))
+        };
+        key(lang).or_else(|| {
+            let lower = lang.to_ascii_lowercase();
+            key(&lower)
+                .or_else(|| key(self.aliases.get(&lower)?))
+                .or_else(|| key(detect::fallback_language(&lower)?))
+        })
+    }
+
+    /// The registered language a name resolves to, the name itself for a registered
+    /// language, for listings that collapse aliases.
+    ///
+    /// Names resolve as in [`Languages::get`].
+    pub fn resolve_alias(&self, name: &str) -> Option<&'a str> {
+        self.canonical(name)
+    }
+
+    /// The registered language a language name resolves to, see [`Languages::get`].
+    fn lookup(&self, lang: &str) -> Option<&Entry> {
+        let lang = self.canonical(lang)?;
+        self.inner
+            .get(lang)
+            .or_else(|| self.build(self.lazy.get(lang)?))
+    }
+
+    /// Like [`Languages::lookup`], for changes.
+    fn lookup_mut(&mut self, lang: &str) -> Option<&mut Entry> {
+        let lang = self.canonical(lang)?;
+        if self.inner.contains_key(lang) {
+            return self.inner.get_mut(lang);
+        }
+        self.build(self.lazy.get(lang)?)?;
+        self.lazy.get_mut(lang)?.entry.get_mut()?.as_mut()
+    }
+
+    /// Resolves an injected language name to a registered language, through the
+    /// injection resolver, then like [`Languages::get`].
+    ///
+    /// Names are read like code fence info strings, so `{.rust}` and `rust,ignore` are
+    /// `rust`.
+    fn injected(&self, name: &str) -> Option<&Entry> {
+        let name = name
+            .trim_start()
+            .trim_start_matches(['{', '.'])
+            .split(|c: char| c == ',' || c == '{' || c == '}' || c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        self.injection_resolver
+            .as_ref()
+            .and_then(|resolve| self.lookup(&resolve(name)?))
+            .or_else(|| self.lookup(self.injection_remap.get(&name.to_lowercase())?))
+            .or_else(|| self.lookup(name))
+    }
+
+    /// Highlights a language, resolving its injections through the registry.
+    fn highlight<'s>(
+        &'s self,
+        highlighter: &'s mut Highlighter,
+        entry: &'s Entry,
+        source: &'s [u8],
+        max_depth: Option<usize>,
+    ) -> Result<
+        impl Iterator<Item = Result<HighlightEvent, tree_sitter_highlight::Error>> + 's,
+        Error,
+    > {
+        let max = max_depth
+            .or(self.max_injection_depth)
+            .unwrap_or(MAX_INJECTION_DEPTH);
+        let depth = Rc::new(RefCell::new(Depth::default()));
+        let events = highlighter.highlight(&entry.inner.0, source, None, {
+            let depth = depth.clone();
+            move |name| {
+                let mut depth = depth.borrow_mut();
+                let highlights = depth.highlights;
+                // Injections resolved with as many highlights open have ended.
+                depth.open.retain(|open| *open < highlights);
+                if depth.open.len() >= max || depth.resolved >= max * INJECTIONS_PER_LEVEL {
+                    return None;
+                }
+                let entry = match entry.injection_override.as_ref().map(|decide| decide(name)) {
+                    Some(InjectionDecision::UseLanguage(lang)) => self.injected(&lang),
+                    Some(InjectionDecision::PlainText) => None,
+                    Some(InjectionDecision::Default) | None => self.injected(name),
+                }?;
+                depth.open.push(highlights);
+                depth.resolved += 1;
+                Some(&entry.inner.0)
+            }
+        })?;
+        Ok(events.inspect(move |event| {
+            let mut depth = depth.borrow_mut();
+            match event {
+                Ok(HighlightEvent::HighlightStart(_)) => depth.highlights += 1,
+                Ok(HighlightEvent::HighlightEnd) => {
+                    depth.highlights = depth.highlights.saturating_sub(1)
+                }
+                _ => {}
+            }
+        }))
+    }
+
+    /// The attributes of every `Highlight` index when rendering a language with its
+    /// injections, or only injected languages without a host.
+    ///
+    /// The indices the host does not recognize take the attributes of a language that
+    /// does, so injected captures keep their classes.
+    fn injection_classes<'s>(&'s self, host: Option<&'s Entry>) -> Cow<'s, [String]> {
+        let class = |entry: &'s Entry, i: usize| {
+            entry
+                .names
+                .get(i)
+                .filter(|n| !n.is_empty())
+                .and_then(|_| entry.inner.1.get(i))
+        };
+        // A host recognizing every name keeps its classes as they are, short or not.
+        if let Some(entry) = host.filter(|entry| {
+            entry.names.len() == self.names.len() && entry.names.iter().all(|n| !n.is_empty())
+        }) {
+            return Cow::Borrowed(&entry.inner.1);
+        }
+        // Lazy languages only count once built.
+        let built = self.lazy.iter().filter_map(|(lang, lazy)| {
+            let entry = lazy.entry.get()?.as_ref()?;
+            Some((lang, entry))
+        });
+        let mut others = self.inner.iter().chain(built).collect::<Vec<_>>();
+        others.sort_unstable_by_key(|(lang, _)| **lang);
+        (0..self.names.len())
+            .map(|i| {
+                host.and_then(|entry| class(entry, i))
+                    .or_else(|| others.iter().find_map(|(_, other)| class(other, i)))
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Returns the configuration and classes of a language.
+    ///
+    /// Like every method taking a language, this looks the name up as is, then lowercase,
+    /// then as an alias, then through built-in fallbacks like `c++` for `cpp`, `shell`
+    /// for `bash` and file extensions.
     pub fn get<'b>(&'a self, lang: &'b str) -> Option<&'a (HighlightConfiguration, Vec<String>)> {
-        self.inner.get(lang)
+        self.lookup(lang).map(|entry| &entry.inner)
     }
 
-    pub fn render(&self, lang: &str, source: &[u8]) -> Option<String> {
-        if let Some((config, names)) = self.get(lang) {
-            let mut highlighter = Highlighter::new();
-            if let Ok(highlights) = highlighter.highlight(config, source, None, |_| None) {
-                let mut renderer = HtmlRenderer::new();
-                return renderer
-                    .render(highlights, source, &|h| {
-                        names.get(h.0).map(String::as_bytes).unwrap_or(b"")
-                    })
-                    .map(|_| String::new())
-                    .map(|mut s| {
-                        s.push_str("<pre class=language-");
-                        s.push_str(lang);
-                        s.push_str("><code>");
-                        renderer.lines().for_each(|line| {
-                            s.push_str("<span class=line>");
-                            s.push_str(line);
-                            s.push_str("</span>");
-                        });
-                        s.push_str("</code></pre>");
-                        s
+    /// Returns the configuration and classes of a language for in-place changes.
+    ///
+    /// The capture names used by the non-HTML renderers are not affected.
+    pub fn get_mut(&mut self, lang: &str) -> Option<&mut (HighlightConfiguration, Vec<String>)> {
+        self.lookup_mut(lang).map(|entry| &mut entry.inner)
+    }
+
+    /// Maps the captures of a language to classes per `style`, returning `false` when the
+    /// language is unknown.
+    ///
+    /// Pair it with [`Theme::to_css`] and the same style so markup and CSS agree.
+    pub fn set_class_style(&mut self, lang: &str, style: &ClassStyle) -> bool {
+        let Some(entry) = self.lookup_mut(lang) else {
+            return false;
+        };
+        let names = entry.names.iter().map(String::as_str).collect::<Vec<_>>();
+        entry.inner.1 = names_to_classes(&names, style);
+        true
+    }
+
+    /// Registers a profile of a language, a names set giving the classes of a render
+    /// with [`Languages::render_profile`], such as a coarse set of a few names next to
+    /// the full one; returns `false` when the language is unknown.
+    ///
+    /// Each recognized capture name takes the class of the longest profile name it is or
+    /// starts with, followed by a `.`, so with a profile of `function`, captures named
+    /// `function.method` get `class=function`. Captures matching no profile name get no
+    /// class.
+    pub fn set_profile(&mut self, lang: &str, profile: &str, names: &[&str]) -> bool {
+        let Some(entry) = self.lookup_mut(lang) else {
+            return false;
+        };
+        let style = ClassStyle::default();
+        let classes = entry
+            .names
+            .iter()
+            .map(|name| {
+                names
+                    .iter()
+                    .filter(|n| {
+                        name.strip_prefix(**n)
+                            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
                     })
-                    .ok();
-            };
+                    .max_by_key(|n| n.len())
+                    .map(|n| style.attr(n))
+                    .unwrap_or_default()
+            })
+            .collect();
+        entry.profiles.insert(profile.to_string(), classes);
+        true
+    }
+
+    /// Renders with the classes of a profile, see [`Languages::set_profile`]; `None`
+    /// when the language or the profile is unknown.
+    pub fn render_profile(&self, lang: &str, profile: &str, source: &[u8]) -> Option<String> {
+        let classes = self.lookup(lang)?.profiles.get(profile)?;
+        let mut s = String::with_capacity(source.len() * 2 + lang.len() + 32);
+        self.write_html(
+            lang,
+            source,
+            &RenderOptions::default(),
+            Some(classes),
+            &mut s,
+        )
+        .ok()?;
+        Some(s)
+    }
+
+    fn highlighter(&self) -> Highlighter {
+        #[cfg(test)]
+        self.highlighters
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Highlighter::new()
+    }
+
+    fn scratch(&self) -> Scratch {
+        Scratch {
+            highlighter: self.highlighter(),
+            buffers: Buffers::default(),
+            highlights: 0,
+        }
+    }
+
+    /// Calls `f` with an idle highlighter and buffers of the pool, or new ones, and
+    /// returns them to the pool afterwards.
+    ///
+    /// Highlighters keep no state between highlights besides their allocations, and
+    /// buffers are cleared before use, so concurrent renders each take their own and
+    /// sequential ones share them. They are dropped rather than returned when `f` panics,
+    /// or when [`MAX_POOLED`] are already idle.
+    fn with_scratch<R>(&self, f: impl FnOnce(&mut Scratch) -> R) -> R {
+        let pooled = self.pool.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let mut scratch = pooled.unwrap_or_else(|| self.scratch());
+        let result = f(&mut scratch);
+        let mut pool = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+        if pool.len() < MAX_POOLED {
+            pool.push(scratch);
+        }
+        result
+    }
+
+    fn entry<'s>(&'s self, lang: &str) -> Result<&'s Entry, Error> {
+        self.lookup(lang)
+            .ok_or_else(|| Error::UnknownLanguage(lang.to_string()))
+    }
+
+    /// Calls `f` with each highlighted or plain source range and its innermost capture
+    /// name, injected captures included.
+    fn for_each_token<'s>(
+        &'s self,
+        lang: &str,
+        source: &[u8],
+        mut f: impl FnMut(Range<usize>, Option<&'s str>),
+    ) -> Result<(), Error> {
+        self.for_each_capture(lang, source, |range, capture| {
+            f(
+                range,
+                capture.and_then(|i| self.names.get(i)).map(String::as_str),
+            )
+        })
+    }
+
+    /// Calls `f` with each highlighted or plain source range and its innermost `Highlight`
+    /// index, in source order.
+    fn for_each_capture(
+        &self,
+        lang: &str,
+        source: &[u8],
+        mut f: impl FnMut(Range<usize>, Option<usize>),
+    ) -> Result<(), Error> {
+        let mut stack = Vec::new();
+        self.for_each_event(lang, source, |event| match event {
+            HighlightEvent::HighlightStart(h) => stack.push(h.0),
+            HighlightEvent::HighlightEnd => {
+                stack.pop();
+            }
+            HighlightEvent::Source { start, end } => f(start..end, stack.last().copied()),
+        })
+    }
+
+    /// Calls `f` with each raw highlight event, stopping at the first highlighter error.
+    ///
+    /// This takes a callback rather than returning an iterator because the events borrow
+    /// a highlighter that lives for the duration of the call. Injections are resolved as
+    /// in [`Languages::render`], up to the registry's maximum depth. The `Source` ranges
+    /// are contiguous and cover the whole source. `Highlight` indices are shared by the
+    /// registry's languages: [`Languages::capture_name`] names those the language itself
+    /// recognizes.
+    pub fn for_each_event(
+        &self,
+        lang: &str,
+        source: &[u8],
+        mut f: impl FnMut(HighlightEvent),
+    ) -> Result<(), Error> {
+        let entry = self.entry(lang)?;
+
+        self.with_scratch(|scratch| {
+            for event in self.highlight(&mut scratch.highlighter, entry, source, None)? {
+                f(event?);
+            }
+            Ok(())
+        })
+    }
+
+    /// The configured capture name of a `Highlight` index.
+    pub fn capture_name(&self, lang: &str, highlight: Highlight) -> Option<&str> {
+        self.lookup(lang)?
+            .names
+            .get(highlight.0)
+            .filter(|name| !name.is_empty())
+            .map(String::as_str)
+    }
+
+    /// Counts the highlights of each capture name in `source`, `None` for an unknown
+    /// language or a highlighter error.
+    ///
+    /// Injected captures count too, and only the names of [`NAMES`] are counted.
+    pub fn capture_stats(&self, lang: &str, source: &[u8]) -> Option<HashMap<&'static str, usize>> {
+        let mut stats = HashMap::new();
+        self.for_each_event(lang, source, |event| {
+            if let HighlightEvent::HighlightStart(h) = event {
+                let name = self.names.get(h.0);
+                if let Some(name) = NAMES.iter().find(|n| Some(**n) == name.map(String::as_str)) {
+                    *stats.entry(*name).or_insert(0) += 1;
+                }
+            }
+        })
+        .ok()?;
+        Some(stats)
+    }
+
+    /// Whether a language's configuration has injection patterns, `None` for an unknown
+    /// language.
+    ///
+    /// Only `@injection.content` captures count, as tree-sitter-highlight ignores others.
+    pub fn has_injections(&self, lang: &str) -> Option<bool> {
+        self.has_capture(lang, |name| name == "injection.content")
+    }
+
+    /// Whether a language's configuration has local variable patterns, `None` for an
+    /// unknown language.
+    ///
+    /// Only `@local.scope`, `@local.definition` and `@local.reference` captures count.
+    pub fn has_locals(&self, lang: &str) -> Option<bool> {
+        self.has_capture(lang, |name| {
+            matches!(
+                name,
+                "local.scope" | "local.definition" | "local.definition-value" | "local.reference"
+            )
+        })
+    }
+
+    fn has_capture(&self, lang: &str, f: impl Fn(&str) -> bool) -> Option<bool> {
+        let (config, _) = &self.lookup(lang)?.inner;
+        Some(config.query.capture_names().iter().any(|name| f(name)))
+    }
+
+    /// Reports how a theme covers the captures of a language's queries, see
+    /// [`Theme::coverage`].
+    ///
+    /// The `injection.*` and `local.*` captures and those starting with `_` only drive the
+    /// highlighter and are left out.
+    pub fn theme_coverage(&self, lang: &str, theme: &Theme) -> Option<Coverage> {
+        let (config, _) = &self.lookup(lang)?.inner;
+        let mut names = config
+            .query
+            .capture_names()
+            .iter()
+            .map(String::as_str)
+            .filter(|name| {
+                !(name.starts_with("injection.")
+                    || name.starts_with("local.")
+                    || name.starts_with('_'))
+            })
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        Some(theme.coverage(&names))
+    }
+
+    /// Parses the source with the grammar of a language, for analyses beyond highlighting.
+    pub fn parse(&self, lang: &str, source: &[u8]) -> Option<tree_sitter::Tree> {
+        self.tree(self.lookup(lang)?, source).ok()
+    }
+
+    /// Parses the source with the grammar of a registered language.
+    fn tree(&self, entry: &Entry, source: &[u8]) -> Result<tree_sitter::Tree, Error> {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(entry.inner.0.language)
+            .map_err(|_| tree_sitter_highlight::Error::InvalidLanguage)?;
+        Ok(parser
+            .parse(source, None)
+            .ok_or(tree_sitter_highlight::Error::Unknown)?)
+    }
+
+    pub fn render(&self, lang: &str, source: &[u8]) -> Option<String> {
+        self.render_with(lang, source, &RenderOptions::default())
+            .ok()
+    }
+
+    /// Renders a `&str` source, see [`Languages::render`].
+    pub fn render_str(&self, lang: &str, source: &str) -> Option<String> {
+        self.render(lang, source.as_bytes())
+    }
+
+    /// Renders with case-insensitive occurrences of the terms wrapped in `<mark>`,
+    /// so `é` matches `É`.
+    pub fn render_with_search(&self, lang: &str, source: &[u8], terms: &[&str]) -> Option<String> {
+        self.render_with(lang, source, &RenderOptions::new().search_terms(terms))
+            .ok()
+    }
+
+    /// Renders and returns the HTML with an ETag, a hex digest of everything that affects
+    /// the output: the crate version, the language, the whole registry, since injected
+    /// languages render too, and the source.
+    pub fn render_with_etag(&self, lang: &str, source: &[u8]) -> Option<(String, String)> {
+        let html = self.render(lang, source)?;
+        let mut hasher = Fingerprint::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        lang.hash(&mut hasher);
+        self.fingerprint().hash(&mut hasher);
+        source.hash(&mut hasher);
+        Some((html, hasher.hex()))
+    }
+
+    /// A digest of everything in the registry that affects renders: the languages, their
+    /// aliases and injection remaps, the patterns and captures of their queries, their
+    /// capture names and classes, and the depth limit.
+    ///
+    /// Lazy languages count by name, whether built or not, and injection resolvers and
+    /// overrides by how many were set.
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = Fingerprint::new();
+        let mut langs = self.inner.iter().collect::<Vec<_>>();
+        langs.sort_unstable_by_key(|(lang, _)| **lang);
+        for (lang, entry) in langs {
+            let query = &entry.inner.0.query;
+            lang.hash(&mut hasher);
+            query.capture_names().hash(&mut hasher);
+            query.pattern_count().hash(&mut hasher);
+            for i in 0..query.pattern_count() {
+                query.start_byte_for_pattern(i).hash(&mut hasher);
+            }
+            entry.names.hash(&mut hasher);
+            entry.inner.1.hash(&mut hasher);
+        }
+        let mut lazy = self.lazy.keys().collect::<Vec<_>>();
+        lazy.sort_unstable();
+        lazy.hash(&mut hasher);
+        let mut aliases = self.aliases.iter().collect::<Vec<_>>();
+        aliases.sort_unstable();
+        aliases.hash(&mut hasher);
+        let mut remap = self.injection_remap.iter().collect::<Vec<_>>();
+        remap.sort_unstable();
+        remap.hash(&mut hasher);
+        self.names.hash(&mut hasher);
+        self.max_injection_depth.hash(&mut hasher);
+        self.hooks.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Renders and returns the HTML with the time the parse, highlight and render took,
+    /// for diagnosing slow grammars.
+    ///
+    /// A lazy language is built before the clock starts. Not available on
+    /// `wasm32-unknown-unknown`, which has no clock.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn render_timed(&self, lang: &str, source: &[u8]) -> Option<(String, std::time::Duration)> {
+        self.lookup(lang)?;
+        let start = std::time::Instant::now();
+        let html = self.render(lang, source)?;
+        Some((html, start.elapsed()))
+    }
+
+    /// Appends the rendered HTML to `buf`, returning `false` and leaving `buf` untouched
+    /// when the language is unknown or highlighting fails.
+    pub fn render_into(&self, lang: &str, source: &[u8], buf: &mut String) -> bool {
+        self.write_html(lang, source, &RenderOptions::default(), None, buf)
+            .is_ok()
+    }
+
+    /// Renders into `w`, for `Display` impls and templates; errors, such as an unknown
+    /// language, are [`fmt::Error`], see [`Languages::render_to`] for the cause.
+    pub fn render_fmt(&self, lang: &str, source: &[u8], w: &mut impl fmt::Write) -> fmt::Result {
+        self.render_to(lang, source, &RenderOptions::default(), w)
+            .map_err(|_| fmt::Error)
+    }
+
+    pub fn render_with(
+        &self,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+    ) -> Result<String, Error> {
+        let mut s = String::with_capacity(source.len() * 2 + lang.len() + 32);
+        self.render_to(lang, source, options, &mut s)?;
+        Ok(s)
+    }
+
+    /// Renders like [`Languages::render_with`], along with warnings about the render, for
+    /// CI checks catching languages that are registered but highlight nothing.
+    pub fn render_report(
+        &self,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+    ) -> Result<RenderReport, Error> {
+        let mut html = String::with_capacity(source.len() * 2 + lang.len() + 32);
+        let highlights = self.with_scratch(|scratch| {
+            self.write_html_with(scratch, lang, source, options, None, &mut html)
+                .map(|()| scratch.highlights)
+        })?;
+        let mut warnings = Vec::new();
+        if highlights == 0 && !source.iter().all(u8::is_ascii_whitespace) {
+            warnings.push(RenderWarning::NoHighlights);
+        }
+        Ok(RenderReport { html, warnings })
+    }
+
+    /// Renders a batch of `(lang, source)` snippets, such as the code fences of a page.
+    ///
+    /// One highlighter of the pool is shared by the whole batch. The results are in input
+    /// order, and a failing snippet, such as one in an unknown language, only fails its
+    /// own slot.
+    pub fn render_many<'s>(
+        &self,
+        items: impl IntoIterator<Item = (&'s str, &'s [u8])>,
+        options: &RenderOptions,
+    ) -> Vec<Result<String, Error>> {
+        self.with_scratch(|scratch| {
+            items
+                .into_iter()
+                .map(|(lang, source)| {
+                    let mut s = String::with_capacity(source.len() * 2 + lang.len() + 32);
+                    self.write_html_with(scratch, lang, source, options, None, &mut s)?;
+                    Ok(s)
+                })
+                .collect()
+        })
+    }
+
+    /// Renders into `out`, see [`Languages::render_with`].
+    ///
+    /// Nothing is written unless highlighting succeeds, and errors from `out` are
+    /// returned as [`Error::Write`].
+    pub fn render_to(
+        &self,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), Error> {
+        self.write_html(lang, source, options, None, out)
+    }
+
+    /// Renders with extra attributes on each highlight span, given as name/value pairs
+    /// for its capture name.
+    ///
+    /// Values are quoted and escaped, and pairs whose name is not a valid attribute name
+    /// are dropped, so untrusted values cannot inject markup.
+    pub fn render_with_data_attrs(
+        &self,
+        lang: &str,
+        source: &[u8],
+        f: impl Fn(&str) -> Vec<(String, String)>,
+    ) -> Option<String> {
+        let classes = self
+            .injection_classes(Some(self.lookup(lang)?))
+            .iter()
+            .zip(&self.names)
+            .map(|(class, name)| {
+                let mut attrs = class.clone();
+                for (key, value) in f(name) {
+                    if !is_attr_name(&key) {
+                        continue;
+                    }
+                    if !attrs.is_empty() {
+                        attrs.push(' ');
+                    }
+                    attrs.push_str(&key);
+                    attrs.push_str("=\"");
+                    value.chars().for_each(|c| html::escape(&mut attrs, c));
+                    attrs.push('"');
+                }
+                attrs
+            })
+            .collect::<Vec<_>>();
+
+        let mut s = String::new();
+        self.write_html(
+            lang,
+            source,
+            &RenderOptions::default(),
+            Some(&classes),
+            &mut s,
+        )
+        .ok()?;
+        Some(s)
+    }
+
+    /// Renders only a byte range of the source, such as the range of a `tree_sitter::Node`,
+    /// classified from the parse of the whole source.
+    ///
+    /// Captures that start before the range are opened at its start, and those that end
+    /// after it are closed at its end.
+    pub fn render_range(&self, lang: &str, source: &[u8], range: Range<usize>) -> Option<String> {
+        let mut events = Vec::new();
+        let mut stack = Vec::new();
+        let mut emitted = 0;
+        self.for_each_event(lang, source, |event| match event {
+            HighlightEvent::HighlightStart(h) => stack.push(h),
+            HighlightEvent::HighlightEnd => {
+                stack.pop();
+                if emitted > stack.len() {
+                    events.push(HighlightEvent::HighlightEnd);
+                    emitted -= 1;
+                }
+            }
+            HighlightEvent::Source { start, end } => {
+                let (start, end) = (start.max(range.start), end.min(range.end));
+                if start < end {
+                    events.extend(
+                        stack[emitted..]
+                            .iter()
+                            .map(|h| HighlightEvent::HighlightStart(*h)),
+                    );
+                    emitted = stack.len();
+                    events.push(HighlightEvent::Source { start, end });
+                }
+            }
+        })
+        .ok()?;
+        events.extend(std::iter::repeat_n(HighlightEvent::HighlightEnd, emitted));
+
+        let classes = self.injection_classes(Some(self.lookup(lang)?));
+        let mut s = String::new();
+        write_events(
+            lang,
+            source,
+            events.into_iter().map(Ok),
+            &RenderOptions::default(),
+            &classes,
+            &mut Buffers::default(),
+            &mut s,
+        )
+        .ok()?;
+        Some(s)
+    }
+
+    /// Renders the HTML of each source line, without the `<pre><code>` wrapper.
+    ///
+    /// Blank lines are included, so line `n` is at index `n - 1`. Each element ends with
+    /// its line break, and concatenating them inside the wrapper gives
+    /// [`Languages::render_with`]; collapsed ranges span lines and are not applied.
+    pub fn render_lines(
+        &self,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+    ) -> Result<Vec<String>, Error> {
+        self.with_scratch(|scratch| self.lines_with(scratch, lang, source, options))
+    }
+
+    /// Like [`Languages::render_lines`], with a given highlighter and buffers.
+    pub(crate) fn lines_with(
+        &self,
+        scratch: &mut Scratch,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+    ) -> Result<Vec<String>, Error> {
+        let entry = self.entry(lang)?;
+        let source = &normalize_source(source, options.zero_width);
+
+        let Scratch {
+            highlighter,
+            buffers,
+            ..
+        } = scratch;
+        if let Some(events) = blank(source, options) {
+            return html_lines(source, events, options, &[], buffers);
+        }
+        let events = self.highlight(highlighter, entry, source, options.max_injection_depth)?;
+        let classes = self.injection_classes(Some(entry));
+        if options.rewrites_events() {
+            let events = events.collect::<Result<Vec<_>, _>>()?;
+            let (events, classes) =
+                self.rewrite(entry, source, events, options, classes.into_owned())?;
+            return html_lines(
+                source,
+                events.into_iter().map(Ok),
+                options,
+                &classes,
+                buffers,
+            );
+        }
+        html_lines(source, events, options, &classes, buffers)
+    }
+
+    /// Applies the options that rewrite the highlight events of a render, in order:
+    /// overrides, local links, bracket pairs and parse errors. The attributes of the
+    /// highlights they add are appended to `classes`.
+    ///
+    /// Every kind of render goes through this, so their outputs agree.
+    pub(crate) fn rewrite(
+        &self,
+        entry: &Entry,
+        source: &[u8],
+        mut events: Vec<HighlightEvent>,
+        options: &RenderOptions,
+        mut classes: Vec<String>,
+    ) -> Result<(Vec<HighlightEvent>, Vec<String>), Error> {
+        if !options.overrides.is_empty() {
+            events = overlay::apply_overrides(&entry.names, source, events, options)?;
+        }
+        if options.link_locals {
+            events = locals::link(&entry.inner.0, source, events, &mut classes);
+        }
+        if options.pair_brackets {
+            events = brackets::pair(&self.names, source, events, &mut classes);
+        }
+        if options.mark_errors {
+            let tree = self.tree(entry, source)?;
+            events = errors::mark(&tree, events, &mut classes);
+        }
+        Ok((events, classes))
+    }
+
+    /// Renders the HTML of each source line with the default options, see
+    /// [`Languages::render_lines`], whose `bare_lines` option leaves out the line spans.
+    pub fn render_line_vec(&self, lang: &str, source: &[u8]) -> Option<Vec<String>> {
+        self.render_lines(lang, source, &RenderOptions::default())
+            .ok()
+    }
+
+    /// Writes the rendered HTML to `out`, only once highlighting has succeeded.
+    ///
+    /// `classes` overrides the registered attributes of each highlight.
+    fn write_html(
+        &self,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+        classes: Option<&[String]>,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), Error> {
+        self.with_scratch(|scratch| {
+            self.write_html_with(scratch, lang, source, options, classes, out)
+        })
+    }
+
+    /// Like [`Languages::write_html`], with a given highlighter and buffers.
+    pub(crate) fn write_html_with(
+        &self,
+        scratch: &mut Scratch,
+        lang: &str,
+        source: &[u8],
+        options: &RenderOptions,
+        classes: Option<&[String]>,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), Error> {
+        let entry = self.entry(lang)?;
+        let source = &normalize_source(source, options.zero_width);
+
+        let Scratch {
+            highlighter,
+            buffers,
+            highlights,
+        } = scratch;
+        *highlights = 0;
+        if let Some(events) = blank(source, options) {
+            return write_events(lang, source, events, options, &[], buffers, out);
         }
+        let events = self
+            .highlight(highlighter, entry, source, options.max_injection_depth)?
+            .inspect(|event| {
+                if let Ok(HighlightEvent::HighlightStart(_)) = event {
+                    *highlights += 1;
+                }
+            });
+        let registered = self.injection_classes(Some(entry));
+        let classes = classes.unwrap_or(&registered);
+        if options.rewrites_events() {
+            let events = events.collect::<Result<Vec<_>, _>>()?;
+            let (events, classes) =
+                self.rewrite(entry, source, events, options, classes.to_vec())?;
+            return write_events(
+                lang,
+                source,
+                events.into_iter().map(Ok),
+                options,
+                &classes,
+                buffers,
+                out,
+            );
+        }
+        write_events(lang, source, events, options, classes, buffers, out)
+    }
+}
+
+impl<'a> Index<&str> for Languages<'a> {
+    type Output = (HighlightConfiguration, Vec<String>);
+
+    /// Returns the configuration and classes of a language, see [`Languages::get`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the language is not registered.
+    fn index(&self, lang: &str) -> &Self::Output {
+        match self.lookup(lang) {
+            Some(entry) => &entry.inner,
+            None => panic!("unknown language `{lang}`"),
+        }
+    }
+}
 
-        None
+/// The events of a whitespace-only source, which has nothing to highlight, so it need
+/// not be parsed; `None` for other sources, or when overrides may style the whitespace.
+fn blank(
+    source: &[u8],
+    options: &RenderOptions,
+) -> Option<impl Iterator<Item = Result<HighlightEvent, tree_sitter_highlight::Error>>> {
+    if !source.iter().all(u8::is_ascii_whitespace) || !options.overrides.is_empty() {
+        return None;
     }
+    let end = source.len();
+    Some(
+        (end > 0)
+            .then_some(Ok(HighlightEvent::Source { start: 0, end }))
+            .into_iter(),
+    )
+}
+
+/// Writes the HTML of highlight events to `out`, only once they have all succeeded.
+///
+/// The lines are written from the writer's buffers, in their wrappers, straight to
+/// `out`, so the output is only held once besides the escaped source text.
+fn write_events(
+    lang: &str,
+    source: &[u8],
+    events: impl Iterator<Item = Result<HighlightEvent, tree_sitter_highlight::Error>>,
+    options: &RenderOptions,
+    classes: &[String],
+    buffers: &mut Buffers,
+    out: &mut impl fmt::Write,
+) -> Result<(), Error> {
+    let mut renderer = html_writer(source, options, classes, buffers);
+    let written = renderer.render(events, source).and_then(|()| {
+        let count = renderer.lines().filter(|line| !line.continuation).count();
+        let mut collapse = Collapse::new(options, count);
+        let rows = Rows::new(source, options);
+        let out = &mut Embed {
+            out,
+            context: options.context,
+        };
+
+        write!(out, "<pre class=language-{lang}><code>")?;
+        let mut row = String::new();
+        for line in renderer.lines() {
+            if !line.continuation {
+                collapse.line(out, line.number)?;
+            }
+            row.clear();
+            rows.push(&mut row, &line);
+            out.write_str(&row)?;
+        }
+        collapse.close(out)?;
+        out.write_str("</code></pre>")?;
+        Ok(())
+    });
+    renderer.recycle(buffers);
+    written
+}
+
+/// Renders highlight events to the HTML of each source line, including its line break.
+///
+/// Each row of a line, more than one when wrapped, is wrapped in a line span unless
+/// `options.bare_lines` is set.
+fn html_lines(
+    source: &[u8],
+    events: impl Iterator<Item = Result<HighlightEvent, tree_sitter_highlight::Error>>,
+    options: &RenderOptions,
+    classes: &[String],
+    buffers: &mut Buffers,
+) -> Result<Vec<String>, Error> {
+    let mut renderer = html_writer(source, options, classes, buffers);
+    let rendered = renderer.render(events, source).map(|()| {
+        let rows = Rows::new(source, options);
+        let mut lines: Vec<String> = Vec::new();
+        for line in renderer.lines() {
+            if !line.continuation {
+                lines.push(String::new());
+            }
+            if let Some(s) = lines.last_mut() {
+                rows.push(s, &line);
+            }
+        }
+        lines
+    });
+    renderer.recycle(buffers);
+    rendered
+}
+
+/// A writer configured per the options, rendering into `buffers`, to which the caller
+/// returns them for the next call, failed or not.
+fn html_writer<'c>(
+    source: &[u8],
+    options: &RenderOptions,
+    classes: &'c [String],
+    buffers: &mut Buffers,
+) -> HtmlWriter<'c> {
+    HtmlWriter::new(classes)
+        .buffers(buffers)
+        .marks(find_terms(source, &options.search_terms))
+        .wrap(options.wrap_column)
+        .strict(options.strict)
+        .unknown_class(options.unknown_class.as_deref())
+        .token_element(options.token_element.as_deref())
+        .show_whitespace(options.show_whitespace)
+        .escape(options.escape)
+        .zero_width(options.zero_width)
+        .indent_guides(options.indent_guides)
 }
 
-fn names_to_classes(names: &[&str]) -> Vec<String> {
+/// Whether `name` can be written as an HTML attribute name as is.
+fn is_attr_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+}
+
+/// The `class=` attribute of each capture, computed once so that rendering only looks
+/// them up.
+fn names_to_classes(names: &[&str], style: &ClassStyle) -> Vec<String> {
     names
         .iter()
         .map(|n| {
-            let mut s = String::new();
-            s.push_str("class=");
-            s.push_str(n);
-            s
+            if n.is_empty() {
+                String::new()
+            } else {
+                style.attr(n)
+            }
         })
         .collect()
 }
@@ -156,6 +1531,1051 @@ mod tests {
     use super::*;
     use std::error::Error;
 
+    pub(crate) fn rust() -> Languages<'static> {
+        let mut languages = Languages::new();
+        languages.insert(
+            "rust",
+            HighlightConfiguration::new(
+                tree_sitter_rust::language(),
+                include_str!("../queries/rust/highlights.scm"),
+                include_str!("../queries/rust/injections.scm"),
+                include_str!("../queries/rust/locals.scm"),
+            )
+            .unwrap(),
+        );
+        languages
+    }
+
+    /// Rust with markdown, whose fences inject it.
+    pub(crate) fn markdown() -> Languages<'static> {
+        let mut languages = rust();
+        languages
+            .insert(
+                "markdown",
+                HighlightConfiguration::new(
+                    tree_sitter_md::language(),
+                    include_str!("../queries/markdown/highlights.scm"),
+                    include_str!("../queries/markdown/injections.scm"),
+                    "",
+                )
+                .unwrap(),
+            )
+            .insert(
+                "markdown_inline",
+                HighlightConfiguration::new(
+                    tree_sitter_md::inline_language(),
+                    include_str!("../queries/markdown_inline/highlights.scm"),
+                    "",
+                    "",
+                )
+                .unwrap(),
+            );
+        languages
+    }
+
+    #[test]
+    fn capture_stats() {
+        let languages = rust();
+        let stats = languages
+            .capture_stats("rust", b"// one\nfn a() {}\nfn b() { let s = \"x\"; }\n")
+            .unwrap();
+        assert_eq!(stats.get("comment"), Some(&1));
+        assert_eq!(stats.get("keyword.function"), Some(&2));
+        assert_eq!(stats.get("function"), Some(&2));
+        assert_eq!(stats.get("keyword"), Some(&1));
+        assert_eq!(stats.get("string"), Some(&1));
+        assert_eq!(stats.get("number"), None);
+        assert!(languages.capture_stats("cobol", b"").is_none());
+    }
+
+    #[test]
+    fn insert_lazy() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let built = Arc::new(AtomicUsize::new(0));
+        let mut languages = Languages::new();
+        languages
+            .insert_lazy("rust", {
+                let built = built.clone();
+                move || {
+                    built.fetch_add(1, Ordering::Relaxed);
+                    HighlightConfiguration::new(
+                        tree_sitter_rust::language(),
+                        include_str!("../queries/rust/highlights.scm"),
+                        "",
+                        "",
+                    )
+                }
+            })
+            .insert_lazy("broken", || {
+                HighlightConfiguration::new(tree_sitter_rust::language(), "(nope) @x", "", "")
+            });
+        assert_eq!(built.load(Ordering::Relaxed), 0);
+
+        let first = languages.render("rust", b"let a = 1;\n").unwrap();
+        let second = languages.render("rust", b"let a = 1;\n").unwrap();
+        assert_eq!(first, second);
+        assert!(first.contains("<span class=keyword>let</span>"));
+        assert!(languages.get("rust").is_some());
+        assert_eq!(built.load(Ordering::Relaxed), 1);
+        assert_eq!(first, rust().render("rust", b"let a = 1;\n").unwrap());
+
+        assert!(languages.get("broken").is_none());
+        assert!(matches!(
+            languages.render_with("broken", b"", &RenderOptions::new()),
+            Err(crate::Error::UnknownLanguage(_))
+        ));
+    }
+
+    #[test]
+    fn merge() {
+        let mut languages = rust();
+        let mut other = rust();
+        let (config, _) = other.inner.remove("rust").unwrap().inner;
+        other.insert("rs", config);
+        let mut conflicting = rust();
+        conflicting
+            .get_mut("rust")
+            .unwrap()
+            .1
+            .fill("class=merged".to_string());
+
+        languages.merge(other).merge(conflicting);
+        assert_eq!(languages.inner.len(), 2);
+        assert!(languages
+            .render("rs", b"let a;")
+            .unwrap()
+            .starts_with("<pre class=language-rs>"));
+        assert!(languages
+            .render("rust", b"let a;")
+            .unwrap()
+            .contains("<span class=merged>let</span>"));
+    }
+
+    #[test]
+    fn injections() {
+        let mut languages = Languages::new();
+        languages
+            .insert(
+                "html",
+                HighlightConfiguration::new(
+                    tree_sitter_html::language(),
+                    "(tag_name) @tag\n(attribute_name) @tag.attribute",
+                    r#"((script_element (raw_text) @injection.content)
+                        (#set! injection.language "js"))
+                       ((style_element (raw_text) @injection.content)
+                        (#set! injection.language "CSS"))"#,
+                    "",
+                )
+                .unwrap(),
+            )
+            .insert(
+                "javascript",
+                HighlightConfiguration::new(
+                    tree_sitter_javascript::language(),
+                    "[\"const\" \"return\"] @keyword\n(number) @number",
+                    "",
+                    "",
+                )
+                .unwrap(),
+            )
+            .insert_with_names(
+                "css",
+                HighlightConfiguration::new(
+                    tree_sitter_css::language(),
+                    "(property_name) @property.definition\n(integer_value) @number",
+                    "",
+                    "",
+                )
+                .unwrap(),
+                &["property.definition", "number"],
+            );
+        assert!(languages.injected("js").is_some());
+        assert!(languages.injected("CSS").is_some());
+        assert!(languages.injected("python").is_none());
+        // The names only CSS recognizes come after the shared ones.
+        let definition = Highlight(NAMES.len());
+        assert_eq!(
+            languages.capture_name("css", definition),
+            Some("property.definition")
+        );
+        assert_eq!(languages.capture_name("css", Highlight(0)), None);
+        assert_eq!(languages.capture_name("html", definition), None);
+        let classes = languages.injection_classes(Some(&languages.inner["html"]));
+        assert_eq!(classes.len(), NAMES.len() + 1);
+        assert_eq!(classes[NAMES.len()], "class=property.definition");
+
+        let html = languages
+            .render(
+                "html",
+                b"<script>const a = 1;</script><style>p { margin: 0 }</style>",
+            )
+            .unwrap();
+        assert!(html.contains("<span class=tag>script</span>"));
+        assert!(html.contains("<span class=keyword>const</span>"));
+        assert!(html.contains("<span class=number>1</span>"));
+        assert!(html.contains("<span class=property.definition>margin</span>"));
+        assert!(html.contains("<span class=number>0</span>"));
+
+        // So do those of every other render.
+        let source = b"<style>p { margin: 0 }</style>";
+        let html = languages
+            .render_with_data_attrs("html", source, |name| {
+                vec![("data-capture".to_string(), name.to_string())]
+            })
+            .unwrap();
+        assert!(html.contains(
+            "<span class=property.definition data-capture=\"property.definition\">margin</span>"
+        ));
+        let html = languages.render_range("html", source, 11..17).unwrap();
+        assert!(html.contains("<span class=property.definition>margin</span>"));
+        let html = languages
+            .render_with_overlay("html", source, &[(19..20, "property.definition")])
+            .unwrap();
+        assert!(html.contains("<span class=property.definition>margin</span>"));
+        assert!(html.contains("<span class=property.definition>0</span>"));
+    }
+
+    #[test]
+    fn fences() {
+        let languages = markdown();
+        for name in ["rust", "Rust", "rs", "{.rust}", "rust,ignore", " RUST {.x}"] {
+            assert!(languages.injected(name).is_some(), "{name}");
+        }
+        for name in ["bash", "brainfuck", ""] {
+            assert!(languages.injected(name).is_none(), "{name}");
+        }
+
+        let html = languages
+            .render_str("markdown", include_str!("../fixtures/fences.md"))
+            .unwrap();
+        let start = html.find("before the code").unwrap();
+        let end = html.find("bash").unwrap();
+        let (prose, block, rest) = (&html[..start], &html[start..end], &html[end..]);
+        assert!(prose.contains("<span class=text.title>Fences</span>"));
+        assert!(prose.contains("<span class=text.emphasis>"));
+        assert!(block.contains("<span class=keyword>let</span>"));
+        assert!(block.contains("<span class=number>42</span>"));
+        for part in [prose, rest] {
+            assert!(!part.contains("class=keyword"), "{part}");
+        }
+        assert!(rest.contains("if true; then echo fn; fi"));
+        assert!(rest.contains("++[&gt;+&lt;-]"));
+    }
+
+    #[test]
+    fn injection_remap() {
+        let mut languages = markdown();
+        let doc = b"```rustx\nlet a;\n```\n";
+        assert!(languages.injected("rustx").is_none());
+        let html = languages.render("markdown", doc).unwrap();
+        assert!(!html.contains("class=keyword"), "{html}");
+
+        languages.set_injection_remap(HashMap::from([("RustX", "rust")]));
+        assert!(languages.injected("rustx").is_some());
+        assert!(languages.injected("{.rustx}").is_some());
+        assert!(languages.lookup("rustx").is_none());
+        let html = languages.render("markdown", doc).unwrap();
+        assert!(html.contains("<span class=keyword>let</span>"), "{html}");
+    }
+
+    #[test]
+    fn injection_depth() {
+        let mut languages = markdown();
+        let doc = "`````markdown\n````markdown\n```rust\nlet a;\n```\n````\n`````\n";
+        let render = |languages: &Languages, options: &RenderOptions| {
+            languages
+                .render_with("markdown", doc.as_bytes(), options)
+                .unwrap()
+        };
+
+        assert!(
+            render(&languages, &RenderOptions::new()).contains("<span class=keyword>let</span>")
+        );
+        for html in [
+            render(&languages, &RenderOptions::new().max_injection_depth(1)),
+            render(languages.set_max_injection_depth(1), &RenderOptions::new()),
+        ] {
+            assert!(html.contains("let a;"), "{html}");
+            assert!(!html.contains("class=keyword"), "{html}");
+        }
+        let html = render(&languages, &RenderOptions::new().max_injection_depth(2));
+        assert!(html.contains("<span class=keyword>let</span>"));
+        let html = render(&languages, &RenderOptions::new().max_injection_depth(0));
+        assert!(!html.contains("class=keyword"));
+    }
+
+    #[test]
+    fn nested_macro_injections() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let levels = 600;
+        let source = format!("{}x{};", "a!(".repeat(levels), ")".repeat(levels));
+        let resolved = |depth: usize| {
+            let count = Arc::new(AtomicUsize::new(0));
+            let mut languages = rust();
+            languages.set_injection_resolver({
+                let count = count.clone();
+                move |_| {
+                    count.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+            });
+            let options = RenderOptions::new().max_injection_depth(depth);
+            let html = languages
+                .render_with("rust", source.as_bytes(), &options)
+                .unwrap();
+            assert_eq!(extract_text(&html), format!("{source}\n"));
+            count.load(Ordering::Relaxed)
+        };
+        assert_eq!(resolved(0), 0);
+        assert!((1..=INJECTIONS_PER_LEVEL).contains(&resolved(1)));
+        assert!(resolved(2) <= 2 * INJECTIONS_PER_LEVEL);
+    }
+
+    #[test]
+    fn injection_override() {
+        let mut languages = markdown();
+        let (config, _) = markdown().inner.remove("markdown").unwrap().inner;
+        languages.insert("notes", config);
+        assert!(languages.set_injection_override("Markdown", |_| InjectionDecision::PlainText));
+        assert!(!languages.set_injection_override("cobol", |_| InjectionDecision::Default));
+        let doc = "```rust\nlet a;\n```\n";
+
+        let html = languages.render_str("markdown", doc).unwrap();
+        assert!(html.contains("let a;"), "{html}");
+        assert!(!html.contains("class=keyword"), "{html}");
+        let html = languages.render_str("notes", doc).unwrap();
+        assert!(html.contains("<span class=keyword>let</span>"), "{html}");
+
+        languages.set_injection_override("notes", |name| match name {
+            "text" => InjectionDecision::UseLanguage("rs".to_string()),
+            _ => InjectionDecision::Default,
+        });
+        let html = languages
+            .render_str("notes", "```text\nlet a;\n```\n")
+            .unwrap();
+        assert!(html.contains("<span class=keyword>let</span>"), "{html}");
+        let html = languages
+            .render_with(
+                "notes",
+                b"```text\nlet a;\n```\n",
+                &RenderOptions::new().max_injection_depth(0),
+            )
+            .unwrap();
+        assert!(!html.contains("class=keyword"), "{html}");
+    }
+
+    #[test]
+    fn aliases() {
+        let mut languages = markdown();
+        languages
+            .insert(
+                "html",
+                HighlightConfiguration::new(
+                    tree_sitter_html::language(),
+                    "(tag_name) @tag",
+                    r#"((script_element (raw_text) @injection.content)
+                        (#set! injection.language "javascript"))"#,
+                    "",
+                )
+                .unwrap(),
+            )
+            .insert(
+                "js",
+                HighlightConfiguration::new(
+                    tree_sitter_javascript::language(),
+                    "[\"const\" \"return\"] @keyword",
+                    "",
+                    "",
+                )
+                .unwrap(),
+            )
+            .alias("JavaScript", "js")
+            .set_injection_resolver(|name| (name == "ecma").then(|| "js".to_string()));
+        assert_eq!(languages.canonical("RUST"), Some("rust"));
+        assert_eq!(languages.canonical("rs"), Some("rust"));
+        assert_eq!(languages.canonical("javascript"), Some("js"));
+        assert_eq!(languages.canonical("JS"), Some("js"));
+        assert_eq!(languages.canonical("shell"), None);
+        assert_eq!(languages.resolve_alias("rs"), Some("rust"));
+        assert_eq!(languages.resolve_alias("rust"), Some("rust"));
+        assert_eq!(languages.resolve_alias("JavaScript"), Some("js"));
+        assert_eq!(languages.resolve_alias("shell"), None);
+        assert!(languages.get("Rust").is_some());
+        assert!(languages.injected("ecma").is_some());
+        assert!(languages.injected("comment").is_none());
+
+        let html = languages
+            .render("html", b"<script>const a = 1;</script>")
+            .unwrap();
+        assert!(html.contains("<span class=keyword>const</span>"));
+        let html = languages
+            .render("markdown", b"```JS\nconst a = 1;\n```\n")
+            .unwrap();
+        assert!(html.contains("<span class=keyword>const</span>"));
+        assert_eq!(
+            html,
+            languages
+                .render("Markdown", b"```JS\nconst a = 1;\n```\n")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut languages = rust();
+        let keyword = NAMES.iter().position(|n| *n == "keyword").unwrap();
+        let (_, classes) = languages.get_mut("rust").unwrap();
+        classes[keyword] = "class=kw".to_string();
+
+        assert!(languages
+            .render("rust", b"let a = 1;")
+            .unwrap()
+            .contains("<span class=kw>let</span>"));
+        assert!(languages.get_mut("go").is_none());
+    }
+
+    #[test]
+    fn etag() {
+        let mut languages = rust();
+        let (html, etag) = languages.render_with_etag("rust", b"let a = 1;").unwrap();
+        assert_eq!(Some(html), languages.render("rust", b"let a = 1;"));
+        assert_eq!(etag.len(), 16);
+        assert_eq!(
+            languages.render_with_etag("rust", b"let a = 1;").unwrap().1,
+            etag
+        );
+        assert_ne!(
+            languages.render_with_etag("rust", b"let a = 2;").unwrap().1,
+            etag
+        );
+
+        languages.get_mut("rust").unwrap().1[0] = "class=changed".to_string();
+        assert_ne!(
+            languages.render_with_etag("rust", b"let a = 1;").unwrap().1,
+            etag
+        );
+        assert!(languages.render_with_etag("go", b"").is_none());
+
+        // Injected languages render too.
+        let mut languages = markdown();
+        let doc = b"```rust\nlet a;\n```\n";
+        let (_, etag) = languages.render_with_etag("markdown", doc).unwrap();
+        languages.insert(
+            "rust",
+            HighlightConfiguration::new(tree_sitter_rust::language(), "\"let\" @keyword", "", "")
+                .unwrap(),
+        );
+        assert_ne!(languages.render_with_etag("markdown", doc).unwrap().1, etag);
+        let (_, etag) = languages.render_with_etag("markdown", doc).unwrap();
+        languages.set_injection_resolver(|_| None);
+        assert_ne!(languages.render_with_etag("markdown", doc).unwrap().1, etag);
+    }
+
+    #[test]
+    fn strict() {
+        let mut languages = rust();
+        languages.get_mut("rust").unwrap().1.truncate(1);
+        let source = b"let a = 1;";
+
+        assert!(languages
+            .render("rust", source)
+            .unwrap()
+            .contains("<span>let</span>"));
+        assert!(matches!(
+            languages.render_with("rust", source, &RenderOptions::new().strict(true)),
+            Err(crate::Error::UnmappedIndex(i)) if i > 0
+        ));
+    }
+
+    #[test]
+    fn render_many() {
+        use std::sync::atomic::Ordering;
+
+        let languages = rust();
+        let before = languages.highlighters.load(Ordering::Relaxed);
+        let results = languages.render_many(
+            [
+                ("rust", &b"let a = 1;"[..]),
+                ("go", b"package main"),
+                ("rust", b"fn b() {}"),
+            ],
+            &RenderOptions::new(),
+        );
+        assert_eq!(languages.highlighters.load(Ordering::Relaxed) - before, 1);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[0].as_deref().unwrap(),
+            languages.render("rust", b"let a = 1;").unwrap()
+        );
+        assert!(matches!(&results[1], Err(crate::Error::UnknownLanguage(lang)) if lang == "go"));
+        assert_eq!(
+            results[2].as_deref().unwrap(),
+            languages.render("rust", b"fn b() {}").unwrap()
+        );
+    }
+
+    #[test]
+    fn highlighter_reuse() {
+        use std::sync::atomic::Ordering;
+
+        fn shared<T: Send + Sync>(_: &T) {}
+
+        let languages = rust();
+        shared(&languages);
+        let source = include_bytes!("../fixtures/sample.rs");
+        let first = languages.render("rust", source).unwrap();
+        let before = languages.highlighters.load(Ordering::Relaxed);
+        for _ in 0..3 {
+            assert_eq!(languages.render("rust", source).unwrap(), first);
+            languages.render("rust", b"let a = 1;").unwrap();
+            languages
+                .render_lines("rust", source, &RenderOptions::new())
+                .unwrap();
+        }
+        assert_eq!(languages.highlighters.load(Ordering::Relaxed), before);
+        assert_eq!(languages.pool.lock().unwrap().len(), 1);
+
+        // Renders of the same source write into the same buffers.
+        let allocations = languages.pool.lock().unwrap()[0].buffers.allocations();
+        for _ in 0..3 {
+            assert_eq!(languages.render("rust", source).unwrap(), first);
+            assert_eq!(
+                languages.pool.lock().unwrap()[0].buffers.allocations(),
+                allocations
+            );
+        }
+    }
+
+    #[test]
+    fn render_timed() {
+        let languages = rust();
+        let source = include_bytes!("../fixtures/sample.rs");
+        let (html, elapsed) = languages.render_timed("rust", source).unwrap();
+        assert_eq!(html, languages.render("rust", source).unwrap());
+        assert!(!elapsed.is_zero());
+        assert!(languages.render_timed("cobol", source).is_none());
+    }
+
+    #[test]
+    fn render_report() {
+        let languages = rust();
+        let options = RenderOptions::new();
+        let report = languages
+            .render_report("rust", b"let a = 1;", &options)
+            .unwrap();
+        assert_eq!(
+            report.html,
+            languages.render("rust", b"let a = 1;").unwrap()
+        );
+        assert!(report.warnings.is_empty());
+
+        // A grammar whose query matches nothing.
+        let mut broken = Languages::new();
+        broken.insert(
+            "rust",
+            HighlightConfiguration::new(tree_sitter_rust::language(), "", "", "").unwrap(),
+        );
+        let report = broken
+            .render_report("rust", b"let a = 1;", &options)
+            .unwrap();
+        assert_eq!(report.warnings, [RenderWarning::NoHighlights]);
+        // Blank sources have nothing to highlight.
+        let report = broken.render_report("rust", b" \n", &options).unwrap();
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn introspection() {
+        let languages = rust();
+        assert_eq!(languages.has_injections("rust"), Some(true));
+        // The bundled locals use `@definition.*` captures, which tree-sitter-highlight ignores.
+        assert_eq!(languages.has_locals("rust"), Some(false));
+        assert_eq!(languages.has_injections("go"), None);
+        assert_eq!(languages.has_locals("go"), None);
+    }
+
+    #[test]
+    fn set_class_style() {
+        let mut languages = rust();
+        let style = ClassStyle::new().prefix("hl-").hyphenate(true);
+        assert!(languages.set_class_style("rust", &style));
+        assert!(!languages.set_class_style("go", &style));
+        let html = languages.render("rust", b"fn f() {}").unwrap();
+        assert!(html.contains("<span class=hl-keyword-function>fn</span>"));
+
+        let classes = names_to_classes(&["a b", "c"], &ClassStyle::new().prefix("x\""));
+        assert_eq!(classes, ["class=\"x&quot;a b\"", "class=\"x&quot;c\""]);
+
+        languages.set_class_style("rust", &ClassStyle::new().bem("token"));
+        let html = languages.render("rust", b"f();").unwrap();
+        assert!(
+            html.contains("<span class=\"token token--function token--function-call\">f</span>")
+        );
+    }
+
+    #[test]
+    fn blank() {
+        let languages = rust();
+        for source in ["", " ", "\n\n", " \t\n  \r\n", "\x0c"] {
+            let source = source.as_bytes();
+            let options = RenderOptions::new().start_line(1);
+            let entry = languages.entry("rust").unwrap();
+            let mut highlighter = Highlighter::new();
+            let events = languages
+                .highlight(&mut highlighter, entry, source, None)
+                .unwrap();
+            let mut parsed = String::new();
+            let classes = languages.injection_classes(Some(entry));
+            let buffers = &mut Buffers::default();
+            write_events(
+                "rust",
+                source,
+                events,
+                &options,
+                &classes,
+                buffers,
+                &mut parsed,
+            )
+            .unwrap();
+
+            let html = languages.render_with("rust", source, &options).unwrap();
+            assert_eq!(html, parsed);
+            assert_eq!(
+                languages.render_lines("rust", source, &options).unwrap(),
+                html_lines(
+                    source,
+                    languages
+                        .highlight(&mut highlighter, entry, source, None)
+                        .unwrap(),
+                    &options,
+                    &classes,
+                    buffers
+                )
+                .unwrap()
+            );
+        }
+        assert!(super::blank(b" x ", &RenderOptions::new()).is_none());
+    }
+
+    #[test]
+    fn render_fmt() {
+        struct Snippet<'l>(&'l Languages<'l>, &'l str);
+
+        impl fmt::Display for Snippet<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.render_fmt("rust", self.1.as_bytes(), f)
+            }
+        }
+
+        let languages = rust();
+        let mut s = String::from("<div>");
+        languages.render_fmt("rust", b"let a = 1;", &mut s).unwrap();
+        assert_eq!(
+            s,
+            format!("<div>{}", languages.render("rust", b"let a = 1;").unwrap())
+        );
+        assert_eq!(
+            Snippet(&languages, "fn f() {}").to_string(),
+            languages.render("rust", b"fn f() {}").unwrap()
+        );
+        assert!(languages.render_fmt("cobol", b"", &mut s).is_err());
+    }
+
+    #[test]
+    fn profiles() {
+        let mut languages = rust();
+        assert!(languages.set_profile("rust", "coarse", &["keyword", "function"]));
+        assert!(languages.set_profile("rs", "fine", NAMES));
+        assert!(!languages.set_profile("go", "fine", NAMES));
+
+        let source = b"fn f() { f(); }";
+        let coarse = languages.render_profile("rust", "coarse", source).unwrap();
+        assert!(coarse.contains("<span class=keyword>fn</span>"), "{coarse}");
+        assert!(
+            coarse.contains("<span class=function>f</span>();"),
+            "{coarse}"
+        );
+        assert!(!coarse.contains("function.call"), "{coarse}");
+
+        let fine = languages.render_profile("rust", "fine", source).unwrap();
+        assert!(
+            fine.contains("<span class=keyword.function>fn</span>"),
+            "{fine}"
+        );
+        assert!(
+            fine.contains("<span class=function.call>f</span>"),
+            "{fine}"
+        );
+        assert_eq!(fine, languages.render("rust", source).unwrap());
+        assert!(languages
+            .render_profile("rust", "verbose", source)
+            .is_none());
+    }
+
+    #[test]
+    fn index() {
+        let languages = rust();
+        let (config, classes) = &languages["rust"];
+        assert_eq!(classes.len(), NAMES.len());
+        assert!(config.query.pattern_count() > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown language `go`")]
+    fn index_unknown() {
+        let _ = &rust()["go"];
+    }
+
+    #[test]
+    fn parse() {
+        let languages = rust();
+        let tree = languages.parse("rust", b"fn main() {}").unwrap();
+        let root = tree.root_node();
+        assert_eq!(root.kind(), "source_file");
+        assert_eq!(root.child(0).unwrap().kind(), "function_item");
+        assert!(languages.parse("go", b"package main").is_none());
+    }
+
+    /// Every renderer honors the font styles of the theme.
+    #[test]
+    fn font_styles() {
+        let languages = rust();
+        let theme = Theme::new(&[
+            ("comment", Style::new().italic()),
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+        ]);
+        let source = b"// c\nfn f() {}\n";
+
+        let html = languages
+            .render_inline_styles("rust", source, &theme, &RenderOptions::default())
+            .unwrap();
+        assert!(html.contains("<span style=\"font-style:italic\">// c"));
+        assert!(html.contains("<span style=\"color:#c678dd;font-weight:bold\">fn</span>"));
+
+        let ansi = languages.render_ansi("rust", source, &theme).unwrap();
+        assert!(ansi.contains("\x1b[3m// c\x1b[0m"));
+        assert!(ansi.contains("\x1b[1;38;2;198;120;221mfn\x1b[0m"));
+
+        let css = theme_css(&theme);
+        assert!(css.contains("[class~=\"comment\"] { font-style: italic; }"));
+        assert!(css.contains("[class~=\"keyword\"] { color: #c678dd; font-weight: bold; }"));
+
+        let svg = languages
+            .render_svg("rust", source, &theme, &SvgOptions::default())
+            .unwrap();
+        assert!(svg.contains("<tspan font-style=\"italic\">// c</tspan>"));
+        assert!(svg.contains("<tspan fill=\"#c678dd\" font-weight=\"bold\">fn</tspan>"));
+    }
+
+    #[test]
+    fn theme_coverage() {
+        let languages = rust();
+        let theme = Theme::new(&[("keyword", Style::new().bold())]);
+        let coverage = languages.theme_coverage("rust", &theme).unwrap();
+        assert_eq!(coverage.exact, ["keyword"]);
+        assert!(coverage.fallback.contains(&"function.macro".to_string()));
+        assert!(coverage.unmatched.contains(&"string".to_string()));
+        let all = [coverage.exact, coverage.fallback, coverage.unmatched].concat();
+        assert!(all
+            .iter()
+            .all(|n| !n.starts_with("injection.") && !n.starts_with("local.")));
+        assert!(languages.theme_coverage("go", &theme).is_none());
+    }
+
+    #[test]
+    fn unknown_class() {
+        let mut languages = rust();
+        languages.get_mut("rust").unwrap().1.truncate(1);
+        let html = languages
+            .render_with(
+                "rust",
+                b"let a = 1;",
+                &RenderOptions::new().unknown_class("unknown"),
+            )
+            .unwrap();
+        assert!(html.contains("<span class=unknown>let</span>"));
+        assert!(!html.contains("<span>"));
+    }
+
+    #[test]
+    fn render_into() {
+        let languages = rust();
+        let mut buf = String::from("<body>");
+        assert!(languages.render_into("rust", b"let a = 1;", &mut buf));
+        assert!(languages.render_into("rust", b"let b = 2;", &mut buf));
+        assert!(!languages.render_into("go", b"package main", &mut buf));
+        assert_eq!(
+            buf,
+            format!(
+                "<body>{}{}",
+                languages.render("rust", b"let a = 1;").unwrap(),
+                languages.render("rust", b"let b = 2;").unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn render_to() {
+        let languages = rust();
+        let source = include_bytes!("../fixtures/sample.rs");
+        let mut s = String::new();
+        languages
+            .render_to("rust", source, &RenderOptions::new(), &mut s)
+            .unwrap();
+        assert_eq!(s, languages.render("rust", source).unwrap());
+
+        struct Failing;
+        impl fmt::Write for Failing {
+            fn write_str(&mut self, _: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+        let result = languages.render_to("rust", source, &RenderOptions::new(), &mut Failing);
+        assert!(matches!(result, Err(crate::Error::Write(_))));
+    }
+
+    /// The core render path must build for `wasm32-unknown-unknown`.
+    #[test]
+    fn portable() {
+        let sources = [
+            include_str!("lib.rs"),
+            include_str!("ansi.rs"),
+            include_str!("base16.rs"),
+            include_str!("brackets.rs"),
+            include_str!("cache.rs"),
+            include_str!("capture.rs"),
+            include_str!("class.rs"),
+            include_str!("css.rs"),
+            include_str!("detect.rs"),
+            include_str!("error.rs"),
+            include_str!("errors.rs"),
+            include_str!("fingerprint.rs"),
+            include_str!("folds.rs"),
+            include_str!("helix.rs"),
+            include_str!("html.rs"),
+            include_str!("inline.rs"),
+            include_str!("json.rs"),
+            include_str!("latex.rs"),
+            include_str!("locals.rs"),
+            include_str!("markdown.rs"),
+            include_str!("options.rs"),
+            include_str!("outline.rs"),
+            include_str!("overlay.rs"),
+            include_str!("renderer.rs"),
+            include_str!("pango.rs"),
+            include_str!("rtf.rs"),
+            include_str!("session.rs"),
+            include_str!("shiki.rs"),
+            include_str!("stream.rs"),
+            include_str!("svg.rs"),
+            include_str!("theme.rs"),
+            include_str!("themes.rs"),
+            include_str!("token.rs"),
+            include_str!("typst.rs"),
+            include_str!("vscode.rs"),
+            include_str!("width.rs"),
+        ];
+        for needle in [
+            concat!("std::", "fs"),
+            concat!("std::", "thread"),
+            concat!("std::", "process"),
+            concat!("thread_", "local!"),
+        ] {
+            assert!(sources.iter().all(|s| !s.contains(needle)), "{needle}");
+        }
+    }
+
+    #[test]
+    fn data_attrs() {
+        let languages = rust();
+        let html = languages
+            .render_with_data_attrs("rust", b"let a = 1;", |name| {
+                vec![
+                    ("data-capture".to_string(), format!("{name}\"><script>")),
+                    ("onclick=\"x".to_string(), "y".to_string()),
+                ]
+            })
+            .unwrap();
+        assert!(html.contains(
+            "<span class=keyword data-capture=\"keyword&quot;&gt;&lt;script&gt;\">let</span>"
+        ));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("onclick"));
+        assert!(languages
+            .render_with_data_attrs("go", b"", |_| Vec::new())
+            .is_none());
+    }
+
+    #[test]
+    fn events() {
+        let languages = rust();
+        let source = include_bytes!("../fixtures/sample.rs");
+        let mut text = Vec::new();
+        let mut depth = 0;
+        let mut names = Vec::new();
+        languages
+            .for_each_event("rust", source, |event| match event {
+                HighlightEvent::Source { start, end } => {
+                    text.extend_from_slice(&source[start..end])
+                }
+                HighlightEvent::HighlightStart(h) => {
+                    depth += 1;
+                    names.push(languages.capture_name("rust", h).unwrap());
+                }
+                HighlightEvent::HighlightEnd => depth -= 1,
+            })
+            .unwrap();
+
+        assert_eq!(text, source);
+        assert_eq!(depth, 0);
+        assert!(names.contains(&"include"));
+        assert!(languages.for_each_event("go", source, |_| {}).is_err());
+    }
+
+    /// Inputs in the spirit of the `render` fuzz target.
+    #[test]
+    fn adversarial() {
+        let languages = rust();
+        let theme = Theme::default();
+        let options = RenderOptions::new()
+            .search_terms(&["本", "\u{fffd}", "a"])
+            .wrap_column(0)
+            .collapse(0..usize::MAX)
+            .collapse_details(true)
+            .mark_conflicts(true)
+            .line_heat(LineHeat::new(HashMap::from([
+                (1, f64::NAN),
+                (2, f64::INFINITY),
+                (usize::MAX, f64::MIN),
+            ])));
+        let sources: [&[u8]; 7] = [
+            b"",
+            b"\xff\xfe\n\xc3",
+            &"日本".as_bytes()[..4],
+            b"\r\r\n\0\0<<<<<<<\n=======",
+            b"\"unterminated \\",
+            b"/* \xe6\x97\n */ 'a",
+            b"\n\n\n",
+        ];
+        for source in sources {
+            languages.render_with("rust", source, &options).unwrap();
+            languages.tokens("rust", source).unwrap();
+            languages
+                .positioned_tokens("rust", source, ColumnUnit::Utf16, true)
+                .unwrap();
+            languages
+                .render_svg("rust", source, &theme, &SvgOptions::new())
+                .unwrap();
+            languages.render_latex("rust", source, &theme).unwrap();
+            languages.render_rtf("rust", source, &theme).unwrap();
+        }
+    }
+
+    #[test]
+    fn render_lines() {
+        let languages = rust();
+        let source = include_bytes!("../fixtures/sample.rs");
+        let options = RenderOptions::new();
+        let lines = languages.render_lines("rust", source, &options).unwrap();
+        assert_eq!(lines.len(), source.split(|b| *b == b'\n').count());
+        assert_eq!(lines[3], "<span class=line>\n</span>");
+        assert_eq!(
+            format!(
+                "<pre class=language-rust><code>{}</code></pre>",
+                lines.concat()
+            ),
+            languages.render("rust", source).unwrap()
+        );
+
+        let lines = languages
+            .render_lines("rust", b"let a;\n\nlet b;\n", &options.bare_lines(true))
+            .unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "\n");
+        assert!(lines.iter().all(|line| !line.contains("class=line")));
+
+        let source = b"fn a() {}\n\nfn b() {}";
+        let lines = languages.render_line_vec("rust", source).unwrap();
+        assert_eq!(lines.len(), 3);
+        let html = languages.render("rust", source).unwrap();
+        assert_eq!(
+            lines.concat(),
+            html["<pre class=language-rust><code>".len()..html.len() - "</code></pre>".len()]
+        );
+        assert!(languages.render_line_vec("go", source).is_none());
+
+        // Options rewriting the events apply too.
+        let source = include_bytes!("../fixtures/sample.rs");
+        for options in [
+            RenderOptions::new().overrides(&[(0..3, "keyword"), (10..14, "string")]),
+            RenderOptions::new().link_locals(true),
+            RenderOptions::new().pair_brackets(true),
+            RenderOptions::new().mark_errors(true),
+        ] {
+            let lines = languages.render_lines("rust", source, &options).unwrap();
+            assert_eq!(
+                format!(
+                    "<pre class=language-rust><code>{}</code></pre>",
+                    lines.concat()
+                ),
+                languages.render_with("rust", source, &options).unwrap()
+            );
+        }
+        let options = RenderOptions::new().mark_errors(true);
+        let source = b"let a = 1;\nlet b = $$$;\nfn f() { g(1 }\n";
+        let lines = languages.render_lines("rust", source, &options).unwrap();
+        assert_eq!(
+            format!(
+                "<pre class=language-rust><code>{}</code></pre>",
+                lines.concat()
+            ),
+            languages.render_with("rust", source, &options).unwrap()
+        );
+    }
+
+    #[test]
+    fn render_range() {
+        let languages = rust();
+        let source = b"use std::fmt;\n\n/// Docs\nfn f() -> &'static str {\n    \"a\\nb\"\n}\n";
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_rust::language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let root = tree.root_node();
+        let function = (0..root.named_child_count())
+            .filter_map(|i| root.named_child(i))
+            .find(|node| node.kind() == "function_item")
+            .unwrap();
+
+        let html = languages
+            .render_range("rust", source, function.byte_range())
+            .unwrap();
+        assert!(html.starts_with(
+            "<pre class=language-rust><code><span class=line><span class=keyword.function>fn</span> <span class=function>f</span>"
+        ));
+        assert!(html.contains(
+            "<span class=string>&quot;a<span class=string.escape>\\n</span>b&quot;</span>"
+        ));
+        assert!(!html.contains("use"));
+        assert!(!html.contains("Docs"));
+        assert_eq!(html.matches("<span class=line>").count(), 3);
+
+        // Inside the string, the enclosing capture is reopened.
+        let start = source.iter().position(|b| *b == b'a').unwrap();
+        assert_eq!(
+            languages.render_range("rust", source, start..start + 1).unwrap(),
+            "<pre class=language-rust><code><span class=line><span class=string>a</span>\n</span></code></pre>"
+        );
+    }
+
     #[test]
     fn highlighting() -> Result<(), Box<dyn Error>> {
         let mut languages = Languages::new();
@@ -168,6 +2588,15 @@ mod tests {
                 include_str!("../queries/rust/locals.scm"),
             )?,
         );
+        // Macro token trees inject Rust, checked apart; the golden is of the host alone.
+        let html = languages
+            .render("rust", b"println!(\"listening on {}\", addr);")
+            .unwrap();
+        assert!(
+            html.contains("<span class=punctuation.delimiter>,</span>"),
+            "{html}"
+        );
+        languages.set_max_injection_depth(0);
 
         assert_eq!(
             languages
@@ -0,0 +1,258 @@
+use std::{collections::HashMap, fmt::Write, ops::Range};
+
+use tree_sitter::{Parser, QueryCursor};
+use tree_sitter_highlight::{Highlight, HighlightConfiguration, HighlightEvent};
+
+/// A local definition, or a reference to one, by definition id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Local {
+    Definition(usize),
+    Reference(usize),
+}
+
+/// A capture of a locals query, in the order captures of one range are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Scope,
+    Definition,
+    Reference,
+}
+
+/// The kind of a capture name, after tree-sitter-highlight's `local.*` names or the
+/// bare `scope`, `definition.*` and `reference` names of older queries.
+fn kind(name: &str) -> Option<Kind> {
+    let definition = |prefix: &str| {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    };
+    match name {
+        "local.scope" | "scope" => Some(Kind::Scope),
+        "local.reference" | "reference" => Some(Kind::Reference),
+        _ if definition("local.definition") || definition("definition") => Some(Kind::Definition),
+        _ => None,
+    }
+}
+
+/// Resolves the local definitions and references of a source, sorted by range.
+///
+/// Definitions are numbered from 1 in source order. A reference resolves to the last
+/// definition of its name in the innermost scope defining it, skipping definitions whose
+/// `@local.definition-value` contains it, so shadowed names get distinct ids.
+fn locals(config: &HighlightConfiguration, source: &[u8]) -> Vec<(Range<usize>, Local)> {
+    let mut parser = Parser::new();
+    let Some(tree) = parser
+        .set_language(config.language)
+        .ok()
+        .and_then(|_| parser.parse(source, None))
+    else {
+        return Vec::new();
+    };
+    let names = config.query.capture_names();
+    let mut captures = Vec::new();
+    let mut cursor = QueryCursor::new();
+    for m in cursor.matches(&config.query, tree.root_node(), source) {
+        let value = m
+            .captures
+            .iter()
+            .find(|c| names[c.index as usize] == "local.definition-value")
+            .map(|c| c.node.byte_range());
+        for c in m.captures {
+            if let Some(kind) = kind(&names[c.index as usize]) {
+                captures.push((c.node.byte_range(), kind, value.clone()));
+            }
+        }
+    }
+    // Scopes open before what they contain, and a definition wins over a reference of
+    // the same range.
+    captures.sort_by_key(|(range, kind, _)| (range.start, usize::MAX - range.end, *kind));
+
+    // The end and the definitions of each open scope, with the whole source outermost.
+    let mut scopes = vec![(usize::MAX, Vec::new())];
+    let mut locals: Vec<(Range<usize>, Local)> = Vec::new();
+    let mut ids = 0;
+    for (range, kind, value) in captures {
+        while scopes.len() > 1 && scopes.last().is_some_and(|(end, _)| *end <= range.start) {
+            scopes.pop();
+        }
+        if locals.last().is_some_and(|(last, _)| *last == range) {
+            continue;
+        }
+        let Ok(name) = std::str::from_utf8(&source[range.clone()]) else {
+            continue;
+        };
+        match kind {
+            Kind::Scope => scopes.push((range.end, Vec::new())),
+            Kind::Definition => {
+                ids += 1;
+                if let Some((_, definitions)) = scopes.last_mut() {
+                    definitions.push((name, ids, value));
+                }
+                locals.push((range, Local::Definition(ids)));
+            }
+            Kind::Reference => {
+                let id = scopes
+                    .iter()
+                    .rev()
+                    .flat_map(|(_, definitions)| definitions.iter().rev())
+                    .find(|(n, _, value)| {
+                        *n == name && !value.as_ref().is_some_and(|v| v.contains(&range.start))
+                    })
+                    .map(|(_, id, _)| *id);
+                if let Some(id) = id {
+                    locals.push((range, Local::Reference(id)));
+                }
+            }
+        }
+    }
+    locals
+}
+
+/// The number of events from the start of `events` to a `Source` ending at `end`, with
+/// the highlights between balanced.
+fn span(events: &[HighlightEvent], end: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, event) in events.iter().enumerate() {
+        match *event {
+            HighlightEvent::HighlightStart(_) => depth += 1,
+            HighlightEvent::HighlightEnd => depth = depth.checked_sub(1)?,
+            HighlightEvent::Source { end: e, .. } if e == end && depth == 0 => return Some(i + 1),
+            HighlightEvent::Source { end: e, .. } if e >= end => return None,
+            HighlightEvent::Source { .. } => {}
+        }
+    }
+    None
+}
+
+/// Adds `data-def` and `data-ref` attributes to the local definitions and references of
+/// a source, resolved with the locals patterns of `config`.
+///
+/// A highlight covering exactly a local gets the attribute next to its class; other locals
+/// are wrapped in a highlight of their own. The attributes are appended to `classes`.
+pub(crate) fn link(
+    config: &HighlightConfiguration,
+    source: &[u8],
+    events: Vec<HighlightEvent>,
+    classes: &mut Vec<String>,
+) -> Vec<HighlightEvent> {
+    let locals = locals(config, source);
+    if locals.is_empty() {
+        return events;
+    }
+
+    // Splits the source events at the bounds of the locals.
+    let mut cuts = locals
+        .iter()
+        .flat_map(|(range, _)| [range.start, range.end])
+        .collect::<Vec<_>>();
+    cuts.sort_unstable();
+    cuts.dedup();
+    let events = events
+        .into_iter()
+        .flat_map(|event| match event {
+            HighlightEvent::Source { start, end } => {
+                let from = cuts.partition_point(|cut| *cut <= start);
+                let mut points = vec![start];
+                points.extend(cuts[from..].iter().take_while(|cut| **cut < end));
+                points.push(end);
+                points
+                    .windows(2)
+                    .map(|w| HighlightEvent::Source {
+                        start: w[0],
+                        end: w[1],
+                    })
+                    .collect()
+            }
+            event => vec![event],
+        })
+        .collect::<Vec<_>>();
+
+    // The index of the attributes of a highlight, if any, with those of a local.
+    let mut indices = HashMap::new();
+    let mut index = |classes: &mut Vec<String>, h: Option<usize>, local: Local| {
+        *indices.entry((h, local)).or_insert_with(|| {
+            let mut attrs = h.and_then(|h| classes.get(h)).cloned().unwrap_or_default();
+            if !attrs.is_empty() {
+                attrs.push(' ');
+            }
+            let _ = match local {
+                Local::Definition(id) => write!(attrs, "data-def=\"d{id}\""),
+                Local::Reference(id) => write!(attrs, "data-ref=\"d{id}\""),
+            };
+            classes.push(attrs);
+            Highlight(classes.len() - 1)
+        })
+    };
+
+    let mut out = Vec::with_capacity(events.len() + locals.len() * 2);
+    let mut locals = locals.into_iter().peekable();
+    let mut i = 0;
+    while i < events.len() {
+        let HighlightEvent::Source { start, .. } = events[i] else {
+            out.push(events[i]);
+            i += 1;
+            continue;
+        };
+        while locals.next_if(|(range, _)| range.start < start).is_some() {}
+        let found = locals
+            .next_if(|(range, _)| range.start == start)
+            .and_then(|(range, local)| Some((local, span(&events[i..], range.end)?)));
+        let Some((local, len)) = found else {
+            out.push(events[i]);
+            i += 1;
+            continue;
+        };
+        match (out.last(), events.get(i + len)) {
+            (Some(HighlightEvent::HighlightStart(h)), Some(HighlightEvent::HighlightEnd)) => {
+                let h = index(classes, Some(h.0), local);
+                out.pop();
+                out.push(HighlightEvent::HighlightStart(h));
+                out.extend_from_slice(&events[i..i + len]);
+            }
+            _ => {
+                let h = index(classes, None, local);
+                out.push(HighlightEvent::HighlightStart(h));
+                out.extend_from_slice(&events[i..i + len]);
+                out.push(HighlightEvent::HighlightEnd);
+            }
+        }
+        i += len;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, RenderOptions};
+
+    #[test]
+    fn kinds() {
+        assert_eq!(kind("local.scope"), Some(Kind::Scope));
+        assert_eq!(kind("definition.var"), Some(Kind::Definition));
+        assert_eq!(kind("local.definition"), Some(Kind::Definition));
+        assert_eq!(kind("local.definition-value"), None);
+        assert_eq!(kind("definitions"), None);
+        assert_eq!(kind("reference"), Some(Kind::Reference));
+    }
+
+    #[test]
+    fn shadowing() {
+        let languages = rust();
+        let html = languages
+            .render_with(
+                "rust",
+                b"fn f(x: i32) {\n    {\n        let x = 1;\n        x;\n    }\n    x;\n}\n",
+                &RenderOptions::new().link_locals(true),
+            )
+            .unwrap();
+        // `f` is d1, the parameter d2 and the inner `x` d3.
+        assert!(html.contains("data-def=\"d2\">x</span>"), "{html}");
+        assert!(html.contains("data-def=\"d3\">x</span>"), "{html}");
+        assert_eq!(html.matches("data-ref=\"d3\"").count(), 1, "{html}");
+        assert_eq!(html.matches("data-ref=\"d2\"").count(), 1, "{html}");
+        assert!(html.find("data-ref=\"d3\"") < html.find("data-ref=\"d2\""));
+
+        let plain = languages.render("rust", b"let x = 1;\n").unwrap();
+        assert!(!plain.contains("data-def"));
+    }
+}
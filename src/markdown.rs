@@ -0,0 +1,170 @@
+use std::ops::Range;
+
+use tree_sitter_highlight::HighlightEvent;
+
+use crate::{
+    html::{normalize_source, Buffers},
+    write_events, Error, Languages, RenderOptions, ZeroWidth,
+};
+
+/// A fenced code block of a Markdown document.
+struct Fence<'s> {
+    /// The info string, whose first word names the language.
+    info: &'s str,
+    content: Range<usize>,
+}
+
+/// Finds the fenced code blocks of a Markdown document, after CommonMark.
+///
+/// A fence is a line of three or more backticks or tildes, indented by at most three
+/// spaces, and is closed by a line of at least as many of the same character; an
+/// unclosed block runs to the end of the document.
+fn fences(source: &[u8]) -> Vec<Fence<'_>> {
+    let mut fences = Vec::new();
+    // The character and length of the open fence, its info string and content start.
+    let mut open: Option<(u8, usize, &str, usize)> = None;
+    let mut offset = 0;
+    for line in source.split_inclusive(|b| *b == b'\n') {
+        let start = offset;
+        offset += line.len();
+        let indent = line.iter().take_while(|b| **b == b' ').count();
+        let text = &line[indent..];
+        let Some(&marker @ (b'`' | b'~')) = text.first() else {
+            continue;
+        };
+        let len = text.iter().take_while(|b| **b == marker).count();
+        if indent > 3 || len < 3 {
+            continue;
+        }
+        let rest = &text[len..];
+        match open {
+            None => {
+                // Backtick fences cannot have backticks in their info string.
+                if marker == b'`' && rest.contains(&b'`') {
+                    continue;
+                }
+                let info = std::str::from_utf8(rest).unwrap_or_default().trim();
+                open = Some((marker, len, info, offset));
+            }
+            Some((m, l, info, content))
+                if m == marker && len >= l && rest.iter().all(u8::is_ascii_whitespace) =>
+            {
+                fences.push(Fence {
+                    info,
+                    content: content..start,
+                });
+                open = None;
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some((_, _, info, content)) = open {
+        fences.push(Fence {
+            info,
+            content: content..source.len(),
+        });
+    }
+    fences
+}
+
+impl<'a> Languages<'a> {
+    /// Renders a Markdown document as a `language-markdown` block, with its fenced code
+    /// highlighted in the registered languages and the prose as plain escaped text.
+    ///
+    /// The language of a fence is the first word of its info string, resolved like an
+    /// injected language, so ```` ```Rust ```` and ```` ```rs ```` are both `rust`. Fences
+    /// without a registered language, or whose code fails to highlight, are left plain.
+    /// No Markdown grammar needs to be registered.
+    pub fn render_markdown(&self, source: &[u8]) -> String {
+        let source = &normalize_source(source, ZeroWidth::Keep);
+        let mut highlighter = self.highlighter();
+        let mut events = Vec::new();
+        let mut prose = 0;
+        for fence in fences(source) {
+            let Some(entry) = self.injected(fence.info) else {
+                continue;
+            };
+            let offset = fence.content.start;
+            let Ok(highlighted) = self
+                .highlight(
+                    &mut highlighter,
+                    entry,
+                    &source[fence.content.clone()],
+                    None,
+                )
+                .and_then(|events| events.collect::<Result<Vec<_>, _>>().map_err(Error::from))
+            else {
+                continue;
+            };
+            events.push(HighlightEvent::Source {
+                start: prose,
+                end: offset,
+            });
+            events.extend(highlighted.into_iter().map(|event| match event {
+                HighlightEvent::Source { start, end } => HighlightEvent::Source {
+                    start: start + offset,
+                    end: end + offset,
+                },
+                event => event,
+            }));
+            prose = fence.content.end;
+        }
+        events.push(HighlightEvent::Source {
+            start: prose,
+            end: source.len(),
+        });
+
+        let classes = self.injection_classes(None);
+        let mut s = String::with_capacity(source.len() * 2 + 64);
+        // The events are complete, so rendering can only fail on writing, which a
+        // `String` does not.
+        let _ = write_events(
+            "markdown",
+            source,
+            events.into_iter().map(Ok),
+            &RenderOptions::default(),
+            &classes,
+            &mut Buffers::default(),
+            &mut s,
+        );
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::rust;
+
+    #[test]
+    fn fences() {
+        let doc = b"a\n```Rust title=x\nlet\n````\n~~~\n```\n~~~~\n``` a`b\n```\n\n````` x\n";
+        let fences = super::fences(doc)
+            .into_iter()
+            .map(|f| (f.info, &doc[f.content]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fences,
+            [
+                ("Rust title=x", &b"let\n"[..]),
+                ("", b"```\n"),
+                ("", b"\n````` x\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_markdown() {
+        let languages = rust();
+        let doc =
+            "# Title\n\nSome `let` prose.\n\n```rs\nlet a = 1;\n```\n\n```nope\nlet b;\n```\n";
+        let html = languages.render_markdown(doc.as_bytes());
+        assert!(html.starts_with(
+            "<pre class=language-markdown><code><span class=line># Title\n</span><span class=line>\n</span><span class=line>Some `let` prose.\n</span>"
+        ));
+        let start = html.find("```rs").unwrap();
+        let end = html.find("```nope").unwrap();
+        assert!(html[start..end].contains("<span class=keyword>let</span>"));
+        assert!(html[end..].contains("<span class=line>let b;\n</span>"));
+        assert_eq!(html.matches("<span class=keyword>").count(), 1);
+    }
+}
@@ -0,0 +1,585 @@
+use std::{collections::HashMap, ops::Range};
+
+/// Render options
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Per-line values rendered as `heat-N` classes on the line spans.
+    pub line_heat: Option<LineHeat>,
+    /// Terms wrapped in `<mark>`, matched case-insensitively and without overlaps.
+    pub search_terms: Vec<String>,
+    /// Hard wraps lines at this display width; continuations get a `line-continuation` class.
+    pub wrap_column: Option<usize>,
+    /// 1-based line ranges wrapped in a `line-collapsed` element, so CSS can hide them.
+    pub collapse: Vec<Range<usize>>,
+    /// Uses `<details>` instead of `<span>` for collapsed ranges.
+    pub collapse_details: bool,
+    /// Fails with [`Error::UnmappedIndex`](crate::Error::UnmappedIndex) when a highlight
+    /// index has no class, instead of emitting a bare `<span>`.
+    pub strict: bool,
+    /// The class of highlights whose index has no class, instead of a bare `<span>`,
+    /// so unclassified regions can be styled while debugging a names list.
+    pub unknown_class: Option<String>,
+    /// The element of highlights instead of `span`, such as a custom element like
+    /// `hl-token`; line spans stay spans. Invalid element names are ignored.
+    pub token_element: Option<String>,
+    /// Tags git conflict marker lines with `conflict-ours`, `conflict-base`,
+    /// `conflict-sep` and `conflict-theirs` classes.
+    pub mark_conflicts: bool,
+    /// Leaves out the line spans, and with them the line classes.
+    pub bare_lines: bool,
+    /// Renders every space as `·` and tab as `→` in `whitespace` spans, for teaching.
+    pub show_whitespace: bool,
+    /// Wraps each indent level of this many columns of leading whitespace in an
+    /// `indent-guide` span, so CSS can draw guides; a tab is one level.
+    pub indent_guides: Option<usize>,
+    /// 1-based line ranges whose line spans get a `highlighted` class, or the theme's
+    /// line highlight background in inline-style output.
+    pub highlight_lines: Vec<Range<usize>>,
+    /// Where the HTML will be embedded; everything but [`HtmlContext::Normal`] escapes
+    /// the whole output once more.
+    pub context: HtmlContext,
+    /// What to do with zero-width spaces and joiners; a leading byte order mark is always
+    /// dropped.
+    pub zero_width: ZeroWidth,
+    /// How deeply injected languages nest, instead of the registry's
+    /// [`Languages::set_max_injection_depth`](crate::Languages::set_max_injection_depth).
+    pub max_injection_depth: Option<usize>,
+    /// Numbers the local definitions of the host language and adds `data-def="dN"` to
+    /// them and `data-ref="dN"` to their references, after its locals patterns, so uses
+    /// of a symbol can be highlighted together; applies to [`Languages::render_with`](crate::Languages::render_with)
+    /// and the renders built on it.
+    pub link_locals: bool,
+    /// The number of the first line, for excerpts, written as a `data-line` attribute on
+    /// each line span; `0` leaves the numbers out. The other line options count from 1.
+    pub start_line: usize,
+    /// Notes by 1-based line, escaped into a trailing `line-note` span inside the line
+    /// span, for review comments in a right gutter; ignored with `bare_lines`.
+    pub annotations: HashMap<usize, String>,
+    /// Escapes `<`, `>`, `&` and quotes in the source text, on by default.
+    ///
+    /// **Turning this off is an XSS risk**: the source is written into the markup as is,
+    /// so it is only safe for sources known to be free of those characters, such as the
+    /// output of a trusted pipeline. Never turn it off for user-provided code.
+    pub escape: bool,
+    /// Capture names of byte ranges, such as LSP semantic tokens, taking precedence over
+    /// the grammar's captures where they overlap, later ranges winning.
+    ///
+    /// Like with [`Languages::render_with_overlay`](crate::Languages::render_with_overlay),
+    /// the highlights are flattened to one span per token. Ranges are in the source as
+    /// rendered, so after [`ZeroWidth::Remove`].
+    pub overrides: Vec<(Range<usize>, String)>,
+    /// Fails with [`Error::InvalidOverride`](crate::Error::InvalidOverride) on overrides
+    /// outside the source or with a capture name the language does not recognize,
+    /// instead of ignoring them.
+    pub strict_overrides: bool,
+    /// Pairs up `punctuation.bracket` highlights of the same bracket and adds the same
+    /// `data-pair="pN"` attribute to both, so a page can highlight matching brackets on
+    /// hover; unbalanced brackets get none.
+    pub pair_brackets: bool,
+    /// Wraps the text of `ERROR` nodes in `parse-error` spans and adds an empty
+    /// `parse-missing` span where the parser inserted a `MISSING` node, so CSS can
+    /// squiggle syntax errors; works whether or not the queries capture `@error`.
+    pub mark_errors: bool,
+    /// What ends each line, for pages where newlines inside `<pre>` are collapsed.
+    pub line_separator: LineSeparator,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            line_heat: None,
+            search_terms: Vec::new(),
+            wrap_column: None,
+            collapse: Vec::new(),
+            collapse_details: false,
+            strict: false,
+            unknown_class: None,
+            token_element: None,
+            mark_conflicts: false,
+            bare_lines: false,
+            show_whitespace: false,
+            indent_guides: None,
+            highlight_lines: Vec::new(),
+            context: HtmlContext::default(),
+            zero_width: ZeroWidth::default(),
+            max_injection_depth: None,
+            link_locals: false,
+            start_line: 0,
+            annotations: HashMap::new(),
+            escape: true,
+            overrides: Vec::new(),
+            strict_overrides: false,
+            pair_brackets: false,
+            mark_errors: false,
+            line_separator: LineSeparator::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line_heat(mut self, heat: LineHeat) -> Self {
+        self.line_heat = Some(heat);
+        self
+    }
+
+    /// Terms match case-insensitively by lowercase mapping, so `é` matches `É`.
+    pub fn search_terms(mut self, terms: &[&str]) -> Self {
+        self.search_terms = terms.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    pub fn wrap_column(mut self, column: usize) -> Self {
+        self.wrap_column = Some(column);
+        self
+    }
+
+    pub fn collapse(mut self, lines: Range<usize>) -> Self {
+        self.collapse.push(lines);
+        self
+    }
+
+    pub fn collapse_details(mut self, details: bool) -> Self {
+        self.collapse_details = details;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn unknown_class(mut self, class: &str) -> Self {
+        self.unknown_class = Some(class.to_string());
+        self
+    }
+
+    pub fn token_element(mut self, element: &str) -> Self {
+        self.token_element = Some(element.to_string());
+        self
+    }
+
+    pub fn mark_conflicts(mut self, mark: bool) -> Self {
+        self.mark_conflicts = mark;
+        self
+    }
+
+    pub fn bare_lines(mut self, bare: bool) -> Self {
+        self.bare_lines = bare;
+        self
+    }
+
+    pub fn show_whitespace(mut self, show: bool) -> Self {
+        self.show_whitespace = show;
+        self
+    }
+
+    pub fn indent_guides(mut self, width: usize) -> Self {
+        self.indent_guides = Some(width);
+        self
+    }
+
+    pub fn highlight_lines(mut self, lines: Range<usize>) -> Self {
+        self.highlight_lines.push(lines);
+        self
+    }
+
+    pub fn context(mut self, context: HtmlContext) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn zero_width(mut self, zero_width: ZeroWidth) -> Self {
+        self.zero_width = zero_width;
+        self
+    }
+
+    pub fn max_injection_depth(mut self, depth: usize) -> Self {
+        self.max_injection_depth = Some(depth);
+        self
+    }
+
+    pub fn link_locals(mut self, link: bool) -> Self {
+        self.link_locals = link;
+        self
+    }
+
+    pub fn start_line(mut self, line: usize) -> Self {
+        self.start_line = line;
+        self
+    }
+
+    pub fn annotate(mut self, line: usize, note: &str) -> Self {
+        self.annotations.insert(line, note.to_string());
+        self
+    }
+
+    /// See [`RenderOptions::escape`] before turning escaping off.
+    pub fn escape(mut self, escape: bool) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    pub fn overrides(mut self, overrides: &[(Range<usize>, &str)]) -> Self {
+        self.overrides = overrides
+            .iter()
+            .map(|(range, name)| (range.clone(), name.to_string()))
+            .collect();
+        self
+    }
+
+    pub fn strict_overrides(mut self, strict: bool) -> Self {
+        self.strict_overrides = strict;
+        self
+    }
+
+    pub fn pair_brackets(mut self, pair: bool) -> Self {
+        self.pair_brackets = pair;
+        self
+    }
+
+    pub fn mark_errors(mut self, mark: bool) -> Self {
+        self.mark_errors = mark;
+        self
+    }
+
+    pub fn line_separator(mut self, separator: LineSeparator) -> Self {
+        self.line_separator = separator;
+        self
+    }
+
+    /// Hashes every option, for the keys of cached renders and to tell the options of
+    /// the lines a [`crate::Session`] reported.
+    pub(crate) fn fingerprint(&self, state: &mut impl std::hash::Hasher) {
+        use std::hash::Hash;
+
+        match &self.line_heat {
+            Some(heat) => {
+                let mut values = heat
+                    .values
+                    .iter()
+                    .map(|(line, v)| (*line, v.to_bits()))
+                    .collect::<Vec<_>>();
+                values.sort_unstable();
+                Some((
+                    values,
+                    heat.buckets,
+                    heat.min.map(f64::to_bits),
+                    heat.max.map(f64::to_bits),
+                ))
+            }
+            None => None,
+        }
+        .hash(state);
+        self.search_terms.hash(state);
+        self.wrap_column.hash(state);
+        self.collapse.hash(state);
+        self.collapse_details.hash(state);
+        self.strict.hash(state);
+        self.unknown_class.hash(state);
+        self.token_element.hash(state);
+        self.mark_conflicts.hash(state);
+        self.bare_lines.hash(state);
+        self.show_whitespace.hash(state);
+        self.indent_guides.hash(state);
+        self.highlight_lines.hash(state);
+        self.context.hash(state);
+        self.zero_width.hash(state);
+        self.max_injection_depth.hash(state);
+        self.link_locals.hash(state);
+        self.start_line.hash(state);
+        let mut annotations = self.annotations.iter().collect::<Vec<_>>();
+        annotations.sort_unstable();
+        annotations.hash(state);
+        self.escape.hash(state);
+        self.overrides.hash(state);
+        self.strict_overrides.hash(state);
+        self.pair_brackets.hash(state);
+        self.mark_errors.hash(state);
+        self.line_separator.hash(state);
+    }
+
+    /// Whether the events of a render are rewritten: overrides, local links, bracket
+    /// pairs or parse errors.
+    pub(crate) fn rewrites_events(&self) -> bool {
+        self.link_locals || self.pair_brackets || self.mark_errors || !self.overrides.is_empty()
+    }
+
+    /// Whether a 1-based line is in a highlighted range.
+    pub(crate) fn is_highlighted(&self, line: usize) -> bool {
+        self.highlight_lines.iter().any(|r| r.contains(&line))
+    }
+
+    /// The collapsed ranges, sorted and merged, and clamped to 1-based lines.
+    pub(crate) fn collapsed_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges = self
+            .collapse
+            .iter()
+            .map(|r| r.start.max(1)..r.end)
+            .filter(|r| !r.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+}
+
+/// The handling of zero-width characters: U+200B, U+200C, U+200D, U+2060 and U+FEFF
+/// after the start of the source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ZeroWidth {
+    /// Renders them as they are.
+    #[default]
+    Keep,
+    /// Removes them before highlighting, so they cannot split tokens.
+    Remove,
+    /// Renders them as their code point, like `<200b>`, in `zero-width` spans.
+    Show,
+}
+
+/// What ends each rendered line, inside its line span.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LineSeparator {
+    /// A line break, `\n`.
+    #[default]
+    Newline,
+    /// A `<br>` element.
+    Br,
+    /// Nothing, for pages that lay out the line spans themselves.
+    None,
+}
+
+impl LineSeparator {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Newline => "\n",
+            Self::Br => "<br>",
+            Self::None => "",
+        }
+    }
+}
+
+/// Where rendered HTML is embedded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HtmlContext {
+    /// As markup, directly in a document.
+    #[default]
+    Normal,
+    /// As the value of a quoted attribute, like `srcdoc` or a `data-` attribute: markup
+    /// characters, quotes and ampersands are escaped, so the value decodes to the
+    /// normal output.
+    Attribute,
+    /// As markup whose entities are decoded once before it is parsed, like a template
+    /// string unescaped when instantiated: ampersands are escaped, so escaped text
+    /// survives the extra decoding.
+    Template,
+}
+
+/// Per-line numeric values, bucketed into `heat-0` … `heat-{buckets - 1}`.
+///
+/// Lines are 1-based. Lines without a value get no heat class.
+#[derive(Debug, Clone)]
+pub struct LineHeat {
+    pub values: HashMap<usize, f64>,
+    pub buckets: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl LineHeat {
+    pub fn new(values: HashMap<usize, f64>) -> Self {
+        Self {
+            values,
+            buckets: 10,
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn buckets(mut self, buckets: usize) -> Self {
+        self.buckets = buckets;
+        self
+    }
+
+    pub fn min(mut self, min: f64) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn max(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// The `(min, max)` range, auto-computed from the finite values unless overridden.
+    pub fn bounds(&self) -> (f64, f64) {
+        let values = self.values.values().copied().filter(|v| v.is_finite());
+        let min = self
+            .min
+            .unwrap_or_else(|| values.clone().fold(f64::INFINITY, f64::min));
+        let max = self
+            .max
+            .unwrap_or_else(|| values.fold(f64::NEG_INFINITY, f64::max));
+        (min, max)
+    }
+
+    /// Buckets a value into `0..buckets`, clamping values outside the bounds.
+    pub fn bucket(&self, value: f64, (min, max): (f64, f64)) -> usize {
+        let buckets = self.buckets.max(1);
+        if max <= min {
+            return 0;
+        }
+        let t = ((value - min) / (max - min)).clamp(0.0, 1.0);
+        ((t * buckets as f64) as usize).min(buckets - 1)
+    }
+
+    /// Returns the bucket and the raw value of a line.
+    pub(crate) fn lookup(&self, line: usize, bounds: (f64, f64)) -> Option<(usize, f64)> {
+        self.values
+            .get(&line)
+            .filter(|v| v.is_finite())
+            .map(|v| (self.bucket(*v, bounds), *v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    #[test]
+    fn heat_buckets() {
+        let heat = LineHeat::new(HashMap::from([(1, 0.0), (2, 10.0)]));
+        let bounds = heat.bounds();
+        assert_eq!(bounds, (0.0, 10.0));
+        assert_eq!(heat.bucket(0.0, bounds), 0);
+        assert_eq!(heat.bucket(0.999, bounds), 0);
+        assert_eq!(heat.bucket(1.0, bounds), 1);
+        assert_eq!(heat.bucket(9.999, bounds), 9);
+        assert_eq!(heat.bucket(10.0, bounds), 9);
+        assert_eq!(heat.bucket(-5.0, bounds), 0);
+        assert_eq!(heat.bucket(50.0, bounds), 9);
+
+        let heat = heat.buckets(4).min(0.0).max(100.0);
+        let bounds = heat.bounds();
+        assert_eq!(bounds, (0.0, 100.0));
+        assert_eq!(heat.bucket(24.9, bounds), 0);
+        assert_eq!(heat.bucket(25.0, bounds), 1);
+        assert_eq!(heat.bucket(75.0, bounds), 3);
+
+        let heat = LineHeat::new(HashMap::from([(1, 3.0), (2, 3.0)]));
+        assert_eq!(heat.lookup(1, heat.bounds()), Some((0, 3.0)));
+        assert_eq!(heat.lookup(3, heat.bounds()), None);
+    }
+
+    #[test]
+    fn collapsed_ranges() {
+        let options = RenderOptions::new()
+            .collapse(8..9)
+            .collapse(3..6)
+            .collapse(5..7)
+            .collapse(2..2);
+        assert_eq!(options.collapsed_ranges(), vec![3..7, 8..9]);
+
+        let options = RenderOptions::new()
+            .collapse(0..1)
+            .collapse(3..5)
+            .collapse(0..2);
+        assert_eq!(options.collapsed_ranges(), vec![1..2, 3..5]);
+    }
+
+    #[test]
+    fn collapse() {
+        let languages = rust();
+        let source = b"let a;\nlet b;\nlet c;\nlet d;\nlet e;\nlet f;\n";
+
+        let html = languages
+            .render_with("rust", source, &RenderOptions::new().collapse(3..6))
+            .unwrap();
+        let (before, rest) = html.split_once("<span class=line-collapsed>").unwrap();
+        let (inside, after) = rest.split_once("</span></span>").unwrap();
+        assert!(before.contains(">b<") && !before.contains(">c<"));
+        assert!(inside.contains(">c<") && inside.contains(">d<") && inside.contains(">e<"));
+        assert!(!inside.contains(">f<") && after.contains(">f<"));
+        assert_eq!(inside.matches("<span class=line>").count(), 3);
+
+        let html = languages
+            .render_with(
+                "rust",
+                source,
+                &RenderOptions::new().collapse(3..6).collapse_details(true),
+            )
+            .unwrap();
+        assert!(html.contains("<details class=line-collapsed><summary>3 lines</summary><span class=line><span class=keyword>let</span> <span class=variable>c</span>"));
+        assert!(html.contains("<span class=variable>e</span><span class=punctuation.delimiter>;</span>\n</span></details><span class=line>"));
+
+        // A range without lines leaves the next one.
+        let html = languages
+            .render_with(
+                "rust",
+                source,
+                &RenderOptions::new().collapse(0..1).collapse(3..5),
+            )
+            .unwrap();
+        assert_eq!(html.matches("<span class=line-collapsed>").count(), 1);
+        let (before, rest) = html.split_once("<span class=line-collapsed>").unwrap();
+        let (inside, _) = rest.split_once("</span></span>").unwrap();
+        assert!(before.contains(">a<") && before.contains(">b<"));
+        assert!(inside.contains(">c<") && inside.contains(">d<") && !inside.contains(">e<"));
+    }
+
+    #[test]
+    fn heat_lines() {
+        let languages = rust();
+        let options = RenderOptions::new().line_heat(
+            LineHeat::new(HashMap::from([(1, 0.25), (3, 3.0)]))
+                .min(0.0)
+                .max(3.0),
+        );
+        let html = languages
+            .render_with("rust", b"let a = 1;\nlet b = 2;\nlet c = 3;\n", &options)
+            .unwrap();
+
+        assert!(html.contains(
+            r#"<span class="line heat-0" data-value="0.25"><span class=keyword>let</span>"#
+        ));
+        assert!(html.contains(r#"</span><span class=line><span class=keyword>let</span> <span class=variable>b</span>"#));
+        assert!(html.contains(r#"<span class="line heat-9" data-value="3"><span class=keyword>let</span> <span class=variable>c</span>"#));
+    }
+
+    #[test]
+    fn conflicts() {
+        let languages = rust();
+        let source = b"<<<<<<< HEAD\nlet a = 1;\n=======\nlet a = 2;\n>>>>>>> feature\n";
+        let html = languages
+            .render_with("rust", source, &RenderOptions::new().mark_conflicts(true))
+            .unwrap();
+        let lines = html
+            .split("<span class=")
+            .filter(|s| s.starts_with("line") || s.starts_with("\"line"))
+            .map(|s| s.split('>').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "\"line conflict-ours\"",
+                "line",
+                "\"line conflict-sep\"",
+                "line",
+                "\"line conflict-theirs\""
+            ]
+        );
+
+        let html = languages.render("rust", source).unwrap();
+        assert!(!html.contains("conflict"));
+    }
+}
@@ -0,0 +1,237 @@
+use std::ops::Range;
+
+use tree_sitter::{Node, QueryCursor, Tree};
+use tree_sitter_highlight::HighlightConfiguration;
+
+use crate::{
+    folds::{folds, last_row},
+    Error, Languages,
+};
+
+/// The kind of a [`Symbol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Type,
+}
+
+impl SymbolKind {
+    /// The kind of a definition capture name: `definition.function`, `definition.method`
+    /// or `definition.type` of a locals query, with or without the `local.` prefix, or
+    /// `type.definition` of a highlights query.
+    fn from_capture(name: &str) -> Option<Self> {
+        match name.strip_prefix("local.").unwrap_or(name) {
+            "definition.function" => Some(Self::Function),
+            "definition.method" => Some(Self::Method),
+            "definition.type" | "type.definition" => Some(Self::Type),
+            _ => None,
+        }
+    }
+}
+
+/// A function, method or type defined in a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The 1-based line of the name.
+    pub line: usize,
+    /// The byte range of the name.
+    pub byte_range: Range<usize>,
+}
+
+/// A construct enclosing a line, see [`Languages::line_contexts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextFrame {
+    /// The grammar's kind of the node, such as `function_item`.
+    pub kind: String,
+    /// The defined name, or the trimmed first line of constructs defining none, such as
+    /// `if let Err(err) = f()`.
+    pub name: String,
+    /// The 1-based line the construct starts on.
+    pub line: usize,
+}
+
+/// The definitions of a source, with the names captured for them.
+fn definitions<'t>(
+    config: &HighlightConfiguration,
+    tree: &'t Tree,
+    source: &[u8],
+) -> Vec<(Symbol, Node<'t>)> {
+    let names = config.query.capture_names();
+    let mut symbols: Vec<(Symbol, Node<'t>)> = Vec::new();
+    let mut cursor = QueryCursor::new();
+    for m in cursor.matches(&config.query, tree.root_node(), source) {
+        for c in m.captures {
+            let Some(kind) = SymbolKind::from_capture(&names[c.index as usize]) else {
+                continue;
+            };
+            let Ok(name) = c.node.utf8_text(source) else {
+                continue;
+            };
+            let symbol = Symbol {
+                name: name.to_string(),
+                kind,
+                line: c.node.start_position().row + 1,
+                byte_range: c.node.byte_range(),
+            };
+            symbols.push((symbol, c.node));
+        }
+    }
+    symbols.sort_by_key(|(s, _)| (s.byte_range.start, s.kind != SymbolKind::Method));
+    symbols.dedup_by(|(s, _), (first, _)| s.byte_range == first.byte_range);
+    symbols
+}
+
+impl<'a> Languages<'a> {
+    /// The functions, methods and types a source defines, in source order, for a sidebar
+    /// next to a long listing.
+    ///
+    /// Symbols come from the definition captures of the language's queries, see
+    /// [`SymbolKind`], so nesting and visibility are not known. A function captured as a
+    /// method too is a method.
+    pub fn outline(&self, lang: &str, source: &[u8]) -> Result<Vec<Symbol>, Error> {
+        let entry = self.entry(lang)?;
+        let tree = self.tree(entry, source)?;
+        Ok(definitions(&entry.inner.0, &tree, source)
+            .into_iter()
+            .map(|(symbol, _)| symbol)
+            .collect())
+    }
+
+    /// The constructs enclosing each line, outermost first, for "sticky scroll" headers.
+    ///
+    /// The constructs are the parents of the definitions of [`Languages::outline`] and,
+    /// with a [folds query](Languages::set_folds_query), the folds spanning several lines,
+    /// such as `if` blocks. Line `n` is at index `n - 1`, and a construct encloses the
+    /// line it starts on.
+    pub fn line_contexts(
+        &self,
+        lang: &str,
+        source: &[u8],
+    ) -> Result<Vec<Vec<ContextFrame>>, Error> {
+        let entry = self.entry(lang)?;
+        let tree = self.tree(entry, source)?;
+
+        let mut frames = definitions(&entry.inner.0, &tree, source)
+            .into_iter()
+            .filter_map(|(symbol, node)| Some((node.parent()?, symbol.name)))
+            .collect::<Vec<_>>();
+        if let Some(query) = &entry.folds {
+            for node in folds(query, &tree, source) {
+                if last_row(&node) == node.start_position().row
+                    || frames
+                        .iter()
+                        .any(|(n, _)| n.byte_range() == node.byte_range())
+                {
+                    continue;
+                }
+                let line = &source[node.start_byte()..];
+                let line = &line[..line.iter().position(|b| *b == b'\n').unwrap_or(line.len())];
+                let name = String::from_utf8_lossy(line);
+                let name = name.trim().trim_end_matches('{').trim_end();
+                frames.push((node, name.to_string()));
+            }
+        }
+        frames.sort_by_key(|(node, _)| (node.start_byte(), usize::MAX - node.end_byte()));
+
+        let count = source.iter().filter(|b| **b == b'\n').count()
+            + usize::from(source.last().is_none_or(|b| *b != b'\n'));
+        let mut lines = vec![Vec::new(); count];
+        for (node, name) in frames {
+            let frame = ContextFrame {
+                kind: node.kind().to_string(),
+                name,
+                line: node.start_position().row + 1,
+            };
+            for line in lines
+                .iter_mut()
+                .take(last_row(&node) + 1)
+                .skip(node.start_position().row)
+            {
+                line.push(frame.clone());
+            }
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    #[test]
+    fn kinds() {
+        assert_eq!(
+            SymbolKind::from_capture("definition.function"),
+            Some(SymbolKind::Function)
+        );
+        assert_eq!(
+            SymbolKind::from_capture("local.definition.method"),
+            Some(SymbolKind::Method)
+        );
+        assert_eq!(
+            SymbolKind::from_capture("type.definition"),
+            Some(SymbolKind::Type)
+        );
+        assert_eq!(SymbolKind::from_capture("definition.var"), None);
+        assert_eq!(SymbolKind::from_capture("function"), None);
+    }
+
+    #[test]
+    fn outline() {
+        let languages = rust();
+        let symbols = languages
+            .outline("rust", include_bytes!("../fixtures/sample.rs"))
+            .unwrap();
+        let functions = symbols
+            .iter()
+            .filter(|s| s.kind == SymbolKind::Function)
+            .map(|s| (s.name.as_str(), s.line))
+            .collect::<Vec<_>>();
+        assert_eq!(functions, [("index", 5), ("main", 10)]);
+
+        let source = b"struct S;\nimpl S {\n    fn get(&self) {}\n}\n";
+        let symbols = languages.outline("rust", source).unwrap();
+        assert_eq!(
+            symbols
+                .iter()
+                .map(|s| (s.name.as_str(), s.kind, s.line))
+                .collect::<Vec<_>>(),
+            [("S", SymbolKind::Type, 1), ("get", SymbolKind::Method, 3)]
+        );
+        assert_eq!(&source[symbols[1].byte_range.clone()], b"get");
+        assert!(matches!(
+            languages.outline("cobol", b""),
+            Err(Error::UnknownLanguage(_))
+        ));
+    }
+
+    #[test]
+    fn line_contexts() {
+        let mut languages = rust();
+        languages
+            .set_folds_query("rust", include_str!("../queries/rust/folds.scm"))
+            .unwrap();
+        let lines = languages
+            .line_contexts("rust", include_bytes!("../fixtures/sample.rs"))
+            .unwrap();
+        let frames = |line: usize| {
+            lines[line - 1]
+                .iter()
+                .map(|f| (f.name.as_str(), f.line))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(frames(2), []);
+        assert_eq!(frames(6), [("index", 5)]);
+        assert_eq!(lines[5][0].kind, "function_item");
+        assert_eq!(frames(11), [("main", 10)]);
+        assert_eq!(
+            frames(20),
+            [("main", 10), ("if let Err(err) = Server::bind(&addr)", 16)]
+        );
+        assert_eq!(frames(25), []);
+    }
+}
@@ -0,0 +1,234 @@
+use std::ops::Range;
+
+use tree_sitter_highlight::{Highlight, HighlightEvent};
+
+use crate::{
+    html::{escape, Buffers},
+    write_events, Error, Languages, RenderOptions,
+};
+
+/// Splits overlapping overlay ranges into sorted, disjoint ones, later ranges winning.
+fn paint(overlay: &[(Range<usize>, usize)]) -> Vec<(Range<usize>, usize)> {
+    let mut points = overlay
+        .iter()
+        .filter(|(r, _)| !r.is_empty())
+        .flat_map(|(r, _)| [r.start, r.end])
+        .collect::<Vec<_>>();
+    points.sort_unstable();
+    points.dedup();
+
+    let mut painted: Vec<(Range<usize>, usize)> = Vec::new();
+    for pair in points.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let Some((_, h)) = overlay.iter().rev().find(|(r, _)| r.contains(&start)) else {
+            continue;
+        };
+        match painted.last_mut() {
+            Some((last, last_h)) if last.end == start && last_h == h => last.end = end,
+            _ => painted.push((start..end, *h)),
+        }
+    }
+    painted
+}
+
+/// The events of highlighted or plain ranges with the innermost capture of each, the
+/// painted ranges taking precedence.
+fn split(
+    painted: &[(Range<usize>, usize)],
+    captures: impl IntoIterator<Item = (Range<usize>, Option<usize>)>,
+) -> Vec<HighlightEvent> {
+    let mut events = Vec::new();
+    let mut push = |range: Range<usize>, h: Option<usize>| {
+        if let Some(h) = h {
+            events.push(HighlightEvent::HighlightStart(Highlight(h)));
+        }
+        events.push(HighlightEvent::Source {
+            start: range.start,
+            end: range.end,
+        });
+        if h.is_some() {
+            events.push(HighlightEvent::HighlightEnd);
+        }
+    };
+    let mut i = 0;
+    for (range, capture) in captures {
+        let mut start = range.start;
+        while start < range.end {
+            while painted.get(i).is_some_and(|(r, _)| r.end <= start) {
+                i += 1;
+            }
+            match painted.get(i) {
+                Some((r, h)) if r.start <= start => {
+                    let end = r.end.min(range.end);
+                    push(start..end, Some(*h));
+                    start = end;
+                }
+                Some((r, _)) if r.start < range.end => {
+                    push(start..r.start, capture);
+                    start = r.start;
+                }
+                _ => {
+                    push(start..range.end, capture);
+                    start = range.end;
+                }
+            }
+        }
+    }
+    events
+}
+
+/// Applies the [`RenderOptions::overrides`] of a render to its events, with `names` the
+/// capture names of the host language.
+pub(crate) fn apply_overrides(
+    names: &[String],
+    source: &[u8],
+    events: Vec<HighlightEvent>,
+    options: &RenderOptions,
+) -> Result<Vec<HighlightEvent>, Error> {
+    let mut overlay = Vec::with_capacity(options.overrides.len());
+    for (range, name) in &options.overrides {
+        let h = names.iter().position(|n| !n.is_empty() && n == name);
+        match h {
+            Some(h) if range.start <= range.end && range.end <= source.len() => {
+                overlay.push((range.clone(), h))
+            }
+            _ if options.strict_overrides => {
+                return Err(Error::InvalidOverride(range.clone(), name.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    let mut stack = Vec::new();
+    let captures = events.into_iter().filter_map(|event| match event {
+        HighlightEvent::HighlightStart(h) => {
+            stack.push(h.0);
+            None
+        }
+        HighlightEvent::HighlightEnd => {
+            stack.pop();
+            None
+        }
+        HighlightEvent::Source { start, end } => Some((start..end, stack.last().copied())),
+    });
+    Ok(split(&paint(&overlay), captures))
+}
+
+impl<'a> Languages<'a> {
+    /// Renders with the classes of an overlay, like LSP semantic tokens, taking
+    /// precedence over the grammar's.
+    ///
+    /// Later overlay ranges win over earlier ones. Unlike [`Languages::render`], the
+    /// highlights are flattened to one span per token, so an overlay class replaces the
+    /// grammar classes instead of nesting inside them.
+    pub fn render_with_overlay(
+        &self,
+        lang: &str,
+        source: &[u8],
+        overlay: &[(Range<usize>, &str)],
+    ) -> Option<String> {
+        let mut classes = self
+            .injection_classes(Some(self.lookup(lang)?))
+            .into_owned();
+        let overlay = overlay
+            .iter()
+            .map(|(range, name)| {
+                let h = match self.names.iter().position(|n| n == name) {
+                    Some(h) => h,
+                    None => {
+                        let mut class = String::from("class=\"");
+                        name.chars().for_each(|c| escape(&mut class, c));
+                        class.push('"');
+                        classes.push(class);
+                        classes.len() - 1
+                    }
+                };
+                (range.clone(), h)
+            })
+            .collect::<Vec<_>>();
+        let mut captures = Vec::new();
+        self.for_each_capture(lang, source, |range, capture| {
+            captures.push((range, capture))
+        })
+        .ok()?;
+        let events = split(&paint(&overlay), captures);
+
+        let mut s = String::new();
+        write_events(
+            lang,
+            source,
+            events.into_iter().map(Ok),
+            &RenderOptions::default(),
+            &classes,
+            &mut Buffers::default(),
+            &mut s,
+        )
+        .ok()?;
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    #[test]
+    fn painted() {
+        assert_eq!(
+            paint(&[(0..10, 1), (2..4, 2), (3..6, 3), (8..8, 4)]),
+            [(0..2, 1), (2..3, 2), (3..6, 3), (6..10, 1)]
+        );
+    }
+
+    #[test]
+    fn overlay() {
+        let languages = rust();
+        let source = b"let a = 1;";
+        assert!(languages
+            .render("rust", source)
+            .unwrap()
+            .contains("<span class=variable>a</span>"));
+
+        let html = languages
+            .render_with_overlay("rust", source, &[(4..5, "type"), (0..2, "x\"y")])
+            .unwrap();
+        assert!(html.contains("<span class=type>a</span>"));
+        assert!(!html.contains("variable"));
+        assert!(html.contains("<span class=\"x&quot;y\">le</span><span class=keyword>t</span>"));
+        assert!(languages.render_with_overlay("go", source, &[]).is_none());
+    }
+
+    #[test]
+    fn overrides() {
+        let languages = rust();
+        let source = b"let a = b;";
+        let options = RenderOptions::new().overrides(&[(4..5, "type"), (20..30, "type")]);
+        let html = languages.render_with("rust", source, &options).unwrap();
+        assert!(html.contains("<span class=type>a</span>"), "{html}");
+        assert_eq!(html.matches("class=type").count(), 1, "{html}");
+        assert!(html.contains("<span class=variable>b</span>"), "{html}");
+        assert!(html.contains("<span class=keyword>let</span>"), "{html}");
+
+        // A semantic token inside a token splits it.
+        let options = RenderOptions::new().overrides(&[(1..2, "function")]);
+        let html = languages.render_with("rust", source, &options).unwrap();
+        assert!(html.contains(
+            "<span class=keyword>l</span><span class=function>e</span><span class=keyword>t</span>"
+        ));
+
+        let unknown = RenderOptions::new().overrides(&[(4..5, "trait")]);
+        assert_eq!(
+            languages.render_with("rust", source, &unknown).unwrap(),
+            languages.render("rust", source).unwrap()
+        );
+        assert!(matches!(
+            languages.render_with("rust", source, &unknown.strict_overrides(true)),
+            Err(Error::InvalidOverride(r, name)) if r == (4..5) && name == "trait"
+        ));
+        let outside = RenderOptions::new()
+            .overrides(&[(8..11, "type")])
+            .strict_overrides(true);
+        assert!(languages.render_with("rust", source, &outside).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use crate::{html::escape, Error, FontStyle, Languages, Theme};
+
+impl<'a> Languages<'a> {
+    /// Renders to Pango markup for GTK labels and text views.
+    ///
+    /// Styled tokens are `<span>`s with `foreground`, `weight`, `style`, `underline` and
+    /// `strikethrough` attributes, and source lines are joined with `\n` without a trailing one.
+    pub fn render_pango(&self, lang: &str, source: &[u8], theme: &Theme) -> Result<String, Error> {
+        let mut lines = vec![String::new()];
+        self.for_each_token(lang, source, |range, name| {
+            let style = name.map(|name| theme.resolve(name)).unwrap_or_default();
+            let mut open = String::new();
+            if let Some(fg) = style.fg {
+                let _ = write!(open, " foreground=\"{fg}\"");
+            }
+            for font_style in style.font_styles() {
+                open.push_str(match font_style {
+                    FontStyle::Bold => " weight=\"bold\"",
+                    FontStyle::Italic => " style=\"italic\"",
+                    FontStyle::Underline => " underline=\"single\"",
+                    FontStyle::Strikethrough => " strikethrough=\"true\"",
+                });
+            }
+            for (i, text) in String::from_utf8_lossy(&source[range])
+                .split('\n')
+                .enumerate()
+            {
+                if i > 0 {
+                    lines.push(String::new());
+                }
+                let line = lines.last_mut().unwrap();
+                let mut span = String::new();
+                text.chars()
+                    .filter(|c| *c == '\t' || !c.is_control())
+                    .for_each(|c| escape(&mut span, c));
+                if span.is_empty() {
+                    continue;
+                }
+                if open.is_empty() {
+                    line.push_str(&span);
+                } else {
+                    let _ = write!(line, "<span{open}>{span}</span>");
+                }
+            }
+        })?;
+        if lines.len() > 1 && lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::rust, Color, Style, Theme};
+
+    #[test]
+    fn render() {
+        let languages = rust();
+        let theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            ("string", Style::new().italic()),
+        ]);
+        let markup = languages
+            .render_pango("rust", b"let s = \"<&>\";\nlet t;\n", &theme)
+            .unwrap();
+        assert_eq!(
+            markup,
+            "<span foreground=\"#c678dd\" weight=\"bold\">let</span> s = <span style=\"italic\">&quot;&lt;&amp;&gt;&quot;</span>;\n<span foreground=\"#c678dd\" weight=\"bold\">let</span> t;"
+        );
+
+        let markup = format!("<markup>{markup}</markup>");
+        let doc = roxmltree::Document::parse(&markup).unwrap();
+        let spans = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("span"))
+            .collect::<Vec<_>>();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].attribute("weight"), Some("bold"));
+        assert_eq!(spans[1].attribute("style"), Some("italic"));
+        assert_eq!(spans[1].text(), Some("\"<&>\""));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use crate::{class::ClassStyle, html::escape, Error, Languages};
+
+/// An output format driven by [`Languages::render_to_renderer`].
+///
+/// The driver calls `start`, then `token` and `newline` in source order with the
+/// `start_capture`/`end_capture` pairs of nested captures around them, then `finish`.
+/// Tokens never contain `\n`; each line break is a `newline` call.
+pub trait Renderer {
+    type Output;
+
+    fn start(&mut self, lang: &str);
+
+    /// Called with a piece of the source and its innermost capture name.
+    fn token(&mut self, text: &[u8], capture: Option<&str>);
+
+    fn newline(&mut self);
+
+    /// Called when a capture opens, for formats that nest.
+    fn start_capture(&mut self, _name: &str) {}
+
+    /// Called when the last opened capture closes.
+    fn end_capture(&mut self) {}
+
+    fn finish(&mut self) -> Self::Output;
+}
+
+/// The HTML output of [`Languages::render`] as a [`Renderer`], for the default classes.
+#[derive(Debug, Default)]
+pub struct Html {
+    html: String,
+    line: String,
+    lines: usize,
+    /// The `class=` attribute of each capture name seen, built once.
+    attrs: Vec<String>,
+    indices: HashMap<String, usize>,
+    /// The indices in `attrs` of the open captures.
+    open: Vec<usize>,
+}
+
+impl Html {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn open_span(line: &mut String, attrs: &str) {
+        line.push_str("<span ");
+        line.push_str(attrs);
+        line.push('>');
+    }
+
+    fn flush_line(&mut self) {
+        self.html.push_str("<span class=line>");
+        self.html.push_str(&self.line);
+        self.html.push_str("</span>");
+        self.line.clear();
+        self.lines += 1;
+    }
+}
+
+impl Renderer for Html {
+    type Output = String;
+
+    fn start(&mut self, lang: &str) {
+        self.html.clear();
+        self.line.clear();
+        self.lines = 0;
+        self.open.clear();
+        self.html.push_str("<pre class=language-");
+        self.html.push_str(lang);
+        self.html.push_str("><code>");
+    }
+
+    fn token(&mut self, text: &[u8], _capture: Option<&str>) {
+        for c in String::from_utf8_lossy(text).chars() {
+            if c != '\r' {
+                escape(&mut self.line, c);
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        for _ in &self.open {
+            self.line.push_str("</span>");
+        }
+        self.line.push('\n');
+        self.flush_line();
+        for i in &self.open {
+            Self::open_span(&mut self.line, &self.attrs[*i]);
+        }
+    }
+
+    fn start_capture(&mut self, name: &str) {
+        let i = match self.indices.get(name) {
+            Some(i) => *i,
+            None => {
+                self.attrs.push(ClassStyle::default().attr(name));
+                self.indices.insert(name.to_string(), self.attrs.len() - 1);
+                self.attrs.len() - 1
+            }
+        };
+        Self::open_span(&mut self.line, &self.attrs[i]);
+        self.open.push(i);
+    }
+
+    fn end_capture(&mut self) {
+        self.open.pop();
+        self.line.push_str("</span>");
+    }
+
+    fn finish(&mut self) -> String {
+        if !self.line.is_empty() || self.lines == 0 {
+            self.line.push('\n');
+            self.flush_line();
+        }
+        self.html.push_str("</code></pre>");
+        std::mem::take(&mut self.html)
+    }
+}
+
+impl<'a> Languages<'a> {
+    /// Renders with a custom output format.
+    pub fn render_to_renderer<R: Renderer>(
+        &self,
+        lang: &str,
+        source: &[u8],
+        renderer: &mut R,
+    ) -> Result<R::Output, Error> {
+        self.entry(lang)?;
+        let name = |i: usize| self.names.get(i).map_or("", String::as_str);
+
+        renderer.start(lang);
+        let mut stack = Vec::new();
+        self.for_each_event(lang, source, |event| match event {
+            tree_sitter_highlight::HighlightEvent::HighlightStart(h) => {
+                stack.push(h.0);
+                renderer.start_capture(name(h.0));
+            }
+            tree_sitter_highlight::HighlightEvent::HighlightEnd => {
+                stack.pop();
+                renderer.end_capture();
+            }
+            tree_sitter_highlight::HighlightEvent::Source { start, end } => {
+                let capture = stack.last().map(|i| name(*i));
+                for (i, text) in source[start..end].split(|b| *b == b'\n').enumerate() {
+                    if i > 0 {
+                        renderer.newline();
+                    }
+                    if !text.is_empty() {
+                        renderer.token(text, capture);
+                    }
+                }
+            }
+        })?;
+        Ok(renderer.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    #[test]
+    fn html() {
+        let languages = rust();
+        let sources: [&[u8]; 6] = [
+            include_bytes!("../fixtures/sample.rs"),
+            b"",
+            b"\n\n",
+            b"let s = \"a\\nb\r\n<&>\";\n",
+            b"/* a\nb */ fn f() {}",
+            b"\xff\n\xe6\x97",
+        ];
+        for source in sources {
+            assert_eq!(
+                languages
+                    .render_to_renderer("rust", source, &mut Html::new())
+                    .unwrap(),
+                languages.render("rust", source).unwrap()
+            );
+        }
+    }
+
+    #[derive(Default)]
+    struct Count {
+        tokens: usize,
+        captured: usize,
+        lines: usize,
+    }
+
+    impl Renderer for Count {
+        type Output = (usize, usize, usize);
+
+        fn start(&mut self, _lang: &str) {
+            self.lines = 1;
+        }
+
+        fn token(&mut self, _text: &[u8], capture: Option<&str>) {
+            self.tokens += 1;
+            self.captured += usize::from(capture.is_some());
+        }
+
+        fn newline(&mut self) {
+            self.lines += 1;
+        }
+
+        fn finish(&mut self) -> Self::Output {
+            (self.tokens, self.captured, self.lines)
+        }
+    }
+
+    #[test]
+    fn count() {
+        let languages = rust();
+        // `let`, ` `, `a`, ` `, `=`, ` `, `1`, `;`, then `let`, ` `, `b`, `;`
+        assert_eq!(
+            languages
+                .render_to_renderer("rust", b"let a = 1;\nlet b;", &mut Count::default())
+                .unwrap(),
+            (12, 8, 2)
+        );
+        assert!(languages
+            .render_to_renderer("go", b"", &mut Count::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+use crate::{Error, FontStyle, Languages, Theme};
+
+/// Escapes RTF special characters and encodes non-ASCII characters as `\uN?`.
+fn escape(s: &mut String, c: char) {
+    match c {
+        '\\' | '{' | '}' => {
+            s.push('\\');
+            s.push(c);
+        }
+        '\t' => s.push_str("\\tab "),
+        c if c.is_ascii() => s.push(c),
+        c => {
+            let mut units = [0; 2];
+            for unit in c.encode_utf16(&mut units) {
+                let _ = write!(s, "\\u{}?", *unit as i16);
+            }
+        }
+    }
+}
+
+impl<'a> Languages<'a> {
+    /// Renders to an RTF document in Courier New, for pasting into word processors.
+    ///
+    /// The color table holds the distinct theme colors, and each styled token is a group
+    /// with `\cfN`, `\b`, `\i` and `\ul` switches.
+    pub fn render_rtf(&self, lang: &str, source: &[u8], theme: &Theme) -> Result<String, Error> {
+        let colors = theme.colors();
+        // Index 0 is the default color.
+        let index = |color| colors.iter().position(|c| *c == color).unwrap_or(0) + 1;
+
+        let mut lines = vec![String::new()];
+        self.for_each_token(lang, source, |range, name| {
+            let style = name.map(|name| theme.resolve(name)).unwrap_or_default();
+            let mut open = String::new();
+            if let Some(fg) = style.fg {
+                let _ = write!(open, "\\cf{}", index(fg));
+            }
+            for font_style in style.font_styles() {
+                open.push_str(match font_style {
+                    FontStyle::Bold => "\\b",
+                    FontStyle::Italic => "\\i",
+                    FontStyle::Underline => "\\ul",
+                    FontStyle::Strikethrough => "\\strike",
+                });
+            }
+            for (i, text) in String::from_utf8_lossy(&source[range])
+                .split('\n')
+                .enumerate()
+            {
+                if i > 0 {
+                    lines.push(String::new());
+                }
+                let line = lines.last_mut().unwrap();
+                let mut span = String::new();
+                text.chars()
+                    .filter(|c| *c == '\t' || !c.is_control())
+                    .for_each(|c| escape(&mut span, c));
+                if span.is_empty() {
+                    continue;
+                }
+                if open.is_empty() {
+                    line.push_str(&span);
+                } else {
+                    let _ = write!(line, "{{{open} {span}}}");
+                }
+            }
+        })?;
+        if lines.len() > 1 && lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+
+        let mut s = String::from("{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fmodern Courier New;}}\n");
+        s.push_str("{\\colortbl;");
+        for color in &colors {
+            let _ = write!(s, "\\red{}\\green{}\\blue{};", color.r, color.g, color.b);
+        }
+        s.push_str("}\n\\f0\\fs20");
+        if let Some(fg) = theme.foreground {
+            let _ = write!(s, "\\cf{}", index(fg));
+        }
+        s.push(' ');
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                s.push_str("\\line\n");
+            }
+            s.push_str(line);
+        }
+        s.push_str("\n}\n");
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, Color, Style};
+
+    #[test]
+    fn escaping() {
+        let mut s = String::new();
+        "{\\}\té😀".chars().for_each(|c| escape(&mut s, c));
+        assert_eq!(s, "\\{\\\\\\}\\tab \\u233?\\u-10179?\\u-8704?");
+    }
+
+    #[test]
+    fn render() {
+        let languages = rust();
+        let mut theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            (
+                "keyword.function",
+                Style::new().fg(Color::rgb(198, 120, 221)),
+            ),
+            ("string", Style::new().fg(Color::rgb(152, 195, 121))),
+        ]);
+        theme.foreground = Some(Color::rgb(171, 178, 191));
+
+        let rtf = languages
+            .render_rtf("rust", b"let s = \"{}\";\nlet t;\n", &theme)
+            .unwrap();
+        let table = &rtf[rtf.find("{\\colortbl;").unwrap()..];
+        let table = &table[..table.find('}').unwrap()];
+        assert_eq!(table.matches("\\red").count(), 3);
+        assert_eq!(
+            table,
+            "{\\colortbl;\\red152\\green195\\blue121;\\red171\\green178\\blue191;\\red198\\green120\\blue221;"
+        );
+        assert!(rtf.contains(
+            "\\f0\\fs20\\cf2 {\\cf3\\b let} s = {\\cf1 \"\\{\\}\"};\\line\n{\\cf3\\b let} t;\n}\n"
+        ));
+    }
+}
@@ -0,0 +1,315 @@
+use std::hash::Hasher;
+
+use tree_sitter::{InputEdit, Parser, Tree};
+
+use crate::{Error, Fingerprint, Languages, RenderOptions, Scratch};
+
+/// A source being edited, such as the buffer of a live playground, rendered again after
+/// each change.
+///
+/// The session owns its parser, highlighter and buffers, so the registry stays shared. Its syntax
+/// tree is parsed incrementally from the previous one on each [`Session::edit`].
+/// tree-sitter-highlight cannot start from a given tree, so [`Session::render`] still
+/// highlights the whole text, with the session's highlighter.
+pub struct Session<'l, 'a> {
+    languages: &'l Languages<'a>,
+    lang: String,
+    parser: Parser,
+    scratch: Scratch,
+    text: Vec<u8>,
+    tree: Option<Tree>,
+    /// The lines of the last [`Session::edit_and_diff`], which a client shows, with the
+    /// fingerprint of their options.
+    lines: Option<(u64, Vec<String>)>,
+}
+
+/// The lines whose HTML changed in an edit, see [`Session::edit_and_diff`].
+///
+/// Lines are 0-based, as in [`Languages::render_lines`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineDiff {
+    /// The lines whose HTML changed, by index after the edit, with their HTML.
+    pub changed: Vec<(usize, String)>,
+    /// The new lines, by index after the edit, with their HTML.
+    pub inserted: Vec<(usize, String)>,
+    /// The lines gone, by index before the edit.
+    pub removed: Vec<usize>,
+    /// The unchanged lines that moved, by index before and after the edit.
+    pub renumbered: Vec<(usize, usize)>,
+}
+
+impl<'a> Languages<'a> {
+    /// Starts an editing session of an empty source in a language.
+    pub fn session(&self, lang: &str) -> Result<Session<'_, 'a>, Error> {
+        let entry = self.entry(lang)?;
+        let mut parser = Parser::new();
+        parser
+            .set_language(entry.inner.0.language)
+            .map_err(|_| tree_sitter_highlight::Error::InvalidLanguage)?;
+        let mut session = Session {
+            languages: self,
+            lang: lang.to_string(),
+            parser,
+            scratch: self.scratch(),
+            text: Vec::new(),
+            tree: None,
+            lines: None,
+        };
+        session.set_text(b"");
+        Ok(session)
+    }
+}
+
+impl Session<'_, '_> {
+    /// Replaces the whole text, parsing it from scratch.
+    pub fn set_text(&mut self, text: &[u8]) {
+        self.text = text.to_vec();
+        self.tree = self.parser.parse(&self.text, None);
+    }
+
+    /// Applies an edit, with `text` the whole text after it, parsing it from the
+    /// previous tree.
+    ///
+    /// The edit describes the change from the previous text to `text` in bytes and rows
+    /// and columns, as for [`Tree::edit`].
+    pub fn edit(&mut self, edit: &InputEdit, text: &[u8]) {
+        if let Some(tree) = &mut self.tree {
+            tree.edit(edit);
+        }
+        self.text = text.to_vec();
+        self.tree = self.parser.parse(&self.text, self.tree.as_ref());
+    }
+
+    /// Applies an edit like [`Session::edit`] and reports the lines whose HTML changed,
+    /// for clients patching a rendered document line by line.
+    ///
+    /// The lines are compared with those the previous call reported, which the client
+    /// shows, even when other changes came in between. They are those of the text before
+    /// the edit on the first call, or when the options differ from the previous call's.
+    /// Lines after the edited region that only moved are reported as renumbered, unless
+    /// [`RenderOptions::start_line`] writes the numbers into their HTML.
+    pub fn edit_and_diff(
+        &mut self,
+        edit: &InputEdit,
+        text: &[u8],
+        options: &RenderOptions,
+    ) -> Result<LineDiff, Error> {
+        let mut hasher = Fingerprint::new();
+        options.fingerprint(&mut hasher);
+        let fingerprint = hasher.finish();
+        let old = match self.lines.take() {
+            Some((reported, lines)) if reported == fingerprint => lines,
+            _ => self.lines(options)?,
+        };
+        self.edit(edit, text);
+        let new = self.lines(options)?;
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+        let mut diff = LineDiff::default();
+        for i in prefix..old_end.max(new_end) {
+            match (
+                old.get(i).filter(|_| i < old_end),
+                new.get(i).filter(|_| i < new_end),
+            ) {
+                (Some(a), Some(b)) if a != b => diff.changed.push((i, b.clone())),
+                (None, Some(b)) => diff.inserted.push((i, b.clone())),
+                (Some(_), None) => diff.removed.push(i),
+                _ => {}
+            }
+        }
+        if old_end != new_end {
+            diff.renumbered = (old_end..old.len()).zip(new_end..).collect();
+        }
+        self.lines = Some((fingerprint, new));
+        Ok(diff)
+    }
+
+    /// Renders the lines of the current text, see [`Languages::render_lines`].
+    fn lines(&mut self, options: &RenderOptions) -> Result<Vec<String>, Error> {
+        self.languages
+            .lines_with(&mut self.scratch, &self.lang, &self.text, options)
+    }
+
+    /// The current text.
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    /// The syntax tree of the current text, `None` when parsing failed.
+    pub fn tree(&self) -> Option<&Tree> {
+        self.tree.as_ref()
+    }
+
+    /// Renders the current text, see [`Languages::render_with`].
+    pub fn render(&mut self, options: &RenderOptions) -> Result<String, Error> {
+        let mut s = String::with_capacity(self.text.len() * 2 + self.lang.len() + 32);
+        self.languages.write_html_with(
+            &mut self.scratch,
+            &self.lang,
+            &self.text,
+            options,
+            None,
+            &mut s,
+        )?;
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter::Point;
+
+    use super::*;
+    use crate::tests::rust;
+
+    /// The point of a byte offset.
+    fn point(text: &[u8], offset: usize) -> Point {
+        let before = &text[..offset];
+        let row = before.iter().filter(|b| **b == b'\n').count();
+        let column = offset
+            - before
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map_or(0, |i| i + 1);
+        Point { row, column }
+    }
+
+    /// Replaces `old` with `new` in a session's text, like an editor would.
+    fn replace(text: &[u8], old: &str, new: &str) -> (InputEdit, Vec<u8>) {
+        let start = text
+            .windows(old.len())
+            .position(|w| w == old.as_bytes())
+            .unwrap();
+        let mut replaced = text[..start].to_vec();
+        replaced.extend_from_slice(new.as_bytes());
+        replaced.extend_from_slice(&text[start + old.len()..]);
+        let edit = InputEdit {
+            start_byte: start,
+            old_end_byte: start + old.len(),
+            new_end_byte: start + new.len(),
+            start_position: point(text, start),
+            old_end_position: point(text, start + old.len()),
+            new_end_position: point(&replaced, start + new.len()),
+        };
+        (edit, replaced)
+    }
+
+    #[test]
+    fn edit_and_diff() {
+        let languages = rust();
+        let options = RenderOptions::default();
+        let mut session = languages.session("rust").unwrap();
+        let text = (0..100)
+            .map(|i| format!("let v{i} = {i};\n"))
+            .collect::<String>();
+        session.set_text(text.as_bytes());
+
+        let (edit, text) = replace(session.text(), "v50 = 50", "v50 = \"s\"");
+        let diff = session.edit_and_diff(&edit, &text, &options).unwrap();
+        let lines = languages.render_lines("rust", &text, &options).unwrap();
+        assert_eq!(diff.changed, [(50, lines[50].clone())]);
+        assert!(diff.inserted.is_empty() && diff.removed.is_empty());
+        assert!(diff.renumbered.is_empty());
+
+        let (edit, text) = replace(&text, "let v10 = 10;\n", "let v10 = 10;\nlet x;\n");
+        let diff = session.edit_and_diff(&edit, &text, &options).unwrap();
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.inserted.len(), 1);
+        assert_eq!(diff.inserted[0].0, 11);
+        assert_eq!(diff.renumbered.len(), 89);
+        assert_eq!(diff.renumbered[0], (11, 12));
+
+        let (edit, text) = replace(&text, "let x;\nlet v11 = 11;\n", "");
+        let diff = session.edit_and_diff(&edit, &text, &options).unwrap();
+        assert!(diff.changed.is_empty() && diff.inserted.is_empty());
+        assert_eq!(diff.removed, [11, 12]);
+        assert_eq!(diff.renumbered[0], (13, 11));
+
+        // Edits in between are reported with the next diff.
+        let (edit, text) = replace(&text, "v20 = 20", "v20 = \"s\"");
+        session.edit(&edit, &text);
+        let (edit, text) = replace(&text, "v30 = 30", "v30 = \"s\"");
+        let diff = session.edit_and_diff(&edit, &text, &options).unwrap();
+        let lines = languages.render_lines("rust", &text, &options).unwrap();
+        assert_eq!(
+            diff.changed,
+            [(19, lines[19].clone()), (29, lines[29].clone())]
+        );
+
+        // Lines reported with other options are rendered again.
+        let options = RenderOptions::new().start_line(1);
+        let (edit, text) = replace(&text, "v40 = 40", "v40 = \"s\"");
+        let diff = session.edit_and_diff(&edit, &text, &options).unwrap();
+        let lines = languages.render_lines("rust", &text, &options).unwrap();
+        assert_eq!(diff.changed, [(39, lines[39].clone())]);
+    }
+
+    #[test]
+    fn edits() {
+        let languages = rust();
+        let options = RenderOptions::default();
+        let mut session = languages.session("rust").unwrap();
+        assert!(matches!(
+            languages.session("cobol"),
+            Err(Error::UnknownLanguage(_))
+        ));
+
+        session.set_text(include_bytes!("../fixtures/sample.rs"));
+        assert_eq!(
+            session.render(&options).unwrap(),
+            languages
+                .render_with("rust", session.text(), &options)
+                .unwrap()
+        );
+
+        // A fixed linear congruential generator keeps the edits reproducible.
+        let mut seed = 0x2545_f491_u64;
+        let mut next = |n: usize| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (seed >> 33) as usize % n.max(1)
+        };
+        let pieces: [&[u8]; 6] = [b"", b"x", b" {", b"}\n", b"\"s\"", b"fn f() {}\n"];
+        for _ in 0..200 {
+            let old = session.text().to_vec();
+            let start = next(old.len() + 1);
+            let old_end = start + next(old.len() - start + 1).min(8);
+            let piece = pieces[next(pieces.len())];
+            let mut text = old[..start].to_vec();
+            text.extend_from_slice(piece);
+            text.extend_from_slice(&old[old_end..]);
+            let new_end = start + piece.len();
+
+            session.edit(
+                &InputEdit {
+                    start_byte: start,
+                    old_end_byte: old_end,
+                    new_end_byte: new_end,
+                    start_position: point(&old, start),
+                    old_end_position: point(&old, old_end),
+                    new_end_position: point(&text, new_end),
+                },
+                &text,
+            );
+            assert_eq!(
+                session.tree().unwrap().root_node().to_sexp(),
+                languages
+                    .tree(languages.entry("rust").unwrap(), &text)
+                    .unwrap()
+                    .root_node()
+                    .to_sexp()
+            );
+            assert_eq!(
+                session.render(&options).unwrap(),
+                languages.render_with("rust", &text, &options).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{Error, FontStyle, Languages, Style, Theme};
+
+/// A token in the shape of Shiki's `ThemedToken`, for frontends consuming Shiki output.
+///
+/// With the `serde` feature it serializes as `{"content":"let","color":"#c678dd","fontStyle":2}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "camelCase"))]
+pub struct ShikiToken {
+    /// The token text, without line breaks.
+    pub content: String,
+    /// The resolved foreground color, falling back to the theme foreground.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub color: Option<String>,
+    /// Shiki's `FontStyle` flags: 1 italic, 2 bold, 4 underline, 8 strikethrough.
+    pub font_style: u8,
+}
+
+impl ShikiToken {
+    pub const ITALIC: u8 = 1;
+    pub const BOLD: u8 = 2;
+    pub const UNDERLINE: u8 = 4;
+    pub const STRIKETHROUGH: u8 = 8;
+}
+
+fn font_style(style: &Style) -> u8 {
+    style.font_styles().fold(0, |flags, font_style| {
+        flags
+            | match font_style {
+                FontStyle::Bold => ShikiToken::BOLD,
+                FontStyle::Italic => ShikiToken::ITALIC,
+                FontStyle::Underline => ShikiToken::UNDERLINE,
+                FontStyle::Strikethrough => ShikiToken::STRIKETHROUGH,
+            }
+    })
+}
+
+impl<'a> Languages<'a> {
+    /// Tokenizes into lines of [`ShikiToken`]s, like Shiki's `codeToTokensBase`.
+    ///
+    /// Adjacent tokens with the same style are merged, empty lines are empty `Vec`s and a
+    /// trailing line break does not start another line. Carriage returns are dropped.
+    pub fn shiki_tokens(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &Theme,
+    ) -> Result<Vec<Vec<ShikiToken>>, Error> {
+        let mut lines = vec![Vec::new()];
+        for (range, style) in self.styled_segments(lang, source, theme)? {
+            for (i, text) in String::from_utf8_lossy(&source[range])
+                .split('\n')
+                .enumerate()
+            {
+                if i > 0 {
+                    lines.push(Vec::new());
+                }
+                let content = text.replace('\r', "");
+                if content.is_empty() {
+                    continue;
+                }
+                lines.last_mut().unwrap().push(ShikiToken {
+                    content,
+                    color: style.fg.map(|fg| fg.to_string()),
+                    font_style: font_style(&style),
+                });
+            }
+        }
+        if lines.len() > 1 && lines.last().is_some_and(Vec::is_empty) {
+            lines.pop();
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, Color};
+
+    fn theme() -> Theme {
+        let mut theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            (
+                "string",
+                Style::new().fg(Color::rgb(152, 195, 121)).italic(),
+            ),
+        ]);
+        theme.foreground = Some(Color::rgb(171, 178, 191));
+        theme
+    }
+
+    fn token(content: &str, color: &str, font_style: u8) -> ShikiToken {
+        ShikiToken {
+            content: content.to_string(),
+            color: Some(color.to_string()),
+            font_style,
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let languages = rust();
+        let lines = languages
+            .shiki_tokens("rust", b"let s = \"a\";\r\n\nlet t;\n", &theme())
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                vec![
+                    token("let", "#c678dd", ShikiToken::BOLD),
+                    token(" s = ", "#abb2bf", 0),
+                    token("\"a\"", "#98c379", ShikiToken::ITALIC),
+                    token(";", "#abb2bf", 0),
+                ],
+                vec![],
+                vec![
+                    token("let", "#c678dd", ShikiToken::BOLD),
+                    token(" t;", "#abb2bf", 0),
+                ],
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn shape() {
+        let languages = rust();
+        let lines = languages
+            .shiki_tokens("rust", b"let a;\n\"b\"", &theme())
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&lines).unwrap(),
+            r##"[[{"content":"let","color":"#c678dd","fontStyle":2},{"content":" a;","color":"#abb2bf","fontStyle":0}],[{"content":"\"b\"","color":"#98c379","fontStyle":1}]]"##
+        );
+    }
+}
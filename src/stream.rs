@@ -0,0 +1,213 @@
+use std::{borrow::Cow, collections::VecDeque, vec};
+
+use tree_sitter_highlight::HighlightEvent;
+
+use crate::{
+    html::{embed, find_terms, normalize_source, Collapse, HtmlWriter, Line, Rows},
+    Error, Languages, RenderOptions,
+};
+
+/// The HTML of a render, a line at a time.
+struct Stream<'s> {
+    lang: &'s str,
+    source: Cow<'s, [u8]>,
+    events: vec::IntoIter<HighlightEvent>,
+    writer: HtmlWriter<'s>,
+    rows: Rows<'s>,
+    collapse: Collapse,
+    pending: VecDeque<String>,
+    started: bool,
+    finished: bool,
+    done: bool,
+}
+
+/// Queues a rendered line, after the collapse wrappers due before it.
+fn queue(rows: &Rows<'_>, collapse: &mut Collapse, pending: &mut VecDeque<String>, line: Line<'_>) {
+    let mut s = String::new();
+    if !line.continuation {
+        let _ = collapse.line(&mut s, line.number);
+    }
+    rows.push(&mut s, &line);
+    pending.push_back(s);
+}
+
+impl Iterator for Stream<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if !self.started {
+            self.started = true;
+            return Some(format!("<pre class=language-{}><code>", self.lang));
+        }
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                return Some(line);
+            }
+            if self.finished {
+                break;
+            }
+            let Self {
+                source,
+                events,
+                writer,
+                rows,
+                collapse,
+                pending,
+                ..
+            } = self;
+            match events.next() {
+                Some(event) => {
+                    // Unmapped indices were checked up front.
+                    let _ = writer.event(event, source);
+                    writer.drain_lines(|line| queue(rows, collapse, pending, line));
+                }
+                None => {
+                    writer.finish();
+                    writer
+                        .lines()
+                        .for_each(|line| queue(rows, collapse, pending, line));
+                    self.finished = true;
+                }
+            }
+        }
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        let mut s = String::new();
+        let _ = self.collapse.close(&mut s);
+        s.push_str("</code></pre>");
+        Some(s)
+    }
+}
+
+impl<'a> Languages<'a> {
+    /// Renders like [`Languages::render_with`], a line at a time.
+    ///
+    /// The iterator yields the `<pre><code>` prologue, then each line span with the
+    /// collapse wrappers around it, then the epilogue; concatenated, they equal the output
+    /// of `render_with`. Highlight events are collected up front, so errors are returned
+    /// here, but the HTML of a line is only built once it is reached.
+    pub fn render_iter<'s>(
+        &'s self,
+        lang: &'s str,
+        source: &'s [u8],
+        options: &'s RenderOptions,
+    ) -> Result<impl Iterator<Item = String> + 's, Error> {
+        let entry = self.entry(lang)?;
+        let source = normalize_source(source, options.zero_width);
+        let mut classes = self.injection_classes(Some(entry));
+
+        let mut highlighter = self.highlighter();
+        let mut events = self
+            .highlight(
+                &mut highlighter,
+                entry,
+                &source,
+                options.max_injection_depth,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        if options.rewrites_events() {
+            let rewritten = self.rewrite(entry, &source, events, options, classes.into_owned())?;
+            events = rewritten.0;
+            classes = rewritten.1.into();
+        }
+        if options.strict {
+            for event in &events {
+                if let HighlightEvent::HighlightStart(h) = event {
+                    if h.0 >= classes.len() {
+                        return Err(Error::UnmappedIndex(h.0));
+                    }
+                }
+            }
+        }
+
+        let count = source.iter().filter(|b| **b == b'\n').count()
+            + usize::from(source.last().is_none_or(|b| *b != b'\n'));
+        let writer = HtmlWriter::new(classes)
+            .marks(find_terms(&source, &options.search_terms))
+            .wrap(options.wrap_column)
+            .unknown_class(options.unknown_class.as_deref())
+            .token_element(options.token_element.as_deref())
+            .show_whitespace(options.show_whitespace)
+            .escape(options.escape)
+            .zero_width(options.zero_width)
+            .indent_guides(options.indent_guides);
+        let rows = Rows::new(&source, options);
+        Ok(Stream {
+            lang,
+            source,
+            events: events.into_iter(),
+            writer,
+            rows,
+            collapse: Collapse::new(options, count),
+            pending: VecDeque::new(),
+            started: false,
+            finished: false,
+            done: false,
+        }
+        .map(|s| embed(options.context, s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::rust, RenderOptions};
+
+    #[test]
+    fn render_iter() {
+        let languages = rust();
+        let source = include_bytes!("../fixtures/sample.rs");
+
+        let options = RenderOptions::new();
+        let lines = languages
+            .render_iter("rust", source, &options)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(lines[0], "<pre class=language-rust><code>");
+        assert_eq!(lines.last().unwrap(), "</code></pre>");
+        assert_eq!(lines.concat(), languages.render("rust", source).unwrap());
+
+        let options = RenderOptions::new()
+            .collapse(2..5)
+            .collapse(8..100)
+            .collapse_details(true)
+            .wrap_column(20)
+            .search_terms(&["Server"]);
+        let streamed = languages
+            .render_iter("rust", source, &options)
+            .unwrap()
+            .collect::<String>();
+        assert_eq!(
+            streamed,
+            languages.render_with("rust", source, &options).unwrap()
+        );
+
+        // Options rewriting the events apply too.
+        for options in [
+            RenderOptions::new().overrides(&[(0..3, "keyword"), (10..14, "string")]),
+            RenderOptions::new().link_locals(true),
+            RenderOptions::new().pair_brackets(true),
+            RenderOptions::new().mark_errors(true),
+        ] {
+            let streamed = languages
+                .render_iter("rust", source, &options)
+                .unwrap()
+                .collect::<String>();
+            assert_eq!(
+                streamed,
+                languages.render_with("rust", source, &options).unwrap()
+            );
+        }
+        let options = RenderOptions::new().mark_errors(true);
+        let source = b"let a = 1;\nlet b = $$$;\nfn f() { g(1 }\n";
+        let streamed = languages
+            .render_iter("rust", source, &options)
+            .unwrap()
+            .collect::<String>();
+        assert_eq!(
+            streamed,
+            languages.render_with("rust", source, &options).unwrap()
+        );
+    }
+}
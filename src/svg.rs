@@ -0,0 +1,330 @@
+use std::fmt::Write;
+
+use crate::{html::escape, width::char_width, Error, FontStyle, Languages, Theme};
+
+/// SVG options
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    /// The font family, referenced by name.
+    pub font_family: String,
+    pub font_size: f64,
+    /// The advance of one column of the monospace font.
+    pub char_width: f64,
+    pub line_height: f64,
+    pub padding: f64,
+    pub tab_width: usize,
+    pub line_numbers: bool,
+    /// The number of the first line in the gutter, for excerpts.
+    pub start_line: usize,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            font_family: "monospace".to_string(),
+            font_size: 14.0,
+            char_width: 8.4,
+            line_height: 21.0,
+            padding: 16.0,
+            tab_width: 4,
+            line_numbers: false,
+            start_line: 1,
+        }
+    }
+}
+
+impl SvgOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn font_family(mut self, family: &str) -> Self {
+        self.font_family = family.to_string();
+        self
+    }
+
+    pub fn font_size(mut self, size: f64) -> Self {
+        self.font_size = size;
+        self
+    }
+
+    pub fn char_width(mut self, width: f64) -> Self {
+        self.char_width = width;
+        self
+    }
+
+    pub fn line_height(mut self, height: f64) -> Self {
+        self.line_height = height;
+        self
+    }
+
+    pub fn padding(mut self, padding: f64) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width;
+        self
+    }
+
+    pub fn line_numbers(mut self, line_numbers: bool) -> Self {
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    pub fn start_line(mut self, line: usize) -> Self {
+        self.start_line = line;
+        self
+    }
+}
+
+/// A line of `<tspan>`s and its width in columns.
+#[derive(Default)]
+struct Line {
+    svg: String,
+    columns: usize,
+}
+
+impl<'a> Languages<'a> {
+    /// Renders to a self-contained SVG image with one `<text>` per line.
+    ///
+    /// The image size is computed from the longest line, after tab expansion, and the
+    /// line count.
+    pub fn render_svg(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &Theme,
+        options: &SvgOptions,
+    ) -> Result<String, Error> {
+        let mut lines = vec![Line::default()];
+        self.for_each_token(lang, source, |range, name| {
+            let style = name.map(|name| theme.resolve(name)).unwrap_or_default();
+            for (i, text) in String::from_utf8_lossy(&source[range])
+                .split('\n')
+                .enumerate()
+            {
+                if i > 0 {
+                    lines.push(Line::default());
+                }
+                let line = lines.last_mut().unwrap();
+                let mut span = String::new();
+                for c in text.chars() {
+                    match c {
+                        '\t' => {
+                            let tab = options.tab_width.max(1);
+                            let n = tab - line.columns % tab;
+                            span.extend(std::iter::repeat_n(' ', n));
+                            line.columns += n;
+                        }
+                        c if c.is_control() => {}
+                        c => {
+                            escape(&mut span, c);
+                            line.columns += char_width(c);
+                        }
+                    }
+                }
+                if span.is_empty() {
+                    continue;
+                }
+                if style == Default::default() {
+                    line.svg.push_str(&span);
+                    continue;
+                }
+                line.svg.push_str("<tspan");
+                if let Some(fg) = style.fg {
+                    let _ = write!(line.svg, " fill=\"{fg}\"");
+                }
+                let mut decorations = Vec::new();
+                for font_style in style.font_styles() {
+                    match font_style {
+                        FontStyle::Bold => line.svg.push_str(" font-weight=\"bold\""),
+                        FontStyle::Italic => line.svg.push_str(" font-style=\"italic\""),
+                        FontStyle::Underline => decorations.push("underline"),
+                        FontStyle::Strikethrough => decorations.push("line-through"),
+                    }
+                }
+                if !decorations.is_empty() {
+                    let _ = write!(line.svg, " text-decoration=\"{}\"", decorations.join(" "));
+                }
+                line.svg.push('>');
+                line.svg.push_str(&span);
+                line.svg.push_str("</tspan>");
+            }
+        })?;
+        if lines.len() > 1 && lines.last().is_some_and(|l| l.svg.is_empty()) {
+            lines.pop();
+        }
+
+        let gutter = if options.line_numbers {
+            (options.start_line + lines.len())
+                .saturating_sub(1)
+                .to_string()
+                .len()
+                + 2
+        } else {
+            0
+        };
+        let columns = lines.iter().map(|l| l.columns).max().unwrap_or(0);
+        let width = 2.0 * options.padding + (gutter + columns) as f64 * options.char_width;
+        let height = 2.0 * options.padding + lines.len() as f64 * options.line_height;
+        let (width, height) = (num(width), num(height));
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" xml:space=\"preserve\" font-family=\""
+        );
+        options
+            .font_family
+            .chars()
+            .for_each(|c| escape(&mut svg, c));
+        let _ = write!(svg, "\" font-size=\"{}\"", num(options.font_size));
+        if let Some(fg) = theme.foreground {
+            let _ = write!(svg, " fill=\"{fg}\"");
+        }
+        svg.push('>');
+        if let Some(bg) = theme.background {
+            let _ = write!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"{bg}\"/>");
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let y = num(options.padding + (i as f64 + 0.5) * options.line_height);
+            if options.line_numbers {
+                let x = num(options.padding + (gutter - 1) as f64 * options.char_width);
+                // Without a gutter color, the numbers are the foreground at half opacity.
+                let color = match theme.gutter_fg {
+                    Some(fg) => format!("fill=\"{fg}\""),
+                    None => "opacity=\"0.5\"".to_string(),
+                };
+                let _ = write!(
+                    svg,
+                    "<text class=\"line-number\" x=\"{x}\" y=\"{y}\" dominant-baseline=\"central\" text-anchor=\"end\" {color}>{}</text>",
+                    options.start_line + i
+                );
+            }
+            let x = num(options.padding + gutter as f64 * options.char_width);
+            let _ = write!(
+                svg,
+                "<text x=\"{x}\" y=\"{y}\" dominant-baseline=\"central\">{}</text>",
+                line.svg
+            );
+        }
+        svg.push_str("</svg>");
+        Ok(svg)
+    }
+}
+
+/// Formats a coordinate with at most two decimals.
+fn num(n: f64) -> String {
+    let s = format!("{n:.2}");
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::rust, Color, Style};
+
+    #[test]
+    fn nums() {
+        assert_eq!(num(95.0), "95");
+        assert_eq!(num(149.6), "149.6");
+        assert_eq!(num(0.1 + 0.2), "0.3");
+    }
+
+    #[test]
+    fn render() {
+        let languages = rust();
+        let mut theme = Theme::new(&[("keyword", Style::new().fg(Color::rgb(198, 120, 221)))]);
+        theme.background = Some(Color::rgb(40, 44, 52));
+        let source = b"fn main() {\n\tlet x = \"<&>\";\n}\n";
+
+        let svg = languages
+            .render_svg("rust", source, &theme, &SvgOptions::new())
+            .unwrap();
+        let doc = roxmltree::Document::parse(&svg).unwrap();
+        let root = doc.root_element();
+        // 2 * 16 padding + 18 columns * 8.4, 2 * 16 padding + 3 lines * 21
+        assert_eq!(root.attribute("viewBox"), Some("0 0 183.2 95"));
+        assert_eq!(root.attribute("width"), Some("183.2"));
+        assert_eq!(root.attribute("height"), Some("95"));
+        assert_eq!(
+            root.descendants()
+                .find(|n| n.has_tag_name("rect"))
+                .and_then(|n| n.attribute("fill")),
+            Some("#282c34")
+        );
+
+        let lines = root
+            .children()
+            .filter(|n| n.has_tag_name("text"))
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].attribute("y"), Some("47.5"));
+        assert_eq!(
+            lines[1]
+                .descendants()
+                .filter(|n| n.is_text())
+                .filter_map(|n| n.text())
+                .collect::<String>(),
+            "    let x = \"<&>\";"
+        );
+        assert!(lines[1]
+            .descendants()
+            .any(|n| n.has_tag_name("tspan") && n.attribute("fill") == Some("#c678dd")));
+
+        let svg = languages
+            .render_svg(
+                "rust",
+                source,
+                &theme,
+                &SvgOptions::new().line_numbers(true),
+            )
+            .unwrap();
+        let doc = roxmltree::Document::parse(&svg).unwrap();
+        // 3 gutter columns
+        assert_eq!(
+            doc.root_element().attribute("viewBox"),
+            Some("0 0 208.4 95")
+        );
+        let number = |doc: &roxmltree::Document| {
+            let number = doc
+                .descendants()
+                .find(|n| n.attribute("class") == Some("line-number"))
+                .unwrap();
+            (
+                number.attribute("fill").map(str::to_string),
+                number.attribute("opacity").map(str::to_string),
+            )
+        };
+        assert_eq!(number(&doc), (None, Some("0.5".to_string())));
+
+        theme.gutter_fg = Some(Color::rgb(99, 109, 131));
+        let svg = languages
+            .render_svg(
+                "rust",
+                source,
+                &theme,
+                &SvgOptions::new().line_numbers(true),
+            )
+            .unwrap();
+        let doc = roxmltree::Document::parse(&svg).unwrap();
+        assert_eq!(number(&doc), (Some("#636d83".to_string()), None));
+
+        let svg = languages
+            .render_svg(
+                "rust",
+                source,
+                &theme,
+                &SvgOptions::new().line_numbers(true).start_line(100),
+            )
+            .unwrap();
+        let doc = roxmltree::Document::parse(&svg).unwrap();
+        let first = doc
+            .descendants()
+            .find(|n| n.attribute("class") == Some("line-number"))
+            .unwrap();
+        assert_eq!(first.text(), Some("100"));
+    }
+}
@@ -0,0 +1,558 @@
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::OnceLock};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::ansi::Palette;
+
+/// An RGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// An invalid hex color.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseColorError(String);
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hex color `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+/// An invalid theme file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThemeError {
+    /// The file does not parse or does not have the expected shape.
+    Parse(String),
+    /// A color is not a hex color.
+    Color(ParseColorError),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "invalid theme: {e}"),
+            Self::Color(e) => write!(f, "invalid theme: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+impl From<ParseColorError> for ThemeError {
+    fn from(e: ParseColorError) -> Self {
+        Self::Color(e)
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    /// Parses `#rrggbb` or `#rgb`, with or without the `#`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseColorError(s.to_string());
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).map_err(|_| error());
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| error());
+        match hex.len() {
+            3 => Ok(Self::rgb(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+            6 => Ok(Self::rgb(byte(0)?, byte(2)?, byte(4)?)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    /// Formats as `#rrggbb`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// Serializes as a `#rrggbb` string.
+#[cfg(feature = "serde")]
+impl Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserializes from a hex string, see [`Color::from_str`].
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The style of a capture.
+///
+/// With the `serde` feature, unset colors and flags are left out when serializing and
+/// default when deserializing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct Style {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub fg: Option<Color>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub bg: Option<Color>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub bold: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub italic: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub underline: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub strikethrough: bool,
+}
+
+impl Style {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fg(mut self, color: Color) -> Self {
+        self.fg = Some(color);
+        self
+    }
+
+    pub fn bg(mut self, color: Color) -> Self {
+        self.bg = Some(color);
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    pub fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
+    pub fn strikethrough(mut self) -> Self {
+        self.strikethrough = true;
+        self
+    }
+
+    /// The font attributes of the style, in the order of [`FontStyle`].
+    ///
+    /// Renderers map each one with an exhaustive `match`, so an attribute cannot be
+    /// honored by some renderers and silently dropped by others.
+    pub fn font_styles(&self) -> impl Iterator<Item = FontStyle> {
+        [
+            (self.bold, FontStyle::Bold),
+            (self.italic, FontStyle::Italic),
+            (self.underline, FontStyle::Underline),
+            (self.strikethrough, FontStyle::Strikethrough),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, font_style)| enabled.then_some(font_style))
+    }
+}
+
+/// A font attribute of a [`Style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+}
+
+/// Theme
+///
+/// Maps capture names to styles. Dotted names fall back to their prefixes, so
+/// `keyword.function` uses the style of `keyword` unless it has its own.
+///
+/// With the `serde` feature, a theme serializes to the shape read by
+/// [`Theme::from_toml`], with the styles under `captures`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct Theme {
+    /// The default background.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub background: Option<Color>,
+    /// The default foreground.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub foreground: Option<Color>,
+    /// The background of highlighted lines.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub line_highlight: Option<Color>,
+    /// The color of line numbers.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub gutter_fg: Option<Color>,
+    /// The background of selected text.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub selection_bg: Option<Color>,
+    #[cfg_attr(feature = "serde", serde(rename = "captures"))]
+    styles: BTreeMap<String, Style>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    palette: OnceLock<Palette>,
+}
+
+impl Theme {
+    pub fn new(styles: &[(&str, Style)]) -> Self {
+        Self {
+            background: None,
+            foreground: None,
+            line_highlight: None,
+            gutter_fg: None,
+            selection_bg: None,
+            styles: styles
+                .iter()
+                .map(|(name, style)| (name.to_string(), *style))
+                .collect(),
+            palette: OnceLock::new(),
+        }
+    }
+
+    /// The distinct colors used by the theme, sorted.
+    pub(crate) fn colors(&self) -> Vec<Color> {
+        let mut colors = self
+            .styles
+            .values()
+            .filter_map(|s| s.fg)
+            .chain(self.foreground)
+            .chain(self.background)
+            .collect::<Vec<_>>();
+        colors.sort_by_key(|c| (c.r, c.g, c.b));
+        colors.dedup();
+        colors
+    }
+
+    /// The capture names with a style of their own.
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.styles.keys().map(String::as_str)
+    }
+
+    /// The downsampled ANSI colors, computed on first use.
+    pub(crate) fn palette(&self) -> &Palette {
+        self.palette.get_or_init(|| {
+            Palette::new(
+                self.styles
+                    .values()
+                    .flat_map(|s| s.fg.into_iter().chain(s.bg)),
+            )
+        })
+    }
+
+    /// The style of a capture name or of its longest dotted prefix with one, if any.
+    pub fn get(&self, name: &str) -> Option<Style> {
+        let mut name = name;
+        loop {
+            if let Some(style) = self.styles.get(name) {
+                return Some(*style);
+            }
+            name = &name[..name.rfind('.')?];
+        }
+    }
+
+    /// Reports which of the capture names have a style of their own, fall back to the
+    /// style of a dotted prefix, or have no style at all.
+    pub fn coverage(&self, names: &[&str]) -> Coverage {
+        let mut coverage = Coverage::default();
+        for name in names {
+            let list = if self.styles.contains_key(*name) {
+                &mut coverage.exact
+            } else if self.get(name).is_some() {
+                &mut coverage.fallback
+            } else {
+                &mut coverage.unmatched
+            };
+            list.push(name.to_string());
+        }
+        coverage
+    }
+
+    /// Loads a theme in the native TOML shape, with the default colors at the top and a
+    /// style per capture name under `[captures]`:
+    ///
+    /// ```toml
+    /// background = "#282c34"
+    /// foreground = "#abb2bf"
+    ///
+    /// [captures]
+    /// keyword = { fg = "#c678dd", bold = true }
+    /// comment = { fg = "#5c6370", italic = true }
+    /// ```
+    ///
+    /// Every key is optional; the others are `line_highlight`, `gutter_fg` and
+    /// `selection_bg`, and `bg`, `underline` and `strikethrough` in styles.
+    #[cfg(feature = "serde")]
+    pub fn from_toml(toml: &str) -> Result<Theme, ThemeError> {
+        toml::from_str(toml).map_err(|e| ThemeError::Parse(e.to_string()))
+    }
+
+    /// Serializes the theme to the TOML shape of [`Theme::from_toml`], leaving out unset
+    /// colors and flags.
+    #[cfg(feature = "serde")]
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("themes serialize")
+    }
+
+    /// Resolves the style of a capture name, falling back to its dotted prefixes and
+    /// then to the default style.
+    pub fn resolve(&self, name: &str) -> Style {
+        self.get(name).unwrap_or_default()
+    }
+
+    /// Starts a theme from a preset, such as one of [`themes`](crate::themes), to change a
+    /// few styles of:
+    ///
+    /// ```ignore
+    /// let theme = Theme::base(themes::one_dark()).color("keyword", "#ff0000").build()?;
+    /// ```
+    pub fn base(preset: Theme) -> ThemeBuilder {
+        ThemeBuilder {
+            theme: preset,
+            error: None,
+        }
+    }
+}
+
+/// Builds a theme from a preset, see [`Theme::base`].
+#[derive(Debug, Clone)]
+pub struct ThemeBuilder {
+    theme: Theme,
+    /// The first invalid color.
+    error: Option<ParseColorError>,
+}
+
+impl ThemeBuilder {
+    /// Sets the foreground of a capture name to a hex color, see [`Color::from_str`],
+    /// keeping the rest of the style it had, its own or a dotted prefix's.
+    pub fn color(mut self, name: &str, color: &str) -> Self {
+        match color.parse() {
+            Ok(color) => {
+                let style = self.theme.resolve(name).fg(color);
+                self.theme.styles.insert(name.to_string(), style);
+            }
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Replaces the style of a capture name.
+    pub fn style(mut self, name: &str, style: Style) -> Self {
+        self.theme.styles.insert(name.to_string(), style);
+        self
+    }
+
+    /// The theme, or the first invalid color given.
+    pub fn build(self) -> Result<Theme, ThemeError> {
+        if let Some(e) = self.error {
+            return Err(e.into());
+        }
+        // The palette of the preset is stale.
+        Ok(Theme {
+            palette: OnceLock::new(),
+            ..self.theme
+        })
+    }
+}
+
+/// How a theme covers a list of capture names, see [`Theme::coverage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// The names with a style of their own.
+    pub exact: Vec<String>,
+    /// The names styled by a dotted prefix.
+    pub fallback: Vec<String>,
+    /// The names without a style.
+    pub unmatched: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let keyword = Style::new().fg(Color::rgb(198, 120, 221)).bold();
+        let theme = Theme::new(&[("keyword", keyword)]);
+        assert_eq!(theme.resolve("keyword"), keyword);
+        assert_eq!(theme.resolve("keyword.function"), keyword);
+        assert_eq!(theme.resolve("string"), Style::default());
+    }
+
+    #[test]
+    fn fallback_chain() {
+        let keyword = Style::new().fg(Color::rgb(198, 120, 221));
+        let operator = Style::new().fg(Color::rgb(86, 182, 194)).bold();
+        let theme = Theme::new(&[("keyword", keyword), ("keyword.operator.logical", operator)]);
+
+        assert_eq!(theme.get("keyword.operator.logical"), Some(operator));
+        assert_eq!(theme.get("keyword.operator.logical.and"), Some(operator));
+        assert_eq!(theme.get("keyword.operator"), Some(keyword));
+        assert_eq!(theme.get("keyword"), Some(keyword));
+        assert_eq!(theme.get("keywords"), None);
+        assert_eq!(theme.get("string.keyword"), None);
+        assert_eq!(theme.get(""), None);
+
+        assert_eq!(theme.resolve("keyword.operator"), keyword);
+        assert_eq!(theme.resolve("operator"), Style::default());
+    }
+
+    #[test]
+    fn base() {
+        let keyword = Style::new().fg(Color::rgb(198, 120, 221)).bold();
+        let comment = Style::new().fg(Color::rgb(92, 99, 112)).italic();
+        let mut preset = Theme::new(&[("keyword", keyword), ("comment", comment)]);
+        preset.background = Some(Color::rgb(40, 44, 52));
+        let theme = Theme::base(preset.clone())
+            .color("keyword", "#ff0000")
+            .color("keyword.function", "#00f")
+            .build()
+            .unwrap();
+        assert_eq!(
+            theme.get("keyword"),
+            Some(keyword.fg(Color::rgb(255, 0, 0)))
+        );
+        assert_eq!(
+            theme.get("keyword.function"),
+            Some(keyword.fg(Color::rgb(0, 0, 255)))
+        );
+        assert_eq!(theme.get("comment"), Some(comment));
+        assert_eq!(theme.background, preset.background);
+
+        // Only the overridden rule and the rules falling back to it change.
+        let theme = Theme::base(preset.clone())
+            .color("keyword", "#ff0000")
+            .build()
+            .unwrap();
+        let css = crate::theme_css(&theme);
+        let before = crate::theme_css(&preset);
+        assert_eq!(css.lines().count(), before.lines().count());
+        let changed = css
+            .lines()
+            .zip(before.lines())
+            .filter(|(a, b)| a != b)
+            .map(|(a, _)| a)
+            .collect::<Vec<_>>();
+        assert!(changed.contains(&"[class~=\"keyword\"] { color: #ff0000; font-weight: bold; }"));
+        assert!(
+            changed
+                .iter()
+                .all(|rule| rule.starts_with("[class~=\"keyword")),
+            "{css}"
+        );
+
+        assert_eq!(
+            Theme::base(preset).color("keyword", "red").build().err(),
+            Some(ThemeError::Color(ParseColorError("red".to_string())))
+        );
+    }
+
+    #[test]
+    fn font_styles() {
+        let style = Style::new().strikethrough().italic();
+        assert_eq!(
+            style.font_styles().collect::<Vec<_>>(),
+            [FontStyle::Italic, FontStyle::Strikethrough]
+        );
+        assert_eq!(
+            Style::new().fg(Color::rgb(0, 0, 0)).font_styles().count(),
+            0
+        );
+    }
+
+    #[test]
+    fn coverage() {
+        let theme = Theme::new(&[
+            ("keyword", Style::new().bold()),
+            ("keyword.return", Style::new().italic()),
+        ]);
+        let coverage = theme.coverage(&["keyword", "keyword.return", "keyword.function", "string"]);
+        assert_eq!(coverage.exact, ["keyword", "keyword.return"]);
+        assert_eq!(coverage.fallback, ["keyword.function"]);
+        assert_eq!(coverage.unmatched, ["string"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn toml() {
+        let mut theme = Theme::new(&[
+            (
+                "keyword",
+                Style::new()
+                    .fg(Color::rgb(198, 120, 221))
+                    .bg(Color::rgb(0, 10, 255))
+                    .bold()
+                    .italic()
+                    .underline()
+                    .strikethrough(),
+            ),
+            ("comment", Style::new().italic()),
+        ]);
+        theme.background = Some(Color::rgb(40, 44, 52));
+        theme.foreground = Some(Color::rgb(171, 178, 191));
+        theme.line_highlight = Some(Color::rgb(44, 49, 58));
+        theme.gutter_fg = Some(Color::rgb(99, 109, 131));
+        theme.selection_bg = Some(Color::rgb(62, 68, 81));
+
+        let toml = theme.to_toml();
+        assert!(toml.contains("background = \"#282c34\""));
+        assert!(toml.contains("fg = \"#c678dd\""));
+        let parsed = Theme::from_toml(&toml).unwrap();
+        assert_eq!(parsed.to_toml(), toml);
+        assert_eq!(parsed.background, theme.background);
+        assert_eq!(parsed.selection_bg, theme.selection_bg);
+        assert_eq!(parsed.get("keyword"), theme.get("keyword"));
+        assert_eq!(parsed.get("comment"), Some(Style::new().italic()));
+
+        let theme = Theme::from_toml(
+            "foreground = \"#abb2bf\"\n\n[captures]\nkeyword = { fg = \"#c678dd\", bold = true }\n",
+        )
+        .unwrap();
+        assert_eq!(theme.background, None);
+        assert_eq!(
+            theme.resolve("keyword.function"),
+            Style::new().fg(Color::rgb(198, 120, 221)).bold()
+        );
+        let toml = theme.to_toml();
+        assert!(!toml.contains("background"));
+        assert!(!toml.contains("italic"));
+        assert_eq!(Theme::from_toml(&toml).unwrap().to_toml(), toml);
+
+        assert!(Theme::from_toml("[captures]\nkeyword = { fg = \"purple\" }").is_err());
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(Color::rgb(198, 120, 221).to_string(), "#c678dd");
+        assert_eq!(Color::rgb(0, 10, 255).to_string(), "#000aff");
+
+        assert_eq!("#c678dd".parse(), Ok(Color::rgb(198, 120, 221)));
+        assert_eq!("C678DD".parse(), Ok(Color::rgb(198, 120, 221)));
+        assert_eq!("#0af".parse(), Ok(Color::rgb(0, 170, 255)));
+        for invalid in ["", "#", "#c678d", "#c678ddff", "#g678dd", "#+1+2+3", "#é12"] {
+            assert!(invalid.parse::<Color>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Built-in themes, styling every capture in [`NAMES`](crate::NAMES).
+
+use crate::{Color, Style, Theme};
+
+/// The colors of a built-in theme, by role.
+struct Colors {
+    background: Color,
+    foreground: Color,
+    line_highlight: Color,
+    comment: Color,
+    keyword: Color,
+    string: Color,
+    escape: Color,
+    number: Color,
+    function: Color,
+    ty: Color,
+    property: Color,
+    tag: Color,
+    operator: Color,
+    punctuation: Color,
+    error: Color,
+    warning: Color,
+    added: Color,
+    removed: Color,
+    /// The tints of added and removed lines.
+    added_bg: Color,
+    removed_bg: Color,
+    selection: Color,
+    changed: Color,
+    link: Color,
+}
+
+const fn hex(rgb: u32) -> Color {
+    Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
+
+/// Builds a theme from its colors; `bold` makes keywords bold.
+fn theme(c: &Colors, bold: bool) -> Theme {
+    let fg = |color| Style::new().fg(color);
+    let keyword = if bold {
+        fg(c.keyword).bold()
+    } else {
+        fg(c.keyword)
+    };
+    let mut theme = Theme::new(&[
+        ("annotation", fg(c.function)),
+        ("attribute", fg(c.property)),
+        ("boolean", fg(c.number)),
+        ("character", fg(c.string)),
+        ("character.special", fg(c.escape)),
+        ("comment", fg(c.comment).italic()),
+        ("conditional", keyword),
+        ("constant", fg(c.number)),
+        ("constructor", fg(c.ty)),
+        ("debug", fg(c.warning)),
+        ("define", keyword),
+        ("diff.delta", fg(c.changed)),
+        ("diff.minus", fg(c.removed).bg(c.removed_bg)),
+        ("diff.plus", fg(c.added).bg(c.added_bg)),
+        ("error", fg(c.error)),
+        ("exception", keyword),
+        ("field", fg(c.property)),
+        ("float", fg(c.number)),
+        ("function", fg(c.function)),
+        ("include", keyword),
+        ("keyword", keyword),
+        ("keyword.operator", fg(c.operator)),
+        ("label", fg(c.tag)),
+        ("method", fg(c.function)),
+        ("namespace", fg(c.ty)),
+        ("none", fg(c.foreground)),
+        ("number", fg(c.number)),
+        ("operator", fg(c.operator)),
+        ("parameter", fg(c.foreground)),
+        ("preproc", keyword),
+        ("property", fg(c.property)),
+        ("punctuation", fg(c.punctuation)),
+        ("punctuation.special", fg(c.operator)),
+        ("repeat", keyword),
+        ("storageclass", keyword),
+        ("string", fg(c.string)),
+        ("string.escape", fg(c.escape)),
+        ("string.regex", fg(c.escape)),
+        ("string.special", fg(c.escape)),
+        ("symbol", fg(c.number)),
+        ("tag", fg(c.tag)),
+        ("tag.attribute", fg(c.property)),
+        ("tag.delimiter", fg(c.punctuation)),
+        ("text", fg(c.foreground)),
+        ("text.danger", fg(c.error).bold()),
+        ("text.diff.add", fg(c.added).bg(c.added_bg)),
+        ("text.diff.delete", fg(c.removed).bg(c.removed_bg)),
+        ("text.emphasis", fg(c.foreground).italic()),
+        ("text.environment", fg(c.keyword)),
+        ("text.literal", fg(c.string)),
+        ("text.math", fg(c.number)),
+        ("text.note", fg(c.function).bold()),
+        ("text.reference", fg(c.link)),
+        ("text.strike", fg(c.foreground).strikethrough()),
+        ("text.strong", fg(c.foreground).bold()),
+        ("text.title", fg(c.keyword).bold()),
+        ("text.underline", fg(c.foreground).underline()),
+        ("text.uri", fg(c.link).underline()),
+        ("text.warning", fg(c.warning).bold()),
+        ("todo", fg(c.warning).bold()),
+        ("type", fg(c.ty)),
+        ("type.qualifier", keyword),
+        ("variable", fg(c.foreground)),
+        ("variable.builtin", fg(c.number)),
+    ]);
+    theme.background = Some(c.background);
+    theme.foreground = Some(c.foreground);
+    theme.line_highlight = Some(c.line_highlight);
+    theme.gutter_fg = Some(c.comment);
+    theme.selection_bg = Some(c.selection);
+    theme
+}
+
+/// A light theme after GitHub's.
+pub fn github_light() -> Theme {
+    theme(
+        &Colors {
+            background: hex(0xffffff),
+            foreground: hex(0x24292f),
+            line_highlight: hex(0xfff8c5),
+            comment: hex(0x6e7781),
+            keyword: hex(0xcf222e),
+            string: hex(0x0a3069),
+            escape: hex(0x116329),
+            number: hex(0x0550ae),
+            function: hex(0x8250df),
+            ty: hex(0x953800),
+            property: hex(0x0550ae),
+            tag: hex(0x116329),
+            operator: hex(0xcf222e),
+            punctuation: hex(0x24292f),
+            error: hex(0x82071e),
+            warning: hex(0x9a6700),
+            added: hex(0x116329),
+            removed: hex(0x82071e),
+            changed: hex(0x953800),
+            link: hex(0x0a3069),
+            added_bg: hex(0xdafbe1),
+            removed_bg: hex(0xffebe9),
+            selection: hex(0xb6e3ff),
+        },
+        false,
+    )
+}
+
+/// A dark theme after GitHub's.
+pub fn github_dark() -> Theme {
+    theme(
+        &Colors {
+            background: hex(0x0d1117),
+            foreground: hex(0xc9d1d9),
+            line_highlight: hex(0x1f2428),
+            comment: hex(0x8b949e),
+            keyword: hex(0xff7b72),
+            string: hex(0xa5d6ff),
+            escape: hex(0x7ee787),
+            number: hex(0x79c0ff),
+            function: hex(0xd2a8ff),
+            ty: hex(0xffa657),
+            property: hex(0x79c0ff),
+            tag: hex(0x7ee787),
+            operator: hex(0xff7b72),
+            punctuation: hex(0xc9d1d9),
+            error: hex(0xffa198),
+            warning: hex(0xd29922),
+            added: hex(0x7ee787),
+            removed: hex(0xffa198),
+            changed: hex(0xffa657),
+            link: hex(0xa5d6ff),
+            added_bg: hex(0x12261e),
+            removed_bg: hex(0x25171c),
+            selection: hex(0x1f3e63),
+        },
+        false,
+    )
+}
+
+/// A variant of Atom's One Dark.
+pub fn one_dark() -> Theme {
+    theme(
+        &Colors {
+            background: hex(0x282c34),
+            foreground: hex(0xabb2bf),
+            line_highlight: hex(0x2c313a),
+            comment: hex(0x5c6370),
+            keyword: hex(0xc678dd),
+            string: hex(0x98c379),
+            escape: hex(0x56b6c2),
+            number: hex(0xd19a66),
+            function: hex(0x61afef),
+            ty: hex(0xe5c07b),
+            property: hex(0xe06c75),
+            tag: hex(0xe06c75),
+            operator: hex(0x56b6c2),
+            punctuation: hex(0xabb2bf),
+            error: hex(0xe06c75),
+            warning: hex(0xe5c07b),
+            added: hex(0x98c379),
+            removed: hex(0xe06c75),
+            changed: hex(0xe5c07b),
+            link: hex(0x61afef),
+            added_bg: hex(0x2e3b2f),
+            removed_bg: hex(0x3f2c30),
+            selection: hex(0x3e4451),
+        },
+        false,
+    )
+}
+
+/// Bright colors on black with bold keywords, for low vision and projectors.
+pub fn high_contrast() -> Theme {
+    theme(
+        &Colors {
+            background: hex(0x000000),
+            foreground: hex(0xffffff),
+            line_highlight: hex(0x303030),
+            comment: hex(0xc0c0c0),
+            keyword: hex(0xffd75f),
+            string: hex(0x87ff87),
+            escape: hex(0xff87ff),
+            number: hex(0x5fd7ff),
+            function: hex(0x87afff),
+            ty: hex(0xff875f),
+            property: hex(0x5fd7ff),
+            tag: hex(0xffd75f),
+            operator: hex(0xffffff),
+            punctuation: hex(0xffffff),
+            error: hex(0xff5f5f),
+            warning: hex(0xffd75f),
+            added: hex(0x87ff87),
+            removed: hex(0xff5f5f),
+            changed: hex(0xffaf5f),
+            link: hex(0x87d7ff),
+            added_bg: hex(0x003300),
+            removed_bg: hex(0x330000),
+            selection: hex(0x005f87),
+        },
+        true,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NAMES;
+
+    #[test]
+    fn coverage() {
+        for theme in [github_light(), github_dark(), one_dark(), high_contrast()] {
+            for name in ["keyword", "string", "comment", "function", "type", "number"] {
+                assert!(theme.resolve(name).fg.is_some(), "{name}");
+            }
+            assert_eq!(theme.coverage(NAMES).unmatched, Vec::<String>::new());
+            assert_ne!(theme.resolve("keyword"), theme.resolve("string"));
+            assert_ne!(theme.foreground, theme.background);
+            for name in [
+                "diff.plus",
+                "diff.minus",
+                "text.diff.add",
+                "text.diff.delete",
+            ] {
+                assert!(theme.resolve(name).bg.is_some(), "{name}");
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::ops::Range;
+
+use crate::{Error, Languages, Style, Theme};
+
+/// A classified range of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    /// The byte range in the source.
+    pub range: Range<usize>,
+    /// The innermost `Highlight` index, `None` for unhighlighted text.
+    pub capture: Option<usize>,
+    /// The capture name of `capture`, borrowed from the registered names.
+    pub name: Option<&'a str>,
+}
+
+/// The unit of token columns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColumnUnit {
+    /// UTF-16 code units, as in the Language Server Protocol.
+    Utf16,
+    /// Unicode scalar values, as in Rust `char`s.
+    #[default]
+    Char,
+}
+
+impl ColumnUnit {
+    fn width(self, c: char) -> usize {
+        match self {
+            Self::Utf16 => c.len_utf16(),
+            Self::Char => 1,
+        }
+    }
+}
+
+/// A token with 0-based `(line, column)` coordinates, the end being exclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionedToken<'a> {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub name: Option<&'a str>,
+}
+
+impl<'a> Languages<'a> {
+    /// Classifies the whole source into tokens.
+    ///
+    /// - Tokens are in source order, non-empty and contiguous: the first starts at `0`,
+    ///   each starts where the previous ends and the last ends at `source.len()`.
+    /// - Nested captures are flattened to the innermost one, so a string inside an
+    ///   attribute is a `string` token between the attribute's tokens.
+    /// - Text outside any capture is a token with `capture: None`.
+    /// - Adjacent ranges with the same capture are merged.
+    pub fn tokens<'s>(&'s self, lang: &str, source: &[u8]) -> Result<Vec<Token<'s>>, Error> {
+        let mut tokens: Vec<Token<'s>> = Vec::new();
+        self.for_each_capture(lang, source, |range, capture| {
+            if range.is_empty() {
+                return;
+            }
+            match tokens.last_mut() {
+                Some(last) if last.capture == capture && last.range.end == range.start => {
+                    last.range.end = range.end;
+                }
+                _ => tokens.push(Token {
+                    range,
+                    capture,
+                    name: capture.and_then(|i| self.names.get(i)).map(String::as_str),
+                }),
+            }
+        })?;
+        Ok(tokens)
+    }
+
+    /// Resolves the tokens through a theme into styled runs for GUI toolkits.
+    ///
+    /// Like the tokens, the runs tile the whole source. Colors a style leaves unset, and
+    /// the style of unhighlighted text, come from the theme's foreground and background.
+    /// Adjacent runs with the same style are merged.
+    pub fn styled_segments(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &Theme,
+    ) -> Result<Vec<(Range<usize>, Style)>, Error> {
+        let mut segments: Vec<(Range<usize>, Style)> = Vec::new();
+        for token in self.tokens(lang, source)? {
+            let mut style = token.name.map(|n| theme.resolve(n)).unwrap_or_default();
+            style.fg = style.fg.or(theme.foreground);
+            style.bg = style.bg.or(theme.background);
+            match segments.last_mut() {
+                Some((range, last)) if *last == style => range.end = token.range.end,
+                _ => segments.push((token.range, style)),
+            }
+        }
+        Ok(segments)
+    }
+
+    /// Classifies the whole source into tokens with line and column coordinates, see
+    /// [`Languages::tokens`].
+    ///
+    /// With `split_lines`, tokens spanning several lines are split into one token per
+    /// line, and line breaks belong to no token. Otherwise a multi-line token ends on a
+    /// later line, at column `0` when it ends with a line break.
+    pub fn positioned_tokens<'s>(
+        &'s self,
+        lang: &str,
+        source: &[u8],
+        unit: ColumnUnit,
+        split_lines: bool,
+    ) -> Result<Vec<PositionedToken<'s>>, Error> {
+        let mut positioned = Vec::new();
+        let mut position = (0, 0);
+        for token in self.tokens(lang, source)? {
+            let mut start = position;
+            for c in String::from_utf8_lossy(&source[token.range]).chars() {
+                if c != '\n' {
+                    position.1 += unit.width(c);
+                    continue;
+                }
+                if split_lines {
+                    if start != position {
+                        positioned.push(PositionedToken {
+                            start,
+                            end: position,
+                            name: token.name,
+                        });
+                    }
+                    start = (position.0 + 1, 0);
+                }
+                position = (position.0 + 1, 0);
+            }
+            if start != position {
+                positioned.push(PositionedToken {
+                    start,
+                    end: position,
+                    name: token.name,
+                });
+            }
+        }
+        Ok(positioned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{markdown, rust};
+
+    #[test]
+    fn tokens() {
+        let languages = rust();
+        let source = br#"#[doc = "a\nb"] fn f() {}"#;
+        let tokens = languages.tokens("rust", source).unwrap();
+
+        assert_eq!(tokens.first().unwrap().range.start, 0);
+        assert_eq!(tokens.last().unwrap().range.end, source.len());
+        for pair in tokens.windows(2) {
+            assert_eq!(pair[0].range.end, pair[1].range.start);
+            assert_ne!(pair[0].capture, pair[1].capture);
+        }
+        assert!(tokens.iter().all(|t| !t.range.is_empty()));
+        assert!(tokens
+            .iter()
+            .all(|t| t.name.is_some() == t.capture.is_some()));
+
+        // The escape is nested inside the string, which is nested inside the attribute.
+        let text = |t: &Token| std::str::from_utf8(&source[t.range.clone()]).unwrap();
+        let escape = tokens
+            .iter()
+            .position(|t| t.name == Some("string.escape"))
+            .unwrap();
+        assert_eq!(text(&tokens[escape]), "\\n");
+        assert_eq!(tokens[escape - 1].name, Some("string"));
+        assert_eq!(text(&tokens[escape - 1]), "\"a");
+        assert_eq!(tokens[escape + 1].name, Some("string"));
+        assert_eq!(text(&tokens[escape + 1]), "b\"");
+        assert!(tokens
+            .iter()
+            .any(|t| t.name == Some("keyword.function") && text(t) == "fn"));
+
+        assert!(languages.tokens("go", source).is_err());
+    }
+
+    #[test]
+    fn segments() {
+        use crate::Color;
+
+        let languages = rust();
+        let mut theme = Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            ("string", Style::new().strikethrough()),
+        ]);
+        theme.foreground = Some(Color::rgb(171, 178, 191));
+        let source = include_bytes!("../fixtures/sample.rs");
+        let segments = languages.styled_segments("rust", source, &theme).unwrap();
+
+        assert_eq!(segments.first().unwrap().0.start, 0);
+        assert_eq!(segments.last().unwrap().0.end, source.len());
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].0.end, pair[1].0.start);
+            assert_ne!(pair[0].1, pair[1].1);
+        }
+        assert!(segments.iter().all(|(_, style)| style.fg.is_some()));
+        // `""` opens the fixture.
+        assert_eq!(
+            segments[0],
+            (
+                0..2,
+                Style::new().fg(Color::rgb(171, 178, 191)).strikethrough()
+            )
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let languages = rust();
+        let source = "let s = \"😀a\";\n/* x\n y */".as_bytes();
+        let find = |tokens: &[PositionedToken<'_>], name| {
+            tokens
+                .iter()
+                .filter(|t| t.name == Some(name))
+                .map(|t| (t.start, t.end))
+                .collect::<Vec<_>>()
+        };
+
+        // 😀 is one scalar value but two UTF-16 code units.
+        let tokens = languages
+            .positioned_tokens("rust", source, ColumnUnit::Utf16, false)
+            .unwrap();
+        assert_eq!(find(&tokens, "string"), [((0, 8), (0, 13))]);
+        assert_eq!(find(&tokens, "comment"), [((1, 0), (2, 5))]);
+        assert_eq!(tokens.last().unwrap().end, (2, 5));
+
+        let tokens = languages
+            .positioned_tokens("rust", source, ColumnUnit::Char, true)
+            .unwrap();
+        assert_eq!(find(&tokens, "string"), [((0, 8), (0, 12))]);
+        assert_eq!(
+            find(&tokens, "comment"),
+            [((1, 0), (1, 4)), ((2, 0), (2, 5))]
+        );
+        assert!(tokens.iter().all(|t| t.start.0 == t.end.0));
+    }
+
+    #[test]
+    fn injection_depth() {
+        let mut languages = markdown();
+        let doc = b"`````markdown\n````markdown\n```rust\nlet a;\n```\n````\n`````\n";
+        let keywords = |languages: &Languages| {
+            languages
+                .tokens("markdown", doc)
+                .unwrap()
+                .iter()
+                .filter(|t| t.name == Some("keyword"))
+                .count()
+        };
+
+        assert_eq!(keywords(&languages), 1);
+        assert_eq!(keywords(languages.set_max_injection_depth(2)), 1);
+        assert_eq!(keywords(languages.set_max_injection_depth(1)), 0);
+        assert_eq!(keywords(languages.set_max_injection_depth(0)), 0);
+    }
+}
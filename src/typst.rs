@@ -0,0 +1,279 @@
+use std::fmt::Write;
+
+use crate::{Color, Error, FontStyle, Languages, Style, Theme};
+
+/// The shape of the Typst output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TypstMode {
+    /// A stream of `#text(..)[..]` calls, one per styled token.
+    #[default]
+    Text,
+    /// `#raw(..)` tokens tagged with `hl-{name}` languages, styled by show rules.
+    Raw,
+}
+
+/// Typst options
+#[derive(Debug, Clone)]
+pub struct TypstOptions {
+    pub mode: TypstMode,
+    /// Tab stops in `Text` mode.
+    pub tab_width: usize,
+}
+
+impl Default for TypstOptions {
+    fn default() -> Self {
+        Self {
+            mode: TypstMode::Text,
+            tab_width: 4,
+        }
+    }
+}
+
+impl TypstOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(mut self, mode: TypstMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width;
+        self
+    }
+}
+
+fn rgb(color: Color) -> String {
+    format!("rgb(\"{color}\")")
+}
+
+/// The `text` arguments of a style and the decoration functions wrapping the text.
+fn text_args(style: &Style) -> (String, Vec<&'static str>) {
+    let mut args = Vec::new();
+    if let Some(fg) = style.fg {
+        args.push(format!("fill: {}", rgb(fg)));
+    }
+    let mut decorations = Vec::new();
+    for font_style in style.font_styles() {
+        match font_style {
+            FontStyle::Bold => args.push("weight: \"bold\"".to_string()),
+            FontStyle::Italic => args.push("style: \"italic\"".to_string()),
+            FontStyle::Underline => decorations.push("underline"),
+            FontStyle::Strikethrough => decorations.push("strike"),
+        }
+    }
+    (args.join(", "), decorations)
+}
+
+/// Escapes Typst markup characters.
+fn escape(s: &mut String, c: char) {
+    match c {
+        '*' | '_' | '`' | '#' | '[' | ']' | '<' | '>' | '@' | '\\' | '$' | '~' | '/' | '-'
+        | '+' | '=' | '"' | '\'' => {
+            s.push('\\');
+            s.push(c);
+        }
+        c => s.push(c),
+    }
+}
+
+/// Escapes a Typst string literal.
+fn escape_str(s: &mut String, c: char) {
+    match c {
+        '\\' | '"' => {
+            s.push('\\');
+            s.push(c);
+        }
+        c => s.push(c),
+    }
+}
+
+/// A line of markup and its width in columns.
+#[derive(Default)]
+struct Line {
+    typst: String,
+    columns: usize,
+}
+
+impl<'a> Languages<'a> {
+    /// Renders to a Typst `#block`, see [`TypstOptions`].
+    ///
+    /// Lines are separated by `\` line breaks. In `Text` mode spaces become `~` so they
+    /// are not collapsed; in `Raw` mode the `raw` elements keep them as is.
+    pub fn render_typst(
+        &self,
+        lang: &str,
+        source: &[u8],
+        theme: &Theme,
+        options: &TypstOptions,
+    ) -> Result<String, Error> {
+        let mut names = Vec::new();
+        let mut lines = vec![Line::default()];
+        self.for_each_token(lang, source, |range, name| {
+            let style = name.map(|name| theme.resolve(name)).unwrap_or_default();
+            let styled = style != Style::default();
+            if let Some(name) = name.filter(|_| styled) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            for (i, text) in String::from_utf8_lossy(&source[range])
+                .split('\n')
+                .enumerate()
+            {
+                if i > 0 {
+                    lines.push(Line::default());
+                }
+                let line = lines.last_mut().unwrap();
+                let mut span = String::new();
+                for c in text.chars() {
+                    match (options.mode, c) {
+                        (TypstMode::Text, '\t') => {
+                            let tab = options.tab_width.max(1);
+                            let n = tab - line.columns % tab;
+                            span.extend(std::iter::repeat_n('~', n));
+                            line.columns += n;
+                        }
+                        (_, c) if c != '\t' && c.is_control() => {}
+                        (TypstMode::Text, ' ') => {
+                            span.push('~');
+                            line.columns += 1;
+                        }
+                        (TypstMode::Text, c) => {
+                            escape(&mut span, c);
+                            line.columns += 1;
+                        }
+                        (TypstMode::Raw, c) => escape_str(&mut span, c),
+                    }
+                }
+                if span.is_empty() {
+                    continue;
+                }
+                match options.mode {
+                    TypstMode::Text if !styled => line.typst.push_str(&span),
+                    TypstMode::Text => {
+                        let (args, decorations) = text_args(&style);
+                        for decoration in &decorations {
+                            let _ = write!(line.typst, "#{decoration}[");
+                        }
+                        if args.is_empty() {
+                            line.typst.push_str(&span);
+                        } else {
+                            let _ = write!(line.typst, "#text({args})[{span}]");
+                        }
+                        line.typst.extend(decorations.iter().map(|_| ']'));
+                    }
+                    TypstMode::Raw => {
+                        let _ = write!(line.typst, "#raw(\"{span}\"");
+                        if let Some(name) = name.filter(|_| styled) {
+                            let _ = write!(line.typst, ", lang: \"hl-{name}\"");
+                        }
+                        line.typst.push(')');
+                    }
+                }
+            }
+        })?;
+        if lines.len() > 1 && lines.last().is_some_and(|l| l.typst.is_empty()) {
+            lines.pop();
+        }
+
+        let mut s = String::from("#block");
+        if let Some(bg) = theme.background {
+            let _ = write!(s, "(fill: {}, inset: 8pt)", rgb(bg));
+        }
+        s.push_str("[\n");
+        if let Some(fg) = theme.foreground {
+            let _ = writeln!(s, "#set text(fill: {})", rgb(fg));
+        }
+        if options.mode == TypstMode::Raw {
+            for name in names {
+                let style = theme.resolve(name);
+                let (args, decorations) = text_args(&style);
+                let mut body = if args.is_empty() {
+                    "it".to_string()
+                } else {
+                    format!("text({args}, it)")
+                };
+                for decoration in decorations.iter().rev() {
+                    body = format!("{decoration}({body})");
+                }
+                let _ = writeln!(s, "#show raw.where(lang: \"hl-{name}\"): it => {body}");
+            }
+        }
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                s.push_str("\\\n");
+            }
+            s.push_str(&line.typst);
+        }
+        s.push_str("\n]\n");
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::rust;
+
+    fn theme() -> Theme {
+        Theme::new(&[
+            ("keyword", Style::new().fg(Color::rgb(198, 120, 221)).bold()),
+            ("string", Style::new().fg(Color::rgb(152, 195, 121))),
+        ])
+    }
+
+    #[test]
+    fn escaping() {
+        let mut s = String::new();
+        "#[a]* _b_ <c> @d `e` f"
+            .chars()
+            .for_each(|c| escape(&mut s, c));
+        assert_eq!(s, "\\#\\[a\\]\\* \\_b\\_ \\<c\\> \\@d \\`e\\` f");
+    }
+
+    #[test]
+    fn text() {
+        let languages = rust();
+        let typst = languages
+            .render_typst(
+                "rust",
+                b"let s = \"#[x]\";\n\n",
+                &theme(),
+                &TypstOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(
+            typst,
+            "#block[
+#text(fill: rgb(\"#c678dd\"), weight: \"bold\")[let]~s~\\=~#text(fill: rgb(\"#98c379\"))[\\\"\\#\\[x\\]\\\"];\\
+
+]
+"
+        );
+    }
+
+    #[test]
+    fn raw() {
+        let languages = rust();
+        let typst = languages
+            .render_typst(
+                "rust",
+                b"let s = \"#[x]\";",
+                &theme(),
+                &TypstOptions::new().mode(TypstMode::Raw),
+            )
+            .unwrap();
+        assert_eq!(
+            typst,
+            "#block[
+#show raw.where(lang: \"hl-keyword\"): it => text(fill: rgb(\"#c678dd\"), weight: \"bold\", it)
+#show raw.where(lang: \"hl-string\"): it => text(fill: rgb(\"#98c379\"), it)
+#raw(\"let\", lang: \"hl-keyword\")#raw(\" s = \")#raw(\"\\\"#[x]\\\"\", lang: \"hl-string\")#raw(\";\")
+]
+"
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{Color, Style, Theme, ThemeError};
+
+/// The TextMate scope of each capture name.
+///
+/// A theme rule styles a capture when its selector is the scope or one of its dotted
+/// prefixes, the longest one winning, so a `keyword.control` rule styles `conditional`
+/// and `repeat` unless `keyword.control.conditional` has its own rule. Captures without
+/// a matching rule fall back to their prefixes, as in [`Theme::resolve`].
+pub const VSCODE_SCOPES: &[(&str, &str)] = &[
+    ("attribute", "entity.other.attribute-name"),
+    ("boolean", "constant.language.boolean"),
+    ("character", "constant.character"),
+    ("comment", "comment"),
+    ("conditional", "keyword.control.conditional"),
+    ("constant", "constant"),
+    ("constant.builtin", "constant.language"),
+    ("constant.macro", "constant.other.macro"),
+    ("constructor", "entity.name.class"),
+    ("diff.delta", "markup.changed"),
+    ("diff.minus", "markup.deleted"),
+    ("diff.plus", "markup.inserted"),
+    ("error", "invalid"),
+    ("exception", "keyword.control.exception"),
+    ("field", "variable.other.member"),
+    ("float", "constant.numeric.float"),
+    ("function", "entity.name.function"),
+    ("function.builtin", "support.function"),
+    ("function.macro", "entity.name.function.macro"),
+    ("include", "keyword.control.import"),
+    ("keyword", "keyword"),
+    ("keyword.function", "storage.type.function"),
+    ("keyword.operator", "keyword.operator.word"),
+    ("keyword.return", "keyword.control.return"),
+    ("label", "entity.name.label"),
+    ("namespace", "entity.name.namespace"),
+    ("number", "constant.numeric"),
+    ("operator", "keyword.operator"),
+    ("parameter", "variable.parameter"),
+    ("preproc", "meta.preprocessor"),
+    ("property", "variable.other.property"),
+    ("punctuation", "punctuation"),
+    ("punctuation.special", "punctuation.section.embedded"),
+    ("repeat", "keyword.control.loop"),
+    ("storageclass", "storage.modifier"),
+    ("string", "string"),
+    ("string.escape", "constant.character.escape"),
+    ("string.regex", "string.regexp"),
+    ("tag", "entity.name.tag"),
+    ("tag.attribute", "entity.other.attribute-name"),
+    ("tag.delimiter", "punctuation.definition.tag"),
+    ("text.diff.add", "markup.inserted"),
+    ("text.diff.delete", "markup.deleted"),
+    ("text.emphasis", "markup.italic"),
+    ("text.literal", "markup.inline.raw"),
+    ("text.strike", "markup.strikethrough"),
+    ("text.strong", "markup.bold"),
+    ("text.title", "markup.heading"),
+    ("text.underline", "markup.underline"),
+    ("text.uri", "markup.underline.link"),
+    ("type", "entity.name.type"),
+    ("type.builtin", "support.type"),
+    ("type.qualifier", "storage.modifier"),
+    ("variable", "variable"),
+    ("variable.builtin", "variable.language"),
+];
+
+#[derive(Deserialize)]
+struct VscodeTheme {
+    #[serde(default)]
+    colors: HashMap<String, String>,
+    #[serde(default, rename = "tokenColors")]
+    token_colors: Vec<TokenColor>,
+}
+
+#[derive(Deserialize)]
+struct TokenColor {
+    #[serde(default)]
+    scope: Scopes,
+    #[serde(default)]
+    settings: Settings,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(untagged)]
+enum Scopes {
+    #[default]
+    None,
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Default, Deserialize)]
+struct Settings {
+    foreground: Option<String>,
+    background: Option<String>,
+    #[serde(rename = "fontStyle")]
+    font_style: Option<String>,
+}
+
+/// Parses `#rgb`, `#rrggbb` and their forms with alpha, which is dropped.
+fn color(s: &str) -> Result<Color, ThemeError> {
+    let s = s.trim();
+    let opaque = match s.len() {
+        5 => &s[..4],
+        9 => &s[..7],
+        _ => s,
+    };
+    Ok(opaque.parse()?)
+}
+
+/// Strips the comments and trailing commas that VS Code allows in theme files.
+fn strip_jsonc(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut chars = json.chars().peekable();
+    let mut string = false;
+    while let Some(c) = chars.next() {
+        if string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|c| *c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            (']' | '}', _) => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.remove(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+impl Theme {
+    /// Loads a VS Code or TextMate JSON color theme.
+    ///
+    /// The `tokenColors` rules are mapped onto capture names with [`VSCODE_SCOPES`]; rules for
+    /// other scopes and descendant selectors like `meta.tag string` are ignored. The
+    /// `editor.background`, `editor.foreground`, `editor.lineHighlightBackground`,
+    /// `editorLineNumber.foreground` and `editor.selectionBackground` colors become the
+    /// defaults, falling back to the settings of a rule without scope.
+    /// Semantic token colors are not supported.
+    pub fn from_vscode_json(json: &str) -> Result<Theme, ThemeError> {
+        let theme: VscodeTheme = serde_json::from_str(&strip_jsonc(json))
+            .map_err(|e| ThemeError::Parse(e.to_string()))?;
+
+        // The selectors of each rule, in order, later rules winning ties.
+        let mut rules = Vec::new();
+        let mut global = None;
+        for rule in &theme.token_colors {
+            let selectors = match &rule.scope {
+                Scopes::None => {
+                    global = Some(&rule.settings);
+                    continue;
+                }
+                Scopes::One(scopes) => scopes.split(',').map(str::trim).collect::<Vec<_>>(),
+                Scopes::Many(scopes) => scopes.iter().map(|s| s.trim()).collect(),
+            };
+            let style = style(&rule.settings)?;
+            for selector in selectors {
+                if !selector.is_empty() && !selector.contains(char::is_whitespace) {
+                    rules.push((selector, style));
+                }
+            }
+        }
+
+        let mut styles = Vec::new();
+        for (name, scope) in VSCODE_SCOPES {
+            let matched = rules
+                .iter()
+                .filter(|(selector, _)| {
+                    scope
+                        .strip_prefix(selector)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+                })
+                .max_by_key(|(selector, _)| selector.len());
+            if let Some((_, style)) = matched {
+                styles.push((*name, *style));
+            }
+        }
+
+        let mut result = Theme::new(&styles);
+        let global = global.map(style).transpose()?.unwrap_or_default();
+        let editor = |key: &str| theme.colors.get(key).map(|c| color(c)).transpose();
+        result.background = editor("editor.background")?.or(global.bg);
+        result.foreground = editor("editor.foreground")?.or(global.fg);
+        result.line_highlight = editor("editor.lineHighlightBackground")?;
+        result.gutter_fg = editor("editorLineNumber.foreground")?;
+        result.selection_bg = editor("editor.selectionBackground")?;
+        Ok(result)
+    }
+}
+
+/// The style of a rule's settings.
+fn style(settings: &Settings) -> Result<Style, ThemeError> {
+    let mut style = Style::new();
+    style.fg = settings.foreground.as_deref().map(color).transpose()?;
+    style.bg = settings.background.as_deref().map(color).transpose()?;
+    for word in settings
+        .font_style
+        .iter()
+        .flat_map(|s| s.split_whitespace())
+    {
+        match word {
+            "bold" => style.bold = true,
+            "italic" => style.italic = true,
+            "underline" => style.underline = true,
+            "strikethrough" => style.strikethrough = true,
+            _ => {}
+        }
+    }
+    Ok(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jsonc() {
+        assert_eq!(
+            strip_jsonc("{\n  // c\n  \"a\": \"//x\", /* b */\n  \"b\": [1, 2,],\n}"),
+            "{\n  \n  \"a\": \"//x\", \n  \"b\": [1, 2]\n}"
+        );
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(color("#f92672"), Ok(Color::rgb(249, 38, 114)));
+        assert_eq!(color("#f9267280"), Ok(Color::rgb(249, 38, 114)));
+        assert_eq!(color("#fff8"), Ok(Color::rgb(255, 255, 255)));
+        assert!(color("red").is_err());
+    }
+
+    #[test]
+    fn monokai() {
+        let theme = Theme::from_vscode_json(include_str!("../fixtures/monokai.json")).unwrap();
+        assert_eq!(theme.background, Some(Color::rgb(0x27, 0x28, 0x22)));
+        assert_eq!(theme.foreground, Some(Color::rgb(0xf8, 0xf8, 0xf2)));
+        assert_eq!(theme.line_highlight, Some(Color::rgb(0x3e, 0x3d, 0x32)));
+        assert_eq!(theme.selection_bg, Some(Color::rgb(0x87, 0x8b, 0x91)));
+
+        let fg = |name| theme.resolve(name).fg;
+        assert_eq!(fg("keyword"), Some(Color::rgb(0xf9, 0x26, 0x72)));
+        assert_eq!(fg("conditional"), Some(Color::rgb(0xf9, 0x26, 0x72)));
+        assert_eq!(fg("string"), Some(Color::rgb(0xe6, 0xdb, 0x74)));
+        assert_eq!(fg("comment"), Some(Color::rgb(0x88, 0x84, 0x6f)));
+        assert_eq!(fg("function"), Some(Color::rgb(0xa6, 0xe2, 0x2e)));
+        assert_eq!(fg("number"), Some(Color::rgb(0xae, 0x81, 0xff)));
+        assert!(theme.resolve("parameter").italic);
+        // `meta.tag string` is a descendant selector.
+        assert_eq!(fg("tag"), Some(Color::rgb(0xf9, 0x26, 0x72)));
+        assert_eq!(theme.resolve("punctuation"), Style::default());
+    }
+}
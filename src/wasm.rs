@@ -0,0 +1,35 @@
+use wasm_bindgen::prelude::*;
+
+use crate::Languages;
+
+/// Languages exported to JavaScript.
+///
+/// Grammars are linked statically, so register them in Rust and hand the result over:
+///
+/// ```ignore
+/// #[wasm_bindgen]
+/// pub fn languages() -> WasmLanguages {
+///     let mut languages = Languages::new();
+///     languages.insert("rust", config);
+///     languages.into()
+/// }
+/// ```
+#[wasm_bindgen]
+pub struct WasmLanguages {
+    inner: Languages<'static>,
+}
+
+impl From<Languages<'static>> for WasmLanguages {
+    fn from(inner: Languages<'static>) -> Self {
+        Self { inner }
+    }
+}
+
+#[wasm_bindgen]
+impl WasmLanguages {
+    /// Renders to HTML, `undefined` when the language is unknown.
+    #[wasm_bindgen(js_name = renderStr)]
+    pub fn render_str(&self, lang: &str, source: &str) -> Option<String> {
+        self.inner.render_str(lang, source)
+    }
+}
@@ -0,0 +1,47 @@
+/// Display width of a character in columns.
+///
+/// With the `unicode-width` feature this follows UAX #11, otherwise East Asian wide
+/// and fullwidth ranges count as 2 columns and control characters as 0.
+#[cfg(feature = "unicode-width")]
+pub(crate) fn char_width(c: char) -> usize {
+    unicode_width::UnicodeWidthChar::width(c).unwrap_or(0)
+}
+
+#[cfg(not(feature = "unicode-width"))]
+pub(crate) fn char_width(c: char) -> usize {
+    if c.is_control() {
+        return 0;
+    }
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('é'), 1);
+        assert_eq!(char_width('日'), 2);
+        assert_eq!(char_width('한'), 2);
+        assert_eq!(char_width('Ａ'), 2);
+        assert_eq!(char_width('\u{7}'), 0);
+    }
+}
@@ -0,0 +1,89 @@
+//! Checks the heap a render holds on top of its output.
+//!
+//! The rendered lines are written from the writer's buffers straight to the output, so
+//! once the registry's buffers are warm, a render into a large enough `String` needs
+//! little more than the syntax tree. A large comment keeps the tree small and the output
+//! large.
+//!
+//! The allocator counts every allocation of this test binary, so it holds this one test.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use highlighting::{Languages, RenderOptions};
+use tree_sitter_highlight::HighlightConfiguration;
+
+struct Peak;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(size: usize) {
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Peak {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        grow(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Counted as a new allocation before the old one is freed, as a move would be.
+        grow(new_size);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Peak = Peak;
+
+#[test]
+fn render_holds_little_besides_its_output() {
+    let mut languages = Languages::new();
+    languages.insert(
+        "rust",
+        HighlightConfiguration::new(
+            tree_sitter_rust::language(),
+            include_str!("../queries/rust/highlights.scm"),
+            "",
+            "",
+        )
+        .unwrap(),
+    );
+    let mut source = String::from("/*\n");
+    for i in 0..50_000 {
+        source.push_str(&format!("  line {i} of a long comment & <more>\n"));
+    }
+    source.push_str("*/\n");
+    let options = RenderOptions::new().start_line(1);
+
+    // Warms up the pooled buffers, and sizes the output.
+    let html = languages
+        .render_with("rust", source.as_bytes(), &options)
+        .unwrap();
+    let mut out = String::with_capacity(html.len());
+
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    languages
+        .render_to("rust", source.as_bytes(), &options, &mut out)
+        .unwrap();
+    let extra = PEAK.load(Ordering::Relaxed) - before;
+    assert_eq!(out, html);
+
+    assert!(
+        extra < html.len() / 4,
+        "the render held a copy of its output: {extra} bytes besides {} bytes of output",
+        html.len()
+    );
+}